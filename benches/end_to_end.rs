@@ -1,4 +1,3 @@
-#![feature(async_await)]
 #![feature(test)]
 #![deny(warnings)]
 
@@ -115,8 +114,8 @@ fn http2_parallel_x10_req_10mb(b: &mut test::Bencher) {
         .parallel(10)
         .method(Method::POST)
         .request_body(body)
-        .http2_stream_window(std::u32::MAX >> 1)
-        .http2_conn_window(std::u32::MAX >> 1)
+        .http2_stream_window(u32::MAX >> 1)
+        .http2_conn_window(u32::MAX >> 1)
         .bench(b)
 }
 
@@ -203,7 +202,7 @@ impl Opts {
             let body = self
                 .request_body
                 .map(Body::from)
-                .unwrap_or_else(|| Body::empty());
+                .unwrap_or_else(Body::empty);
             let mut req = Request::new(body);
             *req.method_mut() = self.request_method.clone();
             *req.uri_mut() = url.clone();
@@ -261,5 +260,5 @@ fn spawn_server(rt: &mut Runtime, opts: &Opts) -> SocketAddr {
             panic!("server error: {}", err);
         }
     });
-    return addr
+    addr
 }
@@ -1,4 +1,3 @@
-#![feature(async_await)]
 #![feature(test)]
 #![deny(warnings)]
 
@@ -95,7 +94,7 @@ fn throughput_fixedsize_large_payload(b: &mut test::Bencher) {
 #[bench]
 fn throughput_fixedsize_many_chunks(b: &mut test::Bencher) {
     bench_server!(b, ("content-length", "1000000"), || {
-        static S: &'static [&'static [u8]] = &[&[b'x'; 1_000] as &[u8]; 1_000] as _;
+        static S: &[&[u8]] = &[&[b'x'; 1_000] as &[u8]; 1_000] as _;
         Body::wrap_stream(stream::iter(S.iter()).map(|&s| Ok::<_, String>(s)))
     })
 }
@@ -113,7 +112,7 @@ fn throughput_chunked_large_payload(b: &mut test::Bencher) {
 #[bench]
 fn throughput_chunked_many_chunks(b: &mut test::Bencher) {
     bench_server!(b, ("transfer-encoding", "chunked"), || {
-        static S: &'static [&'static [u8]] = &[&[b'x'; 1_000] as &[u8]; 1_000] as _;
+        static S: &[&[u8]] = &[&[b'x'; 1_000] as &[u8]; 1_000] as _;
         Body::wrap_stream(stream::iter(S.iter()).map(|&s| Ok::<_, String>(s)))
     })
 }
@@ -128,7 +127,7 @@ fn raw_tcp_throughput_small_payload(b: &mut test::Bencher) {
 
         let mut buf = [0u8; 8192];
         while rx.try_recv().is_err() {
-            sock.read(&mut buf).unwrap();
+            let _ = sock.read(&mut buf).unwrap();
             sock.write_all(b"\
                 HTTP/1.1 200 OK\r\n\
                 Content-Length: 13\r\n\
@@ -1,4 +1,3 @@
-#![feature(async_await)]
 #![deny(warnings)]
 extern crate hyper;
 #[macro_use]
@@ -39,6 +38,7 @@ async fn fetch_json(url: hyper::Uri) -> Result<Vec<User>> {
 }
 
 #[derive(Deserialize, Debug)]
+#[allow(dead_code)]
 struct User {
     id: i32,
     name: String,
@@ -1,4 +1,3 @@
-#![feature(async_await)]
 #![deny(warnings)]
 extern crate hyper;
 extern crate pretty_env_logger;
@@ -7,8 +6,8 @@ use hyper::{Body, Request, Response, Server};
 use hyper::service::{service_fn, make_service_fn};
 use futures_util::future::join;
 
-static INDEX1: &'static [u8] = b"The 1st service!";
-static INDEX2: &'static [u8] = b"The 2nd service!";
+static INDEX1: &[u8] = b"The 1st service!";
+static INDEX2: &[u8] = b"The 2nd service!";
 
 async fn index1(_: Request<Body>) -> Result<Response<Body>, hyper::Error> {
     Ok(Response::new(Body::from(INDEX1)))
@@ -1,4 +1,3 @@
-#![feature(async_await)]
 #![deny(warnings)]
 
 use hyper::{Client, Error, Server};
@@ -14,7 +13,7 @@ async fn main() {
 
     let client_main = Client::new();
 
-    let out_addr_clone = out_addr.clone();
+    let out_addr_clone = out_addr;
 
     // The closure inside `make_service_fn` is run for each connection,
     // creating a 'service' to handle requests for that specific connection.
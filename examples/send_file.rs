@@ -1,8 +1,7 @@
-#![feature(async_await)]
 #![deny(warnings)]
 
 use tokio::io::AsyncReadExt;
-use tokio_fs::file::File;
+use tokio_fs::File;
 
 use hyper::{Body, Method, Result, Request, Response, Server, StatusCode};
 use hyper::service::{make_service_fn, service_fn};
@@ -71,12 +70,12 @@ async fn simple_file_send(f: &str) -> Result<Response<Body>> {
 
     if let Ok(mut file) = File::open(filename).await {
         let mut buf = Vec::new();
-        if let Ok(_) = file.read_to_end(&mut buf).await {
+        if file.read_to_end(&mut buf).await.is_ok() {
             return Ok(Response::new(buf.into()));
         }
 
         return Ok(internal_server_error());
     }
 
-    return Ok(not_found());
+    Ok(not_found())
 }
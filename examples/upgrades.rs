@@ -1,4 +1,3 @@
-#![feature(async_await)]
 #![deny(warnings)]
 
 // Note: `hyper::upgrade` docs link to this upgrade.
@@ -143,7 +142,7 @@ async fn main() {
     });
 
     // Client requests a HTTP connection upgrade.
-    let request = client_upgrade_request(addr.clone());
+    let request = client_upgrade_request(addr);
     if let Err(e) = request.await {
         eprintln!("client error: {}", e);
     }
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::error::Error as StdError;
 use std::fmt;
+use std::sync::Arc;
 
 use bytes::Bytes;
 use futures_core::{Stream, TryStream};
@@ -11,6 +12,7 @@ use h2;
 use http::HeaderMap;
 
 use crate::common::{Future, Never, Pin, Poll, task};
+use crate::proto::h2::{ping, StreamRef, StreamWaker, Waiter};
 use super::internal::{FullDataArg, FullDataRet};
 use super::{Chunk, Payload};
 use crate::upgrade::OnUpgrade;
@@ -38,6 +40,7 @@ enum Kind {
         rx: mpsc::Receiver<Result<Chunk, crate::Error>>,
     },
     H2 {
+        ping: ping::Recorder,
         content_length: Option<u64>,
         recv: h2::RecvStream,
     },
@@ -59,6 +62,12 @@ struct Extra {
     /// connection yet.
     delayed_eof: Option<DelayEof>,
     on_upgrade: OnUpgrade,
+    /// Keeps the HTTP/2 stream of a client response counted as open on its
+    /// connection, so the pool knows when the connection is saturated.
+    h2_stream_ref: Option<StreamRef>,
+    /// Wakes the task forwarding pushes too, when polling an HTTP/2 client
+    /// response that may still receive pushes.
+    h2_stream_waker: Option<Arc<StreamWaker>>,
 }
 
 type DelayEofUntil = oneshot::Receiver<Never>;
@@ -175,8 +184,13 @@ impl Body {
         }
     }
 
-    pub(crate) fn h2(recv: h2::RecvStream, content_length: Option<u64>) -> Self {
+    pub(crate) fn h2(
+        recv: h2::RecvStream,
+        content_length: Option<u64>,
+        ping: ping::Recorder,
+    ) -> Self {
         Body::new(Kind::H2 {
+            ping,
             content_length,
             recv,
         })
@@ -189,6 +203,14 @@ impl Body {
         extra.on_upgrade = upgrade;
     }
 
+    pub(crate) fn set_h2_stream_ref(&mut self, stream_ref: StreamRef) {
+        self.extra_mut().h2_stream_ref = Some(stream_ref);
+    }
+
+    pub(crate) fn set_h2_stream_waker(&mut self, stream_waker: Arc<StreamWaker>) {
+        self.extra_mut().h2_stream_waker = Some(stream_waker);
+    }
+
     pub(crate) fn delayed_eof(&mut self, fut: DelayEofUntil) {
         self.extra_mut().delayed_eof = Some(DelayEof::NotEof(fut));
     }
@@ -206,6 +228,8 @@ impl Body {
             .get_or_insert_with(|| Box::new(Extra {
                 delayed_eof: None,
                 on_upgrade: OnUpgrade::none(),
+                h2_stream_ref: None,
+                h2_stream_waker: None,
             }))
     }
 
@@ -271,23 +295,22 @@ impl Body {
                 }
             }
             Kind::H2 {
-                /*recv: ref mut h2,*/ ..
-            } => {
-                unimplemented!("h2.poll_inner");
-                /*
-                h2
-                    .poll()
-                    .map(|r#async| {
-                        r#async.map(|opt| {
-                            opt.map(|bytes| {
-                                let _ = h2.release_capacity().release_capacity(bytes.len());
-                                Chunk::from(bytes)
-                            })
-                        })
-                    })
-                    .map_err(crate::Error::new_body)
-                    */
-            }
+                ref ping,
+                recv: ref mut h2,
+                content_length: ref mut len,
+            } => match ready!(poll_h2(&self.extra, cx, |cx| h2.poll_data(cx))) {
+                Some(Ok(bytes)) => {
+                    let _ = h2.release_capacity().release_capacity(bytes.len());
+                    if let Some(ref mut len) = *len {
+                        debug_assert!(*len >= bytes.len() as u64);
+                        *len = *len - bytes.len() as u64;
+                    }
+                    ping.record_data(bytes.len());
+                    Poll::Ready(Some(Ok(Chunk::from(bytes))))
+                }
+                Some(Err(e)) => Poll::Ready(Some(Err(crate::Error::new_body(e)))),
+                None => Poll::Ready(None),
+            },
             Kind::Wrapped(ref mut s) => {
                 match ready!(s.as_mut().poll_next(cx)) {
                     Some(res) => Poll::Ready(Some(res.map_err(crate::Error::new_body))),
@@ -298,6 +321,18 @@ impl Body {
     }
 }
 
+/// Polls an HTTP/2 stream, waking the task forwarding its pushes too.
+fn poll_h2<T>(
+    extra: &Option<Box<Extra>>,
+    cx: &mut task::Context<'_>,
+    f: impl FnOnce(&mut task::Context<'_>) -> T,
+) -> T {
+    let stream_waker = extra
+        .as_ref()
+        .and_then(|extra| extra.h2_stream_waker.as_ref());
+    StreamWaker::poll(stream_waker, Waiter::Response, cx, f)
+}
+
 impl Default for Body {
     /// Returns [`Body::empty()`](Body::empty).
     #[inline]
@@ -314,11 +349,15 @@ impl Payload for Body {
         self.poll_eof(cx)
     }
 
-    fn poll_trailers(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
-        match self.kind {
-            Kind::H2 { /*recv: ref mut h2,*/ .. } => {
-                unimplemented!("h2.poll_trailers");
-                //h2.poll_trailers().map_err(crate::Error::new_h2)
+    fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Result<HeaderMap, Self::Error>>> {
+        let me = &mut *self;
+        match me.kind {
+            Kind::H2 { recv: ref mut h2, ref ping, .. } => match ready!(poll_h2(&me.extra, cx, |cx| h2.poll_trailers(cx))) {
+                Ok(t) => {
+                    ping.record_non_data();
+                    Poll::Ready(Ok(t).transpose())
+                }
+                Err(e) => Poll::Ready(Some(Err(crate::Error::new_h2(e)))),
             },
             _ => Poll::Ready(None),
         }
@@ -11,6 +11,8 @@ use std::fmt;
 use std::marker::PhantomData;
 use std::mem;
 use std::sync::Arc;
+#[cfg(feature = "runtime")]
+use std::time::Duration;
 
 use bytes::Bytes;
 use futures_util::future::{self, Either, FutureExt as _};
@@ -24,6 +26,11 @@ use crate::proto;
 use super::dispatch;
 use crate::{Body, Request, Response};
 
+pub use crate::proto::h2::{PeerSettings, PushedResponse, PushedResponseFuture, PushedResponses, Rtt};
+
+/// The `SETTINGS_MAX_CONCURRENT_STREAMS` advertised when push is enabled.
+const DEFAULT_MAX_PUSHED_STREAMS: u32 = 100;
+
 type Http1Dispatcher<T, B, R> = proto::dispatch::Dispatcher<
     proto::dispatch::Client<B>,
     B,
@@ -49,6 +56,8 @@ where
 /// The sender side of an established connection.
 pub struct SendRequest<B> {
     dispatch: dispatch::Sender<Request<B>, Response<Body>>,
+    pushes: Option<PushedResponses>,
+    h2_shared: Option<proto::h2::ClientShared>,
 }
 
 
@@ -78,6 +87,8 @@ pub struct Builder {
     h1_max_buf_size: Option<usize>,
     http2: bool,
     h2_builder: h2::client::Builder,
+    h2_enable_push: bool,
+    h2_ping: proto::h2::ping::Config,
 }
 
 /// A future setting up HTTP over an IO object.
@@ -131,6 +142,7 @@ pub struct Parts<T> {
 #[must_use = "futures do nothing unless polled"]
 pub(super) struct Http2SendRequest<B> {
     dispatch: dispatch::UnboundedSender<Request<B>, Response<Body>>,
+    shared: proto::h2::ClientShared,
 }
 
 // ===== impl SendRequest
@@ -144,6 +156,26 @@ impl<B> SendRequest<B>
         self.dispatch.poll_ready(cx)
     }
 
+    /// Takes the stream of responses pushed by the server.
+    ///
+    /// This is only available on HTTP/2 connections where server push was
+    /// enabled with `Builder::http2_enable_push`, and can only be taken once.
+    /// Until it is taken, and once it is dropped, pushed streams are reset
+    /// with `CANCEL`.
+    pub fn pushed_responses(&mut self) -> Option<PushedResponses> {
+        self.pushes.take().map(PushedResponses::take)
+    }
+
+    /// Returns the SETTINGS the server sent on this connection.
+    ///
+    /// Returns `None` if the connection isn't HTTP/2, or the first SETTINGS
+    /// frame of the server hasn't been received and acknowledged yet.
+    pub fn http2_peer_settings(&self) -> Option<PeerSettings> {
+        self.h2_shared
+            .as_ref()
+            .and_then(|shared| shared.peer_settings())
+    }
+
     pub(super) fn when_ready(self) -> impl Future<Output=crate::Result<Self>> {
         let mut me = Some(self);
         future::poll_fn(move |cx| {
@@ -163,6 +195,7 @@ impl<B> SendRequest<B>
     pub(super) fn into_http2(self) -> Http2SendRequest<B> {
         Http2SendRequest {
             dispatch: self.dispatch.unbound(),
+            shared: self.h2_shared.unwrap_or_default(),
         }
     }
 }
@@ -284,6 +317,12 @@ impl<B> Http2SendRequest<B> {
     pub(super) fn is_closed(&self) -> bool {
         self.dispatch.is_closed()
     }
+
+    /// Whether the connection has as many streams open as the server
+    /// allows, so new requests would be queued.
+    pub(super) fn is_saturated(&self) -> bool {
+        self.shared.is_saturated()
+    }
 }
 
 impl<B> Http2SendRequest<B>
@@ -325,6 +364,7 @@ impl<B> Clone for Http2SendRequest<B> {
     fn clone(&self) -> Self {
         Http2SendRequest {
             dispatch: self.dispatch.clone(),
+            shared: self.shared.clone(),
         }
     }
 }
@@ -374,10 +414,7 @@ where
                 h1.poll_without_shutdown(cx)
             },
             &mut Either::Right(ref mut h2) => {
-                unimplemented!("h2 poll_without_shutdown");
-                /*
-                h2.poll().map(|x| x.map(|_| ()))
-                */
+                Pin::new(h2).poll(cx).map_ok(|_| ())
             }
         }
     }
@@ -450,6 +487,8 @@ impl Builder {
             h1_max_buf_size: None,
             http2: false,
             h2_builder,
+            h2_enable_push: false,
+            h2_ping: proto::h2::ping::Config::new(),
         }
     }
 
@@ -516,11 +555,157 @@ impl Builder {
     /// Default is 65,535
     pub fn http2_initial_connection_window_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
         if let Some(sz) = sz.into() {
+            self.h2_ping.bdp_initial_window = None;
             self.h2_builder.initial_connection_window_size(sz);
         }
         self
     }
 
+    /// Sets whether to use an adaptive flow control.
+    ///
+    /// Enabling this will override the limit set in
+    /// `http2_initial_connection_window_size`. The connection window starts
+    /// at the spec default, and grows as the bandwidth-delay product
+    /// measured with `PING` frames allows, up to
+    /// `http2_max_adaptive_window_size`.
+    ///
+    /// Only the connection window adapts. Stream windows can't be resized
+    /// once the connection is up, so each stream keeps the window set with
+    /// `http2_initial_stream_window_size`.
+    ///
+    /// Default is false.
+    pub fn http2_adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        use proto::h2::SPEC_WINDOW_SIZE;
+
+        if enabled {
+            self.h2_ping.bdp_initial_window = Some(SPEC_WINDOW_SIZE);
+            self.h2_builder.initial_connection_window_size(SPEC_WINDOW_SIZE);
+        } else {
+            self.h2_ping.bdp_initial_window = None;
+        }
+        self
+    }
+
+    /// Sets the largest window an adaptive flow control may grow to.
+    ///
+    /// Passing `None` will do nothing. Values larger than the maximum window
+    /// allowed by the spec are clamped to it. Does nothing unless
+    /// `http2_adaptive_window` is enabled.
+    ///
+    /// Default is 16MB.
+    pub fn http2_max_adaptive_window_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        if let Some(sz) = sz.into() {
+            self.h2_ping.bdp_max_window = sz.min(proto::h2::ping::MAX_WINDOW_SIZE);
+        }
+        self
+    }
+
+    /// Sets the [`SETTINGS_MAX_FRAME_SIZE`][spec] option for HTTP2, the
+    /// largest frame payload this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_FRAME_SIZE
+    pub fn http2_max_frame_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        if let Some(sz) = sz.into() {
+            self.h2_builder.max_frame_size(sz);
+        }
+        self
+    }
+
+    /// Sets the [`SETTINGS_MAX_HEADER_LIST_SIZE`][spec] option for HTTP2,
+    /// the largest header list this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16MB.
+    ///
+    /// The HPACK table size (`SETTINGS_HEADER_TABLE_SIZE`) and the send
+    /// buffer size of each stream are not supported, since h2 0.2 has no
+    /// option for them. The table size stays at the spec default of 4KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_HEADER_LIST_SIZE
+    pub fn http2_max_header_list_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        if let Some(sz) = sz.into() {
+            self.h2_builder.max_header_list_size(sz);
+        }
+        self
+    }
+
+    /// Sets an interval for HTTP2 Ping frames should be sent to keep a
+    /// connection alive.
+    ///
+    /// Pass `None` to disable HTTP2 keep-alive.
+    ///
+    /// Default is currently disabled.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_interval(&mut self, interval: impl Into<Option<Duration>>) -> &mut Self {
+        self.h2_ping.keep_alive_interval = interval.into();
+        self
+    }
+
+    /// Sets a timeout for receiving an acknowledgement of the keep-alive ping.
+    ///
+    /// If the ping is not acknowledged within the timeout, the connection will
+    /// be closed. Does nothing if `http2_keep_alive_interval` is disabled.
+    ///
+    /// Default is 20 seconds.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.h2_ping.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Sets whether HTTP2 keep-alive should apply while the connection is idle.
+    ///
+    /// If disabled, keep-alive pings are only sent while there are open
+    /// request/responses streams. If enabled, pings are also sent when no
+    /// streams are active. Does nothing if `http2_keep_alive_interval` is
+    /// disabled.
+    ///
+    /// Default is `false`.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_while_idle(&mut self, enabled: bool) -> &mut Self {
+        self.h2_ping.keep_alive_while_idle = enabled;
+        self
+    }
+
+    /// Sets whether the server may push responses, using the
+    /// [`SETTINGS_ENABLE_PUSH`][spec] option.
+    ///
+    /// Pushed responses can be received from
+    /// `SendRequest::pushed_responses`. Enabling push also limits the server
+    /// to 100 concurrently pushed streams.
+    ///
+    /// Default is false.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_ENABLE_PUSH
+    pub fn http2_enable_push(&mut self, enabled: bool) -> &mut Self {
+        self.h2_enable_push = enabled;
+        self.h2_builder.enable_push(enabled);
+        if enabled {
+            // Pushed streams count against the client's concurrency limit,
+            // which some servers (h2 among them) take as 0 when the client
+            // never advertised one.
+            self.h2_builder.max_concurrent_streams(DEFAULT_MAX_PUSHED_STREAMS);
+        }
+        self
+    }
+
     /// Constructs a connection with the configured options and IO.
     #[inline]
     pub fn handshake<T, B>(&self, io: T) -> Handshake<T, B>
@@ -549,6 +734,8 @@ where
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
         let io = self.io.take().expect("polled more than once");
         let (tx, rx) = dispatch::channel();
+        let mut pushes = None;
+        let mut h2_shared: Option<proto::h2::ClientShared> = None;
         let either = if !self.builder.http2 {
             let mut conn = proto::Conn::new(io);
             if !self.builder.h1_writev {
@@ -567,13 +754,30 @@ where
             let dispatch = proto::h1::Dispatcher::new(cd, conn);
             Either::Left(dispatch)
         } else {
-            let h2 = proto::h2::Client::new(io, rx, &self.builder.h2_builder, self.builder.exec.clone());
+            let push_tx = if self.builder.h2_enable_push {
+                let (push_tx, push_rx) = PushedResponses::channel();
+                pushes = Some(push_rx);
+                Some(push_tx)
+            } else {
+                None
+            };
+            let h2 = proto::h2::Client::new(
+                io,
+                rx,
+                &self.builder.h2_builder,
+                self.builder.h2_ping.clone(),
+                push_tx,
+                h2_shared.get_or_insert_with(Default::default).clone(),
+                self.builder.exec.clone(),
+            );
             Either::Right(h2)
         };
 
         Poll::Ready(Ok((
             SendRequest {
                 dispatch: tx,
+                pushes,
+                h2_shared,
             },
             Connection {
                 inner: Some(either),
@@ -0,0 +1,629 @@
+//! A resolver caching the addresses resolved by another resolver.
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_channel::oneshot;
+
+use crate::common::{Exec, Future, Pin, Poll, task};
+use super::{Name, Resolve, ServiceFuture};
+
+/// Above this many entries, expired entries are removed when inserting.
+const PRUNE_THRESHOLD: usize = 256;
+
+/// A resolver caching the addresses resolved by another `Resolve`.
+///
+/// Addresses are cached for the TTL reported by the inner resolver,
+/// clamped between the minimum and maximum TTL. If the inner resolver
+/// doesn't report a TTL, the maximum TTL is used. Failed lookups are
+/// cached for the negative TTL.
+///
+/// Concurrent lookups of the same name are merged into a single lookup
+/// of the inner resolver. If the inner resolver looks up the IPv6 and IPv4
+/// addresses separately, with `resolve_split`, each family is cached on its
+/// own.
+///
+/// Once addresses have expired, they are still returned for the stale TTL,
+/// while they are resolved again in the background. If that fails, for
+/// instance during a DNS outage, the stale addresses keep being used until
+/// the stale TTL runs out.
+#[derive(Clone)]
+pub struct CachingResolver<R> {
+    inner: R,
+    cache: Arc<Mutex<HashMap<Key, Entry>>>,
+    min_ttl: Duration,
+    max_ttl: Duration,
+    negative_ttl: Duration,
+    stale_ttl: Duration,
+}
+
+/// A future of the addresses resolved by a `CachingResolver`.
+#[must_use = "futures do nothing unless polled"]
+pub struct CachingFuture<R: Resolve> {
+    state: State<R>,
+}
+
+/// An iterator of the addresses resolved by a `CachingResolver`.
+pub struct CachedAddrs {
+    addrs: Arc<[IpAddr]>,
+    next: usize,
+    ttl: Duration,
+}
+
+type Cached = Result<Arc<[IpAddr]>, CachedError>;
+
+type Key = (Name, Family);
+
+/// The addresses of a name being cached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Family {
+    // Both families, from `resolve`.
+    Any,
+    // Only one family, from `resolve_split`.
+    V6,
+    V4,
+}
+
+// `io::Error` isn't `Clone`, so keep what is needed to make a new one.
+#[derive(Clone, Debug)]
+struct CachedError {
+    kind: io::ErrorKind,
+    message: String,
+}
+
+enum Entry {
+    Resolved {
+        result: Cached,
+        expires: Instant,
+        stale_until: Instant,
+        refreshing: bool,
+    },
+    // A lookup is in progress, and these are waiting for it, and its TTL.
+    Pending(Vec<oneshot::Sender<(Cached, Duration)>>),
+}
+
+enum State<R: Resolve> {
+    Ready(Option<(Cached, Duration)>),
+    Waiting(oneshot::Receiver<(Cached, Duration)>, CachingResolver<R>, Key),
+    Resolving(Lookup<R>),
+}
+
+/// A lookup of the inner resolver, storing the result in the cache.
+struct Lookup<R: Resolve> {
+    future: R::Future,
+    resolver: CachingResolver<R>,
+    key: Key,
+    // Refreshing stale addresses in the background.
+    is_refresh: bool,
+    is_done: bool,
+}
+
+/// The background task refreshing stale addresses.
+struct Refresh<R: Resolve>(Lookup<R>);
+
+impl<R> CachingResolver<R> {
+    /// Construct a new `CachingResolver` around `inner`.
+    pub fn new(inner: R) -> CachingResolver<R> {
+        CachingResolver {
+            inner,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            min_ttl: Duration::from_secs(0),
+            max_ttl: Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(5),
+            stale_ttl: Duration::from_secs(5 * 60),
+        }
+    }
+
+    /// Set the minimum time to cache addresses.
+    ///
+    /// Default is 0.
+    pub fn set_min_ttl(&mut self, ttl: Duration) {
+        self.min_ttl = ttl;
+    }
+
+    /// Set the maximum time to cache addresses, also used when the inner
+    /// resolver doesn't report a TTL.
+    ///
+    /// Default is 60 seconds.
+    pub fn set_max_ttl(&mut self, ttl: Duration) {
+        self.max_ttl = ttl;
+    }
+
+    /// Set the time to cache failed lookups.
+    ///
+    /// Default is 5 seconds.
+    pub fn set_negative_ttl(&mut self, ttl: Duration) {
+        self.negative_ttl = ttl;
+    }
+
+    /// Set how long expired addresses may still be used, while they are
+    /// resolved again.
+    ///
+    /// Default is 5 minutes.
+    pub fn set_stale_ttl(&mut self, ttl: Duration) {
+        self.stale_ttl = ttl;
+    }
+
+    /// Remove all cached addresses.
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+}
+
+impl<R> Resolve for CachingResolver<R>
+where
+    R: Resolve + Clone + Send + 'static,
+    R::Future: Send + 'static,
+{
+    type Addrs = CachedAddrs;
+    type Future = CachingFuture<R>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        self.resolve_key((name, Family::Any), None)
+    }
+
+    // The inner resolver must be asked whether it splits lookups, but its
+    // futures are only used if the cache misses.
+    fn resolve_split(&self, name: Name) -> Option<(Self::Future, Self::Future)> {
+        let (v6, v4) = self.inner.resolve_split(name.clone())?;
+        Some((
+            self.resolve_key((name.clone(), Family::V6), Some(v6)),
+            self.resolve_key((name, Family::V4), Some(v4)),
+        ))
+    }
+
+    fn ttl(&self, addrs: &CachedAddrs) -> Option<Duration> {
+        Some(addrs.ttl)
+    }
+
+    // Service addresses aren't cached.
+    fn resolve_service(&self, name: Name) -> Option<ServiceFuture> {
+        self.inner.resolve_service(name)
+    }
+}
+
+impl<R> CachingResolver<R>
+where
+    R: Resolve + Clone + Send + 'static,
+    R::Future: Send + 'static,
+{
+    /// Resolve from the cache, or with `future` of the inner resolver, if
+    /// given, otherwise a new lookup.
+    fn resolve_key(&self, key: Key, mut future: Option<R::Future>) -> CachingFuture<R> {
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap();
+        let mut refresh = false;
+        let cached = match cache.get_mut(&key) {
+            Some(&mut Entry::Resolved { ref result, expires, .. }) if now < expires => {
+                trace!("cached lookup for {:?}", key);
+                Some(State::Ready(Some((result.clone(), expires - now))))
+            },
+            Some(&mut Entry::Resolved {
+                result: ref result @ Ok(_),
+                stale_until,
+                ref mut refreshing,
+                ..
+            }) if now < stale_until => {
+                trace!("stale lookup for {:?}", key);
+                refresh = !*refreshing;
+                *refreshing = true;
+                Some(State::Ready(Some((result.clone(), Duration::from_secs(0)))))
+            },
+            Some(&mut Entry::Pending(ref mut waiters)) => {
+                trace!("waiting for lookup in progress for {:?}", key);
+                let (tx, rx) = oneshot::channel();
+                waiters.push(tx);
+                Some(State::Waiting(rx, self.clone(), key.clone()))
+            },
+            _ => None,
+        };
+        let state = cached.unwrap_or_else(|| {
+            cache.insert(key.clone(), Entry::Pending(Vec::new()));
+            State::Resolving(self.lookup(key.clone(), future.take(), false))
+        });
+        drop(cache);
+
+        if refresh {
+            let refresh = Refresh(self.lookup(key.clone(), future, true));
+            if let Err(err) = Exec::Default.execute(refresh) {
+                debug!("error spawning refresh of {:?}: {}", key, err);
+            }
+        }
+
+        CachingFuture {
+            state,
+        }
+    }
+
+    fn lookup(&self, key: Key, future: Option<R::Future>, is_refresh: bool) -> Lookup<R> {
+        Lookup {
+            future: future.unwrap_or_else(|| self.inner_lookup(&key)),
+            resolver: self.clone(),
+            key,
+            is_refresh,
+            is_done: false,
+        }
+    }
+
+    fn inner_lookup(&self, key: &Key) -> R::Future {
+        let (ref name, family) = *key;
+        let split = match family {
+            Family::Any => None,
+            Family::V6 | Family::V4 => self.inner.resolve_split(name.clone()),
+        };
+        match (split, family) {
+            (Some((v6, _)), Family::V6) => v6,
+            (Some((_, v4)), _) => v4,
+            (None, _) => self.inner.resolve(name.clone()),
+        }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for CachingResolver<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CachingResolver")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// ===== impl CachingFuture =====
+
+impl<R> Future for CachingFuture<R>
+where
+    R: Resolve + Clone + Send + 'static,
+    R::Future: Send + 'static,
+{
+    type Output = io::Result<CachedAddrs>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let state = match self.state {
+                State::Ready(ref mut result) => {
+                    let (result, ttl) = result.take().expect("polled after complete");
+                    return Poll::Ready(into_addrs(result, ttl));
+                },
+                State::Waiting(ref mut rx, ref resolver, ref key) => {
+                    match ready!(Pin::new(rx).poll(cx)) {
+                        Ok((result, ttl)) => return Poll::Ready(into_addrs(result, ttl)),
+                        Err(_canceled) => {
+                            // The lookup we were waiting for was dropped,
+                            // so start another one.
+                            trace!("lookup for {:?} canceled, retrying", key);
+                            resolver.resolve_key(key.clone(), None).state
+                        },
+                    }
+                },
+                State::Resolving(ref mut lookup) => {
+                    let (result, ttl) = ready!(lookup.poll(cx));
+                    return Poll::Ready(into_addrs(result, ttl));
+                },
+            };
+            self.state = state;
+        }
+    }
+}
+
+impl<R: Resolve> fmt::Debug for CachingFuture<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("CachingFuture")
+    }
+}
+
+fn into_addrs(result: Cached, ttl: Duration) -> io::Result<CachedAddrs> {
+    match result {
+        Ok(addrs) => Ok(CachedAddrs {
+            addrs,
+            next: 0,
+            ttl,
+        }),
+        Err(err) => Err(io::Error::new(err.kind, err.message)),
+    }
+}
+
+// ===== impl Lookup =====
+
+impl<R: Resolve> Lookup<R> {
+    fn poll(&mut self, cx: &mut task::Context<'_>) -> Poll<(Cached, Duration)> {
+        let res = ready!(Pin::new(&mut self.future).poll(cx));
+        let resolver = &self.resolver;
+        let (result, ttl) = match res {
+            Ok(addrs) => {
+                let ttl = resolver.inner
+                    .ttl(&addrs)
+                    .unwrap_or(resolver.max_ttl)
+                    .max(resolver.min_ttl)
+                    .min(resolver.max_ttl);
+                let addrs = addrs.collect::<Vec<_>>();
+                (Ok(Arc::from(addrs)), ttl)
+            },
+            Err(err) => {
+                debug!("lookup error for {:?}: {}", self.key, err);
+                let err = CachedError {
+                    kind: err.kind(),
+                    message: err.to_string(),
+                };
+                (Err(err), resolver.negative_ttl)
+            },
+        };
+
+        self.is_done = true;
+        let now = Instant::now();
+        let mut cache = resolver.cache.lock().unwrap();
+
+        if self.is_refresh && result.is_err() {
+            // Keep using the stale addresses.
+            if let Some(Entry::Resolved { ref mut refreshing, .. }) = cache.get_mut(&self.key) {
+                *refreshing = false;
+            }
+            return Poll::Ready((result, ttl));
+        }
+
+        if cache.len() >= PRUNE_THRESHOLD {
+            cache.retain(|_, entry| match *entry {
+                Entry::Resolved { stale_until, .. } => now < stale_until,
+                Entry::Pending(_) => true,
+            });
+        }
+
+        let stale_until = if result.is_ok() {
+            now + ttl + resolver.stale_ttl
+        } else {
+            now + ttl
+        };
+        let prev = cache.insert(self.key.clone(), Entry::Resolved {
+            result: result.clone(),
+            expires: now + ttl,
+            stale_until,
+            refreshing: false,
+        });
+
+        if let Some(Entry::Pending(waiters)) = prev {
+            for tx in waiters {
+                let _ = tx.send((result.clone(), ttl));
+            }
+        }
+
+        Poll::Ready((result, ttl))
+    }
+}
+
+impl<R: Resolve> Drop for Lookup<R> {
+    fn drop(&mut self) {
+        if self.is_done {
+            return;
+        }
+
+        // No panics in drop, so ignore a poisoned lock.
+        if let Ok(mut cache) = self.resolver.cache.lock() {
+            let is_pending = match cache.get_mut(&self.key) {
+                Some(Entry::Pending(_)) => !self.is_refresh,
+                Some(Entry::Resolved { ref mut refreshing, .. }) => {
+                    if self.is_refresh {
+                        *refreshing = false;
+                    }
+                    false
+                },
+                None => false,
+            };
+            if is_pending {
+                // Any waiters are canceled, and will start their own lookup.
+                cache.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl<R: Resolve> Future for Refresh<R> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.0.poll(cx).map(|_| ())
+    }
+}
+
+// ===== impl CachedAddrs =====
+
+impl Iterator for CachedAddrs {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let addr = self.addrs.get(self.next).cloned();
+        self.next += 1;
+        addr
+    }
+}
+
+impl fmt::Debug for CachedAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.addrs.iter())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::vec;
+
+    use futures_util::future;
+    use tokio_timer::Delay;
+
+    use super::*;
+
+    type FakeFuture = Pin<Box<dyn Future<Output = io::Result<vec::IntoIter<IpAddr>>> + Send>>;
+
+    /// Resolves every name to 10.0.0.1, counting the lookups.
+    ///
+    /// If `split`, also resolves the families separately, to fd00::1 and
+    /// 10.0.0.1, counting the split lookups that were polled.
+    #[derive(Clone, Default)]
+    struct Counting {
+        lookups: Arc<AtomicUsize>,
+        fail: Arc<AtomicBool>,
+        gate: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+        split: Arc<AtomicBool>,
+        split_lookups: Arc<AtomicUsize>,
+    }
+
+    fn ips(addrs: &[&str]) -> Vec<IpAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    impl Resolve for Counting {
+        type Addrs = vec::IntoIter<IpAddr>;
+        type Future = FakeFuture;
+
+        fn resolve(&self, _name: Name) -> Self::Future {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            let fail = self.fail.load(Ordering::SeqCst);
+            let gate = self.gate.lock().unwrap().take();
+            Box::pin(async move {
+                if let Some(gate) = gate {
+                    let _ = gate.await;
+                }
+                if fail {
+                    Err(io::Error::new(io::ErrorKind::NotFound, "fake failure"))
+                } else {
+                    Ok(vec!["10.0.0.1".parse().unwrap()].into_iter())
+                }
+            })
+        }
+
+        fn resolve_split(&self, _name: Name) -> Option<(Self::Future, Self::Future)> {
+            if !self.split.load(Ordering::SeqCst) {
+                return None;
+            }
+            let family = |addr: &'static str| -> FakeFuture {
+                let split_lookups = self.split_lookups.clone();
+                Box::pin(async move {
+                    split_lookups.fetch_add(1, Ordering::SeqCst);
+                    Ok(ips(&[addr]).into_iter())
+                })
+            };
+            Some((family("fd00::1"), family("10.0.0.1")))
+        }
+    }
+
+    fn name() -> Name {
+        "hyper.rs".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn caches_addresses() {
+        let inner = Counting::default();
+        let resolver = CachingResolver::new(inner.clone());
+
+        let addrs = resolver.resolve(name()).await.unwrap().collect::<Vec<_>>();
+        assert_eq!(addrs, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+        let addrs = resolver.resolve(name()).await.unwrap().collect::<Vec<_>>();
+        assert_eq!(addrs, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn caches_failures() {
+        let inner = Counting::default();
+        inner.fail.store(true, Ordering::SeqCst);
+        let resolver = CachingResolver::new(inner.clone());
+
+        let err = resolver.resolve(name()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = resolver.resolve(name()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_addresses_are_resolved_again() {
+        let inner = Counting::default();
+        let mut resolver = CachingResolver::new(inner.clone());
+        resolver.set_max_ttl(Duration::from_secs(0));
+        resolver.set_stale_ttl(Duration::from_secs(0));
+
+        resolver.resolve(name()).await.unwrap();
+        resolver.resolve(name()).await.unwrap();
+
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn merges_concurrent_lookups() {
+        let inner = Counting::default();
+        let (tx, rx) = oneshot::channel();
+        *inner.gate.lock().unwrap() = Some(rx);
+        let resolver = CachingResolver::new(inner.clone());
+
+        let first = resolver.resolve(name());
+        let second = resolver.resolve(name());
+        tx.send(()).unwrap();
+        let (first, second) = future::join(first, second).await;
+
+        let (first, second) = (first.unwrap(), second.unwrap());
+        // Without a TTL from the inner resolver, the maximum is used.
+        assert_eq!(resolver.ttl(&first), Some(Duration::from_secs(60)));
+        assert_eq!(resolver.ttl(&second), Some(Duration::from_secs(60)));
+        assert_eq!(first.collect::<Vec<_>>(), second.collect::<Vec<_>>());
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn caches_split_families_separately() {
+        let inner = Counting::default();
+        let resolver = CachingResolver::new(inner.clone());
+        assert!(resolver.resolve_split(name()).is_none());
+
+        inner.split.store(true, Ordering::SeqCst);
+        for _ in 0..2 {
+            let (v6, v4) = resolver.resolve_split(name()).expect("split");
+            assert_eq!(v6.await.unwrap().collect::<Vec<_>>(), ips(&["fd00::1"]));
+            assert_eq!(v4.await.unwrap().collect::<Vec<_>>(), ips(&["10.0.0.1"]));
+        }
+        assert_eq!(inner.split_lookups.load(Ordering::SeqCst), 2);
+
+        // Both families are cached apart from the split lookups.
+        resolver.resolve(name()).await.unwrap();
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn waiters_retry_if_lookup_dropped() {
+        let inner = Counting::default();
+        let (_tx, rx) = oneshot::channel();
+        *inner.gate.lock().unwrap() = Some(rx);
+        let resolver = CachingResolver::new(inner.clone());
+
+        let first = resolver.resolve(name());
+        let second = resolver.resolve(name());
+        drop(first);
+
+        second.await.unwrap();
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn stale_addresses_during_outage() {
+        let inner = Counting::default();
+        let mut resolver = CachingResolver::new(inner.clone());
+        resolver.set_max_ttl(Duration::from_secs(0));
+
+        resolver.resolve(name()).await.unwrap();
+        inner.fail.store(true, Ordering::SeqCst);
+
+        // Expired, so the stale addresses are returned while refreshing.
+        let addrs = resolver.resolve(name()).await.unwrap().collect::<Vec<_>>();
+        assert_eq!(addrs, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+
+        // Let the refresh fail in the background.
+        tokio_timer::delay(Instant::now() + Duration::from_millis(10)).await;
+        assert_eq!(inner.lookups.load(Ordering::SeqCst), 2);
+
+        resolver.resolve(name()).await.expect("still stale");
+    }
+}
@@ -0,0 +1,323 @@
+//! A DNS-over-HTTPS resolver.
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::vec;
+
+use futures_util::{future, TryStreamExt};
+use http::header::{ACCEPT, CONTENT_TYPE};
+use http::{Request, Uri};
+
+use crate::body::Body;
+use crate::client::Client;
+use crate::client::connect::Connect;
+use crate::common::{Future, Pin, Poll, task};
+use super::{Name, Resolve};
+use super::stub::{encode_query, parse_response, Answer, QTYPE_A, QTYPE_AAAA};
+
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// A resolver sending DNS queries over HTTPS, as described in
+/// [RFC 8484][rfc].
+///
+/// Queries are `POST`ed to the endpoint with the given `Client`, which
+/// should have a connector supporting TLS for an `https` endpoint.
+///
+/// # Bootstrapping
+///
+/// The hostname of the endpoint can't be resolved by the `DohResolver`
+/// itself. The connector of the `Client` must resolve it with another
+/// resolver, for instance a [`StaticResolver`](super::StaticResolver)
+/// pinning the endpoint to its known addresses, or the
+/// [`GaiResolver`](super::GaiResolver). The `DohResolver` fails to resolve
+/// the hostname of its endpoint, instead of recursing.
+///
+/// [rfc]: https://tools.ietf.org/html/rfc8484
+pub struct DohResolver<C> {
+    client: Client<C, Body>,
+    endpoint: Uri,
+}
+
+/// A future of the addresses resolved by a `DohResolver`.
+#[must_use = "futures do nothing unless polled"]
+pub struct DohFuture {
+    inner: Pin<Box<dyn Future<Output = io::Result<DohAddrs>> + Send>>,
+}
+
+/// An iterator of the addresses resolved by a `DohResolver`.
+pub struct DohAddrs {
+    inner: vec::IntoIter<IpAddr>,
+    // The smallest TTL of the address records.
+    ttl: Option<Duration>,
+}
+
+impl<C> DohResolver<C> {
+    /// Construct a new `DohResolver` sending queries to `endpoint` with
+    /// `client`.
+    ///
+    /// The endpoint is the full URI of the DoH service, such as
+    /// `https://dns.example/dns-query`.
+    pub fn new(client: Client<C, Body>, endpoint: Uri) -> DohResolver<C> {
+        DohResolver {
+            client,
+            endpoint,
+        }
+    }
+
+    fn is_endpoint(&self, name: &Name) -> bool {
+        self.endpoint
+            .host()
+            .map(|host| host.eq_ignore_ascii_case(name.as_str().trim_end_matches('.')))
+            .unwrap_or(false)
+    }
+}
+
+impl<C> Resolve for DohResolver<C>
+where
+    C: Connect + Sync + 'static,
+    C::Transport: 'static,
+    C::Future: 'static,
+{
+    type Addrs = DohAddrs;
+    type Future = DohFuture;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        if self.is_endpoint(&name) {
+            return DohFuture::error(name);
+        }
+        let v6 = query(self.client.clone(), self.endpoint.clone(), name.clone(), QTYPE_AAAA);
+        let v4 = query(self.client.clone(), self.endpoint.clone(), name, QTYPE_A);
+        DohFuture {
+            inner: Box::pin(async move {
+                let (addrs, ttl) = match future::join(v6, v4).await {
+                    (Ok(v6), Ok(v4)) => {
+                        let ttl = match (v6.ttl, v4.ttl) {
+                            (Some(a), Some(b)) => Some(a.min(b)),
+                            (a, b) => a.or(b),
+                        };
+                        (v6.inner.chain(v4.inner).collect::<Vec<_>>(), ttl)
+                    },
+                    (Ok(addrs), Err(err)) | (Err(err), Ok(addrs)) => {
+                        debug!("DoH lookup error for one address family: {}", err);
+                        (addrs.inner.collect::<Vec<_>>(), addrs.ttl)
+                    },
+                    (Err(err), Err(_)) => return Err(err),
+                };
+
+                if addrs.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "no addresses found"));
+                }
+                Ok(DohAddrs {
+                    inner: addrs.into_iter(),
+                    ttl,
+                })
+            }),
+        }
+    }
+
+    fn resolve_split(&self, name: Name) -> Option<(Self::Future, Self::Future)> {
+        if self.is_endpoint(&name) {
+            return None;
+        }
+        let v6 = DohFuture {
+            inner: Box::pin(query(self.client.clone(), self.endpoint.clone(), name.clone(), QTYPE_AAAA)),
+        };
+        let v4 = DohFuture {
+            inner: Box::pin(query(self.client.clone(), self.endpoint.clone(), name, QTYPE_A)),
+        };
+        Some((v6, v4))
+    }
+
+    fn ttl(&self, addrs: &DohAddrs) -> Option<Duration> {
+        addrs.ttl
+    }
+}
+
+impl<C> Clone for DohResolver<C> {
+    fn clone(&self) -> DohResolver<C> {
+        DohResolver {
+            client: self.client.clone(),
+            endpoint: self.endpoint.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for DohResolver<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DohResolver")
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+/// Query the endpoint for the addresses of one family of `name`.
+async fn query<C>(client: Client<C, Body>, endpoint: Uri, name: Name, qtype: u16) -> io::Result<DohAddrs>
+where
+    C: Connect + Sync + 'static,
+    C::Transport: 'static,
+    C::Future: 'static,
+{
+    // The ID should be 0, so answers can be cached by HTTP caches.
+    let msg = encode_query(0, name.as_str().trim_end_matches('.'), qtype)?;
+    let req = Request::post(endpoint)
+        .header(CONTENT_TYPE, DNS_MESSAGE)
+        .header(ACCEPT, DNS_MESSAGE)
+        .body(Body::from(msg.clone()))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let res = client
+        .request(req)
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    if !res.status().is_success() {
+        debug!("DoH endpoint responded with status {}", res.status());
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("DoH endpoint responded with status {}", res.status()),
+        ));
+    }
+    let body = res
+        .into_body()
+        .try_concat()
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+    match parse_response(&body, &msg)? {
+        Answer::Addrs(addrs, ttl) => Ok(DohAddrs {
+            inner: addrs.into_iter(),
+            ttl,
+        }),
+        Answer::NoSuchDomain => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such host: {}", name),
+        )),
+        Answer::Failed(rcode) => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("nameserver failure, rcode {}", rcode),
+        )),
+    }
+}
+
+// ===== impl DohFuture =====
+
+impl DohFuture {
+    fn error(name: Name) -> DohFuture {
+        let err = io::Error::new(
+            io::ErrorKind::Other,
+            format!("DoH endpoint {} must be resolved by another resolver", name),
+        );
+        DohFuture {
+            inner: Box::pin(future::err(err)),
+        }
+    }
+}
+
+impl Future for DohFuture {
+    type Output = io::Result<DohAddrs>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for DohFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("DohFuture")
+    }
+}
+
+// ===== impl DohAddrs =====
+
+impl Iterator for DohAddrs {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl fmt::Debug for DohAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("DohAddrs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use http::{Method, Response, StatusCode};
+
+    use crate::client::HttpConnector;
+    use crate::server::Server;
+    use crate::service::{make_service_fn, service_fn};
+    use super::*;
+    use super::super::stub::{read_u16, skip_name, QCLASS_IN};
+
+    /// Answer a query with one address, like a nameserver would.
+    fn answer(query: &[u8]) -> Vec<u8> {
+        let end = skip_name(query, 12).unwrap() + 4;
+        let qtype = read_u16(query, end - 4).unwrap();
+        let mut res = query[..end].to_vec();
+        // response, recursion available, 1 answer
+        res[2] |= 0x80;
+        res[3] |= 0x80;
+        res[7] = 1;
+        res.extend_from_slice(&[0xc0, 12]);
+        res.extend_from_slice(&qtype.to_be_bytes());
+        res.extend_from_slice(&QCLASS_IN.to_be_bytes());
+        res.extend_from_slice(&30u32.to_be_bytes());
+        if qtype == QTYPE_A {
+            res.extend_from_slice(&[0, 4, 10, 0, 0, 1]);
+        } else {
+            res.extend_from_slice(&[0, 16]);
+            res.extend_from_slice(&"fd00::1".parse::<Ipv6Addr>().unwrap().octets());
+        }
+        res
+    }
+
+    /// A DoH endpoint on localhost, answering every query.
+    fn endpoint() -> Uri {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, crate::Error>(service_fn(|req: Request<Body>| async move {
+                assert_eq!(req.method(), Method::POST);
+                assert_eq!(req.headers()[CONTENT_TYPE], DNS_MESSAGE);
+                let query = req.into_body().try_concat().await?;
+                let mut res = Response::new(Body::from(answer(&query)));
+                res.headers_mut().insert(CONTENT_TYPE, DNS_MESSAGE.parse().unwrap());
+                Ok::<_, crate::Error>(res)
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let uri = format!("http://{}/dns-query", server.local_addr()).parse().unwrap();
+        tokio::spawn(async move {
+            let _ = server.await;
+        });
+        uri
+    }
+
+    #[tokio::test]
+    async fn resolve_over_http() {
+        let client = Client::builder().build(HttpConnector::new(1));
+        let resolver = DohResolver::new(client, endpoint());
+
+        let addrs = resolver.resolve("hyper.rs".parse().unwrap()).await.expect("resolve");
+        assert_eq!(resolver.ttl(&addrs), Some(Duration::from_secs(30)));
+        let mut addrs = addrs.collect::<Vec<_>>();
+        addrs.sort();
+        assert_eq!(addrs, vec![
+            "10.0.0.1".parse::<IpAddr>().unwrap(),
+            "fd00::1".parse::<IpAddr>().unwrap(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_resolve_endpoint() {
+        let client = Client::builder().build(HttpConnector::new(1));
+        let resolver = DohResolver::new(client, "https://dns.example/dns-query".parse().unwrap());
+
+        resolver.resolve("DNS.example".parse().unwrap()).await.unwrap_err();
+        assert!(resolver.resolve_split("dns.example".parse().unwrap()).is_none());
+    }
+}
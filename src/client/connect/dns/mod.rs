@@ -0,0 +1,708 @@
+//! The `Resolve` trait, support types, and some basic implementations.
+//!
+//! This module contains:
+//!
+//! - A [`GaiResolver`](dns::GaiResolver) that is the default resolver for the
+//!   `HttpConnector`.
+//! - A [`StubResolver`](dns::StubResolver) that queries nameservers
+//!   asynchronously, without blocking threads.
+//! - A [`DohResolver`](dns::DohResolver) that sends queries over HTTPS with
+//!   a hyper `Client`.
+//! - A [`CachingResolver`](dns::CachingResolver) that caches the addresses
+//!   resolved by another resolver.
+//! - A [`StaticResolver`](dns::StaticResolver) that resolves some hosts to
+//!   fixed addresses, like `curl --resolve`.
+//! - The [`Resolve`](dns::Resolve) trait and related types to build a custom
+//!   resolver for use with the `HttpConnector`.
+use std::{fmt, io, vec};
+use std::error::Error;
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr,
+    SocketAddr, ToSocketAddrs,
+    SocketAddrV4, SocketAddrV6,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{FutureExt, StreamExt};
+use tokio_executor::TypedExecutor;
+use tokio_sync::{mpsc, oneshot};
+use tokio_executor::threadpool;
+
+use crate::common::{Future, Never, Pin, Poll, Unpin, task};
+
+pub use self::cache::{CachedAddrs, CachingFuture, CachingResolver};
+pub use self::doh::{DohAddrs, DohFuture, DohResolver};
+pub use self::overrides::{Overrides, StaticAddrs, StaticFuture, StaticResolver};
+pub use self::stub::{StubAddrs, StubFuture, StubResolver};
+
+mod cache;
+mod doh;
+mod overrides;
+mod stub;
+
+/// Resolve a hostname to a set of IP addresses.
+pub trait Resolve: Unpin {
+    /// The set of IP addresses to try to connect to.
+    type Addrs: Iterator<Item=IpAddr>;
+    /// A Future of the resolved set of addresses.
+    type Future: Future<Output=Result<Self::Addrs, io::Error>> + Unpin;
+    /// Resolve a hostname.
+    fn resolve(&self, name: Name) -> Self::Future;
+
+    /// Resolve a hostname's IPv6 and IPv4 addresses with separate lookups.
+    ///
+    /// Returns the futures of the `AAAA` and the `A` lookup, in that order,
+    /// each only yielding addresses of its family. This allows the
+    /// `HttpConnector` to start connecting before both lookups are done, as
+    /// described in [RFC 8305][rfc].
+    ///
+    /// The default returns `None`, and `resolve` is used instead.
+    ///
+    /// [rfc]: https://tools.ietf.org/html/rfc8305#section-3
+    fn resolve_split(&self, name: Name) -> Option<(Self::Future, Self::Future)> {
+        let _ = name;
+        None
+    }
+
+    /// How long the resolved addresses may be cached, if known.
+    ///
+    /// This is used by the [`CachingResolver`](CachingResolver). The default
+    /// returns `None`.
+    fn ttl(&self, addrs: &Self::Addrs) -> Option<Duration> {
+        let _ = addrs;
+        None
+    }
+
+    /// Resolve the addresses of a service at a hostname, such as with `SRV`
+    /// records.
+    ///
+    /// The addresses include a port, used instead of the port of the
+    /// destination, and are tried in order of priority and weight as
+    /// described in [RFC 2782][rfc]. If the future yields no addresses, the
+    /// hostname is resolved with `resolve_split` or `resolve` instead.
+    ///
+    /// The default returns `None`.
+    ///
+    /// [rfc]: https://tools.ietf.org/html/rfc2782
+    fn resolve_service(&self, name: Name) -> Option<ServiceFuture> {
+        let _ = name;
+        None
+    }
+}
+
+/// A domain name to resolve into IP addresses.
+#[derive(Clone, Hash, Eq, PartialEq)]
+pub struct Name {
+    host: String,
+    port: Option<u16>,
+}
+
+/// A resolver using blocking `getaddrinfo` calls in a threadpool.
+#[derive(Clone)]
+pub struct GaiResolver {
+    tx: threadpool::Sender,
+    /// A handle to keep the threadpool alive until all `GaiResolver` clones
+    /// have been dropped.
+    _threadpool_keep_alive: ThreadPoolKeepAlive,
+}
+
+#[derive(Clone)]
+struct ThreadPoolKeepAlive(mpsc::Sender<Never>);
+
+/// An iterator of IP addresses returned from `getaddrinfo`.
+pub struct GaiAddrs {
+    inner: IpAddrs,
+}
+
+/// A future to resole a name returned by `GaiResolver`.
+pub struct GaiFuture {
+    rx: oneshot::Receiver<Result<IpAddrs, io::Error>>,
+    _threadpool_keep_alive: ThreadPoolKeepAlive,
+}
+
+impl Name {
+    /// Construct a `Name` from a hostname.
+    pub fn new(host: String) -> Name {
+        Name {
+            host,
+            port: None,
+        }
+    }
+
+    /// Set the port that will be connected to.
+    ///
+    /// The `HttpConnector` sets the port of the destination, for resolvers
+    /// that resolve hosts differently by port.
+    pub fn with_port(mut self, port: u16) -> Name {
+        self.port = Some(port);
+        self
+    }
+
+    /// View the hostname as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.host
+    }
+
+    /// The port that will be connected to, if known.
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.host, f)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.host, f)
+    }
+}
+
+impl FromStr for Name {
+    type Err = InvalidNameError;
+
+    fn from_str(host: &str) -> Result<Self, Self::Err> {
+        // Possibly add validation later
+        Ok(Name::new(host.to_owned()))
+    }
+}
+
+/// Error indicating a given string was not a valid domain name.
+#[derive(Debug)]
+pub struct InvalidNameError(());
+
+impl fmt::Display for InvalidNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Not a valid domain name")
+    }
+}
+
+impl Error for InvalidNameError {}
+
+/// The address of a service, with the priority and weight of its `SRV`
+/// record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ServiceAddr {
+    addr: SocketAddr,
+    priority: u16,
+    weight: u16,
+}
+
+impl ServiceAddr {
+    /// Construct a new `ServiceAddr`.
+    ///
+    /// Addresses with a lower `priority` are tried first. Among those with
+    /// the same priority, addresses with a larger `weight` are more likely
+    /// to be tried first.
+    pub fn new(addr: SocketAddr, priority: u16, weight: u16) -> ServiceAddr {
+        ServiceAddr {
+            addr,
+            priority,
+            weight,
+        }
+    }
+
+    /// The socket address of the service.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The priority of the address, lower is preferred.
+    pub fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    /// The relative weight of the address among those of the same priority.
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+}
+
+/// A future of the addresses of a service, returned by
+/// `Resolve::resolve_service`.
+#[must_use = "futures do nothing unless polled"]
+pub struct ServiceFuture {
+    inner: Pin<Box<dyn Future<Output = io::Result<Vec<ServiceAddr>>> + Send>>,
+}
+
+impl ServiceFuture {
+    /// Wrap a future resolving to the addresses of a service.
+    pub fn new<F>(future: F) -> ServiceFuture
+    where
+        F: Future<Output = io::Result<Vec<ServiceAddr>>> + Send + 'static,
+    {
+        ServiceFuture {
+            inner: Box::pin(future),
+        }
+    }
+}
+
+impl Future for ServiceFuture {
+    type Output = io::Result<Vec<ServiceAddr>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for ServiceFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("ServiceFuture")
+    }
+}
+
+
+impl GaiResolver {
+    /// Construct a new `GaiResolver`.
+    ///
+    /// Takes number of DNS worker threads.
+    pub fn new(threads: usize) -> Self {
+        let pool = threadpool::Builder::new()
+            .name_prefix("hyper-dns-gai-resolver")
+            // not for CPU tasks, so only spawn workers
+            // in blocking mode
+            .pool_size(1)
+            .max_blocking(threads)
+            .build();
+
+        let tx = pool.sender().clone();
+
+        // The pool will start to shutdown once `pool` is dropped,
+        // so to keep it alive, we spawn a future onto the pool itself
+        // that will only resolve once all `GaiResolver` requests
+        // are finished.
+        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+
+        let on_shutdown = shutdown_rx
+            .into_future()
+            .map(move |(next, _rx)| {
+                match next {
+                    Some(never) => match never {},
+                    None => (),
+                }
+
+                drop(pool)
+            });
+        tx.spawn(on_shutdown);
+
+        GaiResolver {
+            tx,
+            _threadpool_keep_alive: ThreadPoolKeepAlive(shutdown_tx),
+        }
+    }
+}
+
+impl Resolve for GaiResolver {
+    type Addrs = GaiAddrs;
+    type Future = GaiFuture;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        let (tx, rx) = oneshot::channel();
+        self.tx.spawn(GaiBlocking {
+            host: name.host,
+            tx: Some(tx),
+        });
+        GaiFuture {
+            rx,
+            _threadpool_keep_alive: self._threadpool_keep_alive.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for GaiResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("GaiResolver")
+    }
+}
+
+impl Future for GaiFuture {
+    type Output = Result<GaiAddrs, io::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx).poll(cx).map(|res| match res {
+            Ok(Ok(addrs)) => Ok(GaiAddrs { inner: addrs }),
+            Ok(Err(err)) => Err(err),
+            Err(_canceled) => unreachable!("GaiResolver threadpool shutdown"),
+        })
+    }
+}
+
+impl fmt::Debug for GaiFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("GaiFuture")
+    }
+}
+
+impl Iterator for GaiAddrs {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|sa| sa.ip())
+    }
+}
+
+impl fmt::Debug for GaiAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("GaiAddrs")
+    }
+}
+
+
+pub(super) struct GaiBlocking {
+    host: String,
+    tx: Option<oneshot::Sender<io::Result<IpAddrs>>>,
+}
+
+impl GaiBlocking {
+    fn block(&self) -> io::Result<IpAddrs> {
+        debug!("resolving host={:?}", self.host);
+        (&*self.host, 0).to_socket_addrs()
+            .map(|i| IpAddrs { iter: i })
+
+    }
+}
+
+impl Future for GaiBlocking {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        if self.tx.as_mut().expect("polled after complete").poll_closed(cx).is_ready() {
+            trace!("resolve future canceled for {:?}", self.host);
+            return Poll::Ready(());
+        }
+
+        debug!("resolving host={:?}", self.host);
+        let res = (&*self.host, 0).to_socket_addrs()
+            .map(|i| IpAddrs { iter: i });
+
+        let tx = self.tx.take().expect("polled after complete");
+        let _ = tx.send(res);
+
+        Poll::Ready(())
+    }
+}
+
+pub(super) struct IpAddrs {
+    iter: vec::IntoIter<SocketAddr>,
+}
+
+impl IpAddrs {
+    pub(super) fn new(addrs: Vec<SocketAddr>) -> Self {
+        IpAddrs { iter: addrs.into_iter() }
+    }
+
+    pub(super) fn try_parse(host: &str, port: u16) -> Option<IpAddrs> {
+        if let Ok(addr) = host.parse::<Ipv4Addr>() {
+            let addr = SocketAddrV4::new(addr, port);
+            return Some(IpAddrs { iter: vec![SocketAddr::V4(addr)].into_iter() })
+        }
+        let host = {
+            // trim_left/trim_right deprecated...
+            // TODO: use trim_start/trim_end in Rust 1.30
+            #[allow(deprecated)]
+            {
+                host
+                .trim_left_matches('[')
+                .trim_right_matches(']')
+            }
+        };
+        if let Ok(addr) = host.parse::<Ipv6Addr>() {
+            let addr = SocketAddrV6::new(addr, port, 0, 0);
+            return Some(IpAddrs { iter: vec![SocketAddr::V6(addr)].into_iter() })
+        }
+        None
+    }
+
+    /// Order the addresses to connect to, as described in
+    /// [RFC 8305 section 4](https://tools.ietf.org/html/rfc8305#section-4).
+    ///
+    /// The addresses are sorted by the rules of RFC 6724 that don't depend
+    /// on the source address, and then the families are interleaved. The
+    /// first family is IPv6 if `first_v6` is `Some(true)`, IPv4 if it is
+    /// `Some(false)`, or else the family of the first sorted address.
+    pub(super) fn sort_for_happy_eyeballs(self, first_v6: Option<bool>) -> Vec<SocketAddr> {
+        let mut addrs = self.iter.collect::<Vec<_>>();
+        // A stable sort, so equal addresses keep the order of the resolver.
+        addrs.sort_by(|a, b| {
+            // Rule 6: prefer higher precedence.
+            precedence(b.ip()).cmp(&precedence(a.ip()))
+                // Rule 8: prefer smaller scope.
+                .then_with(|| scope(a.ip()).cmp(&scope(b.ip())))
+        });
+
+        let first_v6 = first_v6
+            .or_else(|| addrs.first().map(SocketAddr::is_ipv6))
+            .unwrap_or(false);
+        let (first, second) = addrs
+            .into_iter()
+            .partition::<Vec<_>, _>(|addr| addr.is_ipv6() == first_v6);
+
+        let mut sorted = Vec::with_capacity(first.len() + second.len());
+        let mut first = first.into_iter();
+        let mut second = second.into_iter();
+        loop {
+            match (first.next(), second.next()) {
+                (None, None) => break,
+                (a, b) => {
+                    sorted.extend(a);
+                    sorted.extend(b);
+                }
+            }
+        }
+        sorted
+    }
+}
+
+/// The precedence of an address in the default policy table of RFC 6724.
+fn precedence(ip: IpAddr) -> u8 {
+    let ip = match ip {
+        IpAddr::V4(_) => return 35,
+        IpAddr::V6(ip) => ip,
+    };
+    let segments = ip.segments();
+    if ip.is_loopback() {
+        50
+    } else if segments[..6] == [0, 0, 0, 0, 0, 0xffff] {
+        // ::ffff:0:0/96, IPv4-mapped
+        35
+    } else if segments[0] == 0x2002 {
+        // 2002::/16, 6to4
+        30
+    } else if segments[0] == 0x2001 && segments[1] == 0 {
+        // 2001::/32, Teredo
+        5
+    } else if segments[0] & 0xfe00 == 0xfc00 {
+        // fc00::/7, unique local
+        3
+    } else if segments[..6] == [0; 6]
+        || segments[0] & 0xffc0 == 0xfec0
+        || segments[0] == 0x3ffe {
+        // ::/96, fec0::/10 and 3ffe::/16, deprecated
+        1
+    } else {
+        40
+    }
+}
+
+/// The scope of an address, as used by RFC 6724.
+fn scope(ip: IpAddr) -> u8 {
+    const LINK_LOCAL: u8 = 0x2;
+    const SITE_LOCAL: u8 = 0x5;
+    const GLOBAL: u8 = 0xe;
+
+    match ip {
+        IpAddr::V4(ip) => {
+            if ip.is_loopback() || ip.is_link_local() {
+                LINK_LOCAL
+            } else {
+                GLOBAL
+            }
+        },
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            if ip.is_multicast() {
+                (segments[0] & 0x000f) as u8
+            } else if ip.is_loopback() || segments[0] & 0xffc0 == 0xfe80 {
+                LINK_LOCAL
+            } else if segments[0] & 0xffc0 == 0xfec0 {
+                SITE_LOCAL
+            } else {
+                GLOBAL
+            }
+        },
+    }
+}
+
+impl Iterator for IpAddrs {
+    type Item = SocketAddr;
+    #[inline]
+    fn next(&mut self) -> Option<SocketAddr> {
+        self.iter.next()
+    }
+}
+
+/// Order the addresses of a service as described in RFC 2782.
+///
+/// Lower priorities come first. Within a priority, addresses are picked
+/// one at a time, each with a chance proportional to its weight.
+/// `random(max)` must return a number in `0..=max`.
+pub(super) fn sort_services<F>(mut addrs: Vec<ServiceAddr>, mut random: F) -> Vec<SocketAddr>
+where
+    F: FnMut(u32) -> u32,
+{
+    // A stable sort, with the weight 0 addresses first within a priority,
+    // so they are only picked when the random number is 0.
+    addrs.sort_by_key(|addr| (addr.priority, addr.weight != 0));
+
+    let mut sorted = Vec::with_capacity(addrs.len());
+    while !addrs.is_empty() {
+        let priority = addrs[0].priority;
+        let same = addrs.iter().take_while(|addr| addr.priority == priority).count();
+        let mut group = addrs.drain(..same).collect::<Vec<_>>();
+        while !group.is_empty() {
+            let total = group.iter().map(|addr| addr.weight as u32).sum();
+            let pick = random(total);
+            let mut sum = 0;
+            let i = group
+                .iter()
+                .position(|addr| {
+                    sum += addr.weight as u32;
+                    sum >= pick
+                })
+                .unwrap_or(0);
+            sorted.push(group.remove(i).addr);
+        }
+    }
+    sorted
+}
+
+/// A random number in `0..=max`, good enough to spread load.
+pub(super) fn random_up_to(max: u32) -> u32 {
+    use std::cell::Cell;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    thread_local!(static STATE: Cell<u64> = Cell::new({
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|dur| dur.as_nanos() as u64)
+            .unwrap_or(0);
+        // xorshift gets stuck on 0
+        nanos | 1
+    }));
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x % (max as u64 + 1)) as u32
+    })
+}
+
+/// A resolver using `getaddrinfo` calls via the `threadpool::blocking` API.
+///
+/// Unlike the `GaiResolver` this will not spawn dedicated threads, but only works when running on the
+/// multi-threaded Tokio runtime.
+#[derive(Clone, Debug)]
+pub struct TokioThreadpoolGaiResolver(());
+
+/// The future returned by `TokioThreadpoolGaiResolver`.
+#[derive(Debug)]
+pub struct TokioThreadpoolGaiFuture {
+    name: Name,
+}
+
+impl TokioThreadpoolGaiResolver {
+    /// Creates a new DNS resolver that will use tokio threadpool's blocking
+    /// feature.
+    ///
+    /// **Requires** its futures to be run on the threadpool runtime.
+    pub fn new() -> Self {
+        TokioThreadpoolGaiResolver(())
+    }
+}
+
+impl Resolve for TokioThreadpoolGaiResolver {
+    type Addrs = GaiAddrs;
+    type Future = TokioThreadpoolGaiFuture;
+
+    fn resolve(&self, name: Name) -> TokioThreadpoolGaiFuture {
+        TokioThreadpoolGaiFuture { name }
+    }
+}
+
+impl Future for TokioThreadpoolGaiFuture {
+    type Output = Result<GaiAddrs, io::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        match ready!(threadpool::blocking(|| (self.name.as_str(), 0).to_socket_addrs())) {
+            Ok(Ok(iter)) => Poll::Ready(Ok(GaiAddrs { inner: IpAddrs { iter } })),
+            Ok(Err(e)) => Poll::Ready(Err(e)),
+            // a BlockingError, meaning not on a tokio_threadpool :(
+            Err(e) => Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use super::*;
+
+    #[test]
+    fn test_ip_addrs_sort_for_happy_eyeballs() {
+        let v4_a = (Ipv4Addr::new(192, 0, 2, 1), 80).into();
+        let v4_b = (Ipv4Addr::new(192, 0, 2, 2), 80).into();
+        let v6_a = (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 80).into();
+        let v6_b = (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2), 80).into();
+        let teredo = (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 1), 80).into();
+
+        // IPv6 has a higher precedence, then the families are interleaved.
+        let sorted = IpAddrs::new(vec![v4_a, v4_b, v6_a, v6_b])
+            .sort_for_happy_eyeballs(None);
+        assert_eq!(sorted, vec![v6_a, v4_a, v6_b, v4_b]);
+
+        // Teredo has a lower precedence than IPv4.
+        let sorted = IpAddrs::new(vec![teredo, v4_a])
+            .sort_for_happy_eyeballs(None);
+        assert_eq!(sorted, vec![v4_a, teredo]);
+
+        // The first family can be chosen.
+        let sorted = IpAddrs::new(vec![v6_a, v6_b, v4_a])
+            .sort_for_happy_eyeballs(Some(false));
+        assert_eq!(sorted, vec![v4_a, v6_a, v6_b]);
+    }
+
+    #[test]
+    fn test_sort_services() {
+        let addr = |port: u16| SocketAddr::from((Ipv4Addr::new(192, 0, 2, 1), port));
+        let services = vec![
+            ServiceAddr::new(addr(3), 20, 0),
+            ServiceAddr::new(addr(1), 10, 1),
+            ServiceAddr::new(addr(2), 10, 3),
+            ServiceAddr::new(addr(0), 10, 0),
+        ];
+
+        // Always picking the first of the running sums.
+        let sorted = sort_services(services.clone(), |_| 0);
+        assert_eq!(sorted, vec![addr(0), addr(1), addr(2), addr(3)]);
+
+        // Always picking the last, so the heaviest goes first.
+        let sorted = sort_services(services.clone(), |max| max);
+        assert_eq!(sorted, vec![addr(2), addr(1), addr(0), addr(3)]);
+
+        for _ in 0..10 {
+            let sorted = sort_services(services.clone(), random_up_to);
+            assert_eq!(sorted[3], addr(3));
+        }
+    }
+
+    #[test]
+    fn test_name_from_str() {
+        const DOMAIN: &str = "test.example.com";
+        let name = Name::from_str(DOMAIN).expect("Should be a valid domain");
+        assert_eq!(name.as_str(), DOMAIN);
+        assert_eq!(name.to_string(), DOMAIN);
+    }
+
+    #[test]
+    fn ip_addrs_try_parse_v6() {
+        let uri = ::http::Uri::from_static("http://[::1]:8080/");
+        let dst = super::super::Destination::new(uri);
+
+        let mut addrs = IpAddrs::try_parse(
+            dst.host(),
+            dst.port().expect("port")
+        ).expect("try_parse");
+
+        let expected = "[::1]:8080".parse::<SocketAddr>().expect("expected");
+
+        assert_eq!(addrs.next(), Some(expected));
+    }
+}
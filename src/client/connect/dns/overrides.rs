@@ -0,0 +1,306 @@
+//! A resolver with fixed addresses for some hosts.
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::common::{Future, Pin, Poll, task};
+use super::{Name, Resolve, ServiceFuture};
+
+/// A resolver returning fixed addresses for some hosts, and asking another
+/// `Resolve` for the rest.
+///
+/// This is like `curl --resolve`, pinning hosts to addresses without
+/// editing `/etc/hosts`. An override may apply to a host on any port, or
+/// only on a specific port, the latter taking precedence.
+///
+/// The overrides are shared by all clones of the resolver, and can be
+/// changed at any time through its [`Overrides`](Overrides) handle. This
+/// only affects new lookups, not connections already established.
+///
+/// # Example
+///
+/// ```
+/// use hyper::client::HttpConnector;
+/// use hyper::client::connect::dns::{GaiResolver, StaticResolver};
+///
+/// let resolver = StaticResolver::new(GaiResolver::new(1));
+/// let overrides = resolver.overrides();
+/// overrides.insert("example.com", Some(443), vec![[127, 0, 0, 1].into()]);
+///
+/// let connector = HttpConnector::new_with_resolver(resolver);
+///
+/// // later, cut over to another address
+/// overrides.insert("example.com", Some(443), vec![[127, 0, 0, 2].into()]);
+/// # drop(connector);
+/// ```
+#[derive(Clone)]
+pub struct StaticResolver<R> {
+    inner: R,
+    overrides: Overrides,
+}
+
+/// A shared handle to change the overrides of a `StaticResolver`.
+#[derive(Clone, Default)]
+pub struct Overrides {
+    map: Arc<RwLock<HashMap<(String, Option<u16>), Arc<[IpAddr]>>>>,
+}
+
+/// A future of the addresses resolved by a `StaticResolver`.
+#[must_use = "futures do nothing unless polled"]
+pub struct StaticFuture<R: Resolve> {
+    inner: Inner<Option<Arc<[IpAddr]>>, R::Future>,
+}
+
+/// An iterator of the addresses resolved by a `StaticResolver`.
+pub struct StaticAddrs<A> {
+    inner: Inner<(Arc<[IpAddr]>, usize), A>,
+}
+
+enum Inner<S, R> {
+    Static(S),
+    Resolved(R),
+}
+
+impl<R> StaticResolver<R> {
+    /// Construct a new `StaticResolver` around `inner`, without overrides.
+    pub fn new(inner: R) -> StaticResolver<R> {
+        StaticResolver::with_overrides(inner, Overrides::new())
+    }
+
+    /// Construct a new `StaticResolver` around `inner`, using existing
+    /// overrides.
+    pub fn with_overrides(inner: R, overrides: Overrides) -> StaticResolver<R> {
+        StaticResolver {
+            inner,
+            overrides,
+        }
+    }
+
+    /// Get a handle to change the overrides of this resolver.
+    pub fn overrides(&self) -> Overrides {
+        self.overrides.clone()
+    }
+}
+
+impl<R: Resolve> Resolve for StaticResolver<R> {
+    type Addrs = StaticAddrs<R::Addrs>;
+    type Future = StaticFuture<R>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        let inner = match self.overrides.get(&name) {
+            Some(addrs) => {
+                trace!("static override for {:?}", name);
+                Inner::Static(Some(addrs))
+            },
+            None => Inner::Resolved(self.inner.resolve(name)),
+        };
+        StaticFuture {
+            inner,
+        }
+    }
+
+    fn resolve_split(&self, name: Name) -> Option<(Self::Future, Self::Future)> {
+        if self.overrides.get(&name).is_some() {
+            return None;
+        }
+        self.inner
+            .resolve_split(name)
+            .map(|(v6, v4)| {
+                (
+                    StaticFuture { inner: Inner::Resolved(v6) },
+                    StaticFuture { inner: Inner::Resolved(v4) },
+                )
+            })
+    }
+
+    fn ttl(&self, addrs: &Self::Addrs) -> Option<Duration> {
+        match addrs.inner {
+            Inner::Static(..) => None,
+            Inner::Resolved(ref addrs) => self.inner.ttl(addrs),
+        }
+    }
+
+    fn resolve_service(&self, name: Name) -> Option<ServiceFuture> {
+        if self.overrides.get(&name).is_some() {
+            return None;
+        }
+        self.inner.resolve_service(name)
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for StaticResolver<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StaticResolver")
+            .field("inner", &self.inner)
+            .field("overrides", &self.overrides)
+            .finish()
+    }
+}
+
+// ===== impl Overrides =====
+
+impl Overrides {
+    /// Construct empty overrides.
+    pub fn new() -> Overrides {
+        Overrides::default()
+    }
+
+    /// Resolve `host` to `addrs`, replacing any previous override.
+    ///
+    /// With a `port`, the override only applies to connections to that
+    /// port. Hosts are matched case-insensitively.
+    pub fn insert<I>(&self, host: &str, port: Option<u16>, addrs: I)
+    where
+        I: IntoIterator<Item=IpAddr>,
+    {
+        let addrs = addrs.into_iter().collect::<Vec<_>>();
+        self.map
+            .write()
+            .unwrap()
+            .insert((host.to_ascii_lowercase(), port), Arc::from(addrs));
+    }
+
+    /// Remove the override of `host` for `port`.
+    ///
+    /// Returns whether there was such an override.
+    pub fn remove(&self, host: &str, port: Option<u16>) -> bool {
+        self.map
+            .write()
+            .unwrap()
+            .remove(&(host.to_ascii_lowercase(), port))
+            .is_some()
+    }
+
+    /// Remove all overrides.
+    pub fn clear(&self) {
+        self.map.write().unwrap().clear();
+    }
+
+    fn get(&self, name: &Name) -> Option<Arc<[IpAddr]>> {
+        let map = self.map.read().unwrap();
+        let host = name.as_str().to_ascii_lowercase();
+        name.port()
+            .and_then(|port| map.get(&(host.clone(), Some(port))))
+            .or_else(|| map.get(&(host, None)))
+            .cloned()
+    }
+}
+
+impl fmt::Debug for Overrides {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.map.read() {
+            Ok(map) => f.debug_map()
+                .entries(map.iter().map(|(key, addrs)| (key, &**addrs)))
+                .finish(),
+            Err(_) => f.pad("Overrides"),
+        }
+    }
+}
+
+// ===== impl StaticFuture =====
+
+impl<R: Resolve> Future for StaticFuture<R> {
+    type Output = io::Result<StaticAddrs<R::Addrs>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let inner = match self.inner {
+            Inner::Static(ref mut addrs) => {
+                let addrs = addrs.take().expect("polled after complete");
+                Inner::Static((addrs, 0))
+            },
+            Inner::Resolved(ref mut future) => {
+                Inner::Resolved(ready!(Pin::new(future).poll(cx))?)
+            },
+        };
+        Poll::Ready(Ok(StaticAddrs {
+            inner,
+        }))
+    }
+}
+
+impl<R: Resolve> fmt::Debug for StaticFuture<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("StaticFuture")
+    }
+}
+
+// ===== impl StaticAddrs =====
+
+impl<A: Iterator<Item=IpAddr>> Iterator for StaticAddrs<A> {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner {
+            Inner::Static((ref addrs, ref mut next)) => {
+                let addr = addrs.get(*next).cloned();
+                *next += 1;
+                addr
+            },
+            Inner::Resolved(ref mut addrs) => addrs.next(),
+        }
+    }
+}
+
+impl<A> fmt::Debug for StaticAddrs<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("StaticAddrs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use futures_util::future;
+
+    use super::*;
+
+    /// Resolves every name to 10.0.0.1.
+    #[derive(Clone)]
+    struct Fallback;
+
+    impl Resolve for Fallback {
+        type Addrs = vec::IntoIter<IpAddr>;
+        type Future = future::Ready<io::Result<Self::Addrs>>;
+
+        fn resolve(&self, _name: Name) -> Self::Future {
+            future::ok(vec!["10.0.0.1".parse().unwrap()].into_iter())
+        }
+    }
+
+    async fn lookup(resolver: &StaticResolver<Fallback>, host: &str, port: u16) -> Vec<IpAddr> {
+        let name = Name::new(host.to_owned()).with_port(port);
+        resolver.resolve(name).await.unwrap().collect()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn overrides_and_falls_back() {
+        let resolver = StaticResolver::new(Fallback);
+        let overrides = resolver.overrides();
+        overrides.insert("hyper.rs", None, vec![ip("127.0.0.1"), ip("::1")]);
+        overrides.insert("hyper.rs", Some(443), vec![ip("127.0.0.2")]);
+
+        assert_eq!(lookup(&resolver, "HYPER.rs", 80).await, vec![ip("127.0.0.1"), ip("::1")]);
+        assert_eq!(lookup(&resolver, "hyper.rs", 443).await, vec![ip("127.0.0.2")]);
+        assert_eq!(lookup(&resolver, "example.com", 80).await, vec![ip("10.0.0.1")]);
+    }
+
+    #[tokio::test]
+    async fn changes_at_runtime() {
+        let resolver = StaticResolver::new(Fallback);
+        let clone = resolver.clone();
+
+        resolver.overrides().insert("hyper.rs", Some(80), vec![ip("127.0.0.1")]);
+        assert_eq!(lookup(&clone, "hyper.rs", 80).await, vec![ip("127.0.0.1")]);
+
+        assert!(resolver.overrides().remove("hyper.rs", Some(80)));
+        assert_eq!(lookup(&clone, "hyper.rs", 80).await, vec![ip("10.0.0.1")]);
+    }
+}
@@ -0,0 +1,891 @@
+//! An asynchronous DNS stub resolver.
+//!
+//! The nameservers are queried directly over UDP (and TCP for truncated
+//! answers), so no threads are blocked while resolving.
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fmt, vec};
+
+use futures_util::future::{self, poll_fn, Either};
+use pin_utils::pin_mut;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_net::tcp::TcpStream;
+use tokio_net::udp::UdpSocket;
+
+use crate::common::{Future, Pin, Poll, task};
+use super::{Name, Resolve};
+
+#[cfg(unix)]
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+#[cfg(unix)]
+const HOSTS_PATH: &str = "/etc/hosts";
+
+pub(super) const QTYPE_A: u16 = 1;
+pub(super) const QTYPE_AAAA: u16 = 28;
+pub(super) const QCLASS_IN: u16 = 1;
+
+/// The largest answer over UDP without EDNS.
+const MAX_UDP_SIZE: usize = 512;
+
+/// How many random source ports to try before letting the OS pick one.
+const BIND_ATTEMPTS: usize = 8;
+
+/// A resolver sending DNS queries to the nameservers itself, without
+/// blocking any threads.
+///
+/// Names are first looked up in the hosts file. Otherwise, the `A` and
+/// `AAAA` queries are sent in parallel over UDP, and retried over TCP if
+/// the answer was truncated. Each nameserver is tried in turn until one
+/// answers, up to the configured number of attempts.
+#[derive(Clone)]
+pub struct StubResolver {
+    config: Arc<Config>,
+}
+
+/// A future of the addresses resolved by a `StubResolver`.
+pub struct StubFuture {
+    inner: Pin<Box<dyn Future<Output = io::Result<StubAddrs>> + Send>>,
+}
+
+/// An iterator of the addresses resolved by a `StubResolver`.
+pub struct StubAddrs {
+    inner: vec::IntoIter<IpAddr>,
+    // The smallest TTL of the address records, if they came from DNS.
+    ttl: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+struct Config {
+    nameservers: Vec<SocketAddr>,
+    search: Vec<String>,
+    ndots: usize,
+    timeout: Duration,
+    attempts: usize,
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl StubResolver {
+    /// Construct a new `StubResolver` with the system configuration.
+    ///
+    /// On Unix, reads the nameservers, search domains and options from
+    /// `/etc/resolv.conf`, and static addresses from `/etc/hosts`. If a
+    /// file can't be read, or on other platforms, the defaults are used
+    /// instead: querying a nameserver on the loopback address, with no
+    /// static addresses.
+    pub fn new() -> StubResolver {
+        StubResolver {
+            config: Arc::new(Config::system()),
+        }
+    }
+
+    /// Construct a new `StubResolver` querying the given nameservers.
+    ///
+    /// The system configuration is not read.
+    pub fn with_nameservers<I>(nameservers: I) -> StubResolver
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
+        StubResolver {
+            config: Arc::new(Config {
+                nameservers: nameservers.into_iter().collect(),
+                ..Config::default()
+            }),
+        }
+    }
+
+    /// Set how long to wait for the answer of a nameserver.
+    ///
+    /// Default is 5 seconds, or the `timeout` option of `/etc/resolv.conf`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        Arc::make_mut(&mut self.config).timeout = timeout;
+    }
+
+    /// Set how many times every nameserver is tried, before giving up.
+    ///
+    /// Default is 2, or the `attempts` option of `/etc/resolv.conf`.
+    pub fn set_attempts(&mut self, attempts: usize) {
+        Arc::make_mut(&mut self.config).attempts = attempts;
+    }
+}
+
+impl Resolve for StubResolver {
+    type Addrs = StubAddrs;
+    type Future = StubFuture;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        StubFuture {
+            inner: Box::pin(lookup_ip(self.config.clone(), name.host)),
+        }
+    }
+
+    fn resolve_split(&self, name: Name) -> Option<(Self::Future, Self::Future)> {
+        let v6 = StubFuture {
+            inner: Box::pin(lookup(self.config.clone(), name.host.clone(), QTYPE_AAAA)),
+        };
+        let v4 = StubFuture {
+            inner: Box::pin(lookup(self.config.clone(), name.host, QTYPE_A)),
+        };
+        Some((v6, v4))
+    }
+
+    fn ttl(&self, addrs: &StubAddrs) -> Option<Duration> {
+        addrs.ttl
+    }
+}
+
+impl fmt::Debug for StubResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StubResolver")
+            .field("nameservers", &self.config.nameservers)
+            .finish()
+    }
+}
+
+impl Future for StubFuture {
+    type Output = io::Result<StubAddrs>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for StubFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("StubFuture")
+    }
+}
+
+impl Iterator for StubAddrs {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl fmt::Debug for StubAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("StubAddrs")
+    }
+}
+
+// ===== impl Config =====
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            nameservers: vec![
+                (Ipv4Addr::LOCALHOST, 53).into(),
+                (Ipv6Addr::LOCALHOST, 53).into(),
+            ],
+            search: Vec::new(),
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+            hosts: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    #[cfg(unix)]
+    fn system() -> Config {
+        let mut config = match fs::read_to_string(RESOLV_CONF_PATH) {
+            Ok(conf) => parse_resolv_conf(&conf),
+            Err(err) => {
+                debug!("error reading {}: {}", RESOLV_CONF_PATH, err);
+                Config::default()
+            }
+        };
+        match fs::read_to_string(HOSTS_PATH) {
+            Ok(hosts) => config.hosts = parse_hosts(&hosts),
+            Err(err) => debug!("error reading {}: {}", HOSTS_PATH, err),
+        }
+        config
+    }
+
+    #[cfg(not(unix))]
+    fn system() -> Config {
+        Config::default()
+    }
+
+    /// The names to query for `host`, applying the search domains.
+    fn candidates(&self, host: &str) -> Vec<String> {
+        if host.ends_with('.') {
+            return vec![host.trim_end_matches('.').to_owned()];
+        }
+
+        let is_qualified = host.matches('.').count() >= self.ndots;
+        let mut names = Vec::with_capacity(self.search.len() + 1);
+        if is_qualified {
+            names.push(host.to_owned());
+        }
+        for domain in &self.search {
+            names.push(format!("{}.{}", host, domain));
+        }
+        if !is_qualified {
+            names.push(host.to_owned());
+        }
+        names
+    }
+}
+
+#[cfg(any(unix, test))]
+fn parse_resolv_conf(conf: &str) -> Config {
+    let mut config = Config {
+        nameservers: Vec::new(),
+        ..Config::default()
+    };
+
+    for line in conf.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => {
+                match words.next().and_then(|ip| ip.parse::<IpAddr>().ok()) {
+                    Some(ip) => config.nameservers.push((ip, 53).into()),
+                    None => debug!("invalid nameserver in resolv.conf: {:?}", line),
+                }
+            },
+            Some("domain") => {
+                config.search = words
+                    .next()
+                    .map(|domain| vec![domain.trim_end_matches('.').to_owned()])
+                    .unwrap_or_default();
+            },
+            Some("search") => {
+                config.search = words
+                    .map(|domain| domain.trim_end_matches('.').to_owned())
+                    .collect();
+            },
+            Some("options") => {
+                for option in words {
+                    let mut parts = option.splitn(2, ':');
+                    let key = parts.next().unwrap_or("");
+                    let value = parts.next().and_then(|val| val.parse::<u64>().ok());
+                    // The limits are the same as glibc's.
+                    match (key, value) {
+                        ("ndots", Some(n)) => config.ndots = n.min(15) as usize,
+                        ("timeout", Some(n)) => config.timeout = Duration::from_secs(n.min(30).max(1)),
+                        ("attempts", Some(n)) => config.attempts = n.min(5).max(1) as usize,
+                        _ => trace!("ignoring resolv.conf option {:?}", option),
+                    }
+                }
+            },
+            // blank lines and comments, or unsupported directives
+            _ => (),
+        }
+    }
+
+    if config.nameservers.is_empty() {
+        config.nameservers = Config::default().nameservers;
+    }
+    config
+}
+
+#[cfg(any(unix, test))]
+fn parse_hosts(hosts: &str) -> HashMap<String, Vec<IpAddr>> {
+    let mut map = HashMap::new();
+    for line in hosts.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let ip = match words.next().and_then(|ip| ip.parse::<IpAddr>().ok()) {
+            Some(ip) => ip,
+            None => continue,
+        };
+        for name in words {
+            map.entry(name.to_ascii_lowercase())
+                .or_insert_with(Vec::new)
+                .push(ip);
+        }
+    }
+    map
+}
+
+// ===== lookups =====
+
+/// Look up both the IPv6 and IPv4 addresses of `host`.
+async fn lookup_ip(config: Arc<Config>, host: String) -> io::Result<StubAddrs> {
+    let v6 = lookup(config.clone(), host.clone(), QTYPE_AAAA);
+    let v4 = lookup(config, host, QTYPE_A);
+    let (addrs, ttl) = match future::join(v6, v4).await {
+        (Ok(v6), Ok(v4)) => {
+            let ttl = match (v6.ttl, v4.ttl) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            (v6.inner.chain(v4.inner).collect::<Vec<_>>(), ttl)
+        },
+        (Ok(addrs), Err(err)) | (Err(err), Ok(addrs)) => {
+            let ttl = addrs.ttl;
+            let addrs = addrs.inner.collect::<Vec<_>>();
+            if addrs.is_empty() {
+                return Err(err);
+            }
+            (addrs, ttl)
+        },
+        (Err(err), Err(_)) => return Err(err),
+    };
+
+    if addrs.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no addresses found"));
+    }
+    Ok(StubAddrs {
+        inner: addrs.into_iter(),
+        ttl,
+    })
+}
+
+/// Look up the addresses of one family of `host`.
+async fn lookup(config: Arc<Config>, host: String, qtype: u16) -> io::Result<StubAddrs> {
+    let host = host.to_ascii_lowercase();
+    if let Some(addrs) = config.hosts.get(host.trim_end_matches('.')) {
+        trace!("found {:?} in hosts file", host);
+        let addrs = addrs
+            .iter()
+            .filter(|addr| addr.is_ipv6() == (qtype == QTYPE_AAAA))
+            .cloned()
+            .collect::<Vec<_>>();
+        return Ok(StubAddrs {
+            inner: addrs.into_iter(),
+            ttl: None,
+        });
+    }
+
+    let mut last_err = None;
+    for name in config.candidates(&host) {
+        match query(&config, &name, qtype).await {
+            Ok(Some((addrs, ttl))) => {
+                return Ok(StubAddrs {
+                    inner: addrs.into_iter(),
+                    ttl,
+                });
+            },
+            Ok(None) => trace!("no such domain: {:?}", name),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no such host: {}", host))
+    }))
+}
+
+/// Query the nameservers for `name`.
+///
+/// Returns `None` if the name doesn't exist.
+async fn query(config: &Config, name: &str, qtype: u16) -> io::Result<Option<(Vec<IpAddr>, Option<Duration>)>> {
+    let mut last_err = None;
+    for _ in 0..config.attempts.max(1) {
+        for nameserver in &config.nameservers {
+            let id = random_u16();
+            let msg = encode_query(id, name, qtype)?;
+            match exchange(nameserver, &msg, config.timeout).await.and_then(|res| parse_response(&res, &msg)) {
+                Ok(Answer::Addrs(addrs, ttl)) => return Ok(Some((addrs, ttl))),
+                Ok(Answer::NoSuchDomain) => return Ok(None),
+                Ok(Answer::Failed(rcode)) => {
+                    debug!("nameserver {} failed query for {:?}, rcode={}", nameserver, name, rcode);
+                    last_err = Some(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("nameserver failure, rcode {}", rcode),
+                    ));
+                },
+                Err(err) => {
+                    debug!("error querying nameserver {} for {:?}: {}", nameserver, name, err);
+                    last_err = Some(err);
+                },
+            }
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::Other, "no nameservers configured")
+    }))
+}
+
+/// Send a query to a nameserver over UDP, retrying over TCP if the
+/// answer was truncated.
+async fn exchange(nameserver: &SocketAddr, msg: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let res = with_timeout(exchange_udp(nameserver, msg), timeout).await?;
+    if is_truncated(&res) {
+        trace!("truncated answer from {}, retrying over TCP", nameserver);
+        return with_timeout(exchange_tcp(nameserver, msg), timeout).await;
+    }
+    Ok(res)
+}
+
+async fn exchange_udp(nameserver: &SocketAddr, msg: &[u8]) -> io::Result<Vec<u8>> {
+    let local: IpAddr = if nameserver.is_ipv4() {
+        Ipv4Addr::UNSPECIFIED.into()
+    } else {
+        Ipv6Addr::UNSPECIFIED.into()
+    };
+    let mut sock = bind_random_port(local).await?;
+    sock.connect(nameserver).await?;
+    sock.send(msg).await?;
+
+    let mut buf = vec![0; MAX_UDP_SIZE];
+    loop {
+        let n = sock.recv(&mut buf).await?;
+        // Answers to other queries (or spoofed ones) are ignored.
+        if n >= 2 && buf[..2] == msg[..2] {
+            buf.truncate(n);
+            return Ok(buf);
+        }
+        trace!("ignoring answer with mismatched id from {}", nameserver);
+    }
+}
+
+/// Bind to a random source port, so that answers are harder to spoof.
+async fn bind_random_port(ip: IpAddr) -> io::Result<UdpSocket> {
+    for _ in 0..BIND_ATTEMPTS {
+        // Above the well-known ports.
+        let port = 1024 + random_u16() % (u16::max_value() - 1023);
+        match UdpSocket::bind(&SocketAddr::new(ip, port)).await {
+            Ok(sock) => return Ok(sock),
+            Err(ref err) if err.kind() == io::ErrorKind::AddrInUse => {
+                trace!("source port {} in use", port);
+            },
+            Err(err) => return Err(err),
+        }
+    }
+    UdpSocket::bind(&SocketAddr::new(ip, 0)).await
+}
+
+async fn exchange_tcp(nameserver: &SocketAddr, msg: &[u8]) -> io::Result<Vec<u8>> {
+    let mut tcp = TcpStream::connect(nameserver).await?;
+
+    let mut out = Vec::with_capacity(msg.len() + 2);
+    out.extend_from_slice(&(msg.len() as u16).to_be_bytes());
+    out.extend_from_slice(msg);
+    let mut written = 0;
+    while written < out.len() {
+        let n = poll_fn(|cx| Pin::new(&mut tcp).poll_write(cx, &out[written..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        written += n;
+    }
+
+    let mut len = [0; 2];
+    read_exact(&mut tcp, &mut len).await?;
+    let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+    read_exact(&mut tcp, &mut buf).await?;
+    Ok(buf)
+}
+
+async fn read_exact(tcp: &mut TcpStream, buf: &mut [u8]) -> io::Result<()> {
+    let mut read = 0;
+    while read < buf.len() {
+        let n = poll_fn(|cx| Pin::new(&mut *tcp).poll_read(cx, &mut buf[read..])).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        read += n;
+    }
+    Ok(())
+}
+
+async fn with_timeout<F, T>(fut: F, timeout: Duration) -> io::Result<T>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    let delay = tokio_timer::delay(Instant::now() + timeout);
+    pin_mut!(fut);
+    pin_mut!(delay);
+    match future::select(fut, delay).await {
+        Either::Left((res, _)) => res,
+        Either::Right(((), _)) => Err(io::Error::new(io::ErrorKind::TimedOut, "DNS query timed out")),
+    }
+}
+
+/// A random number for the query IDs and source ports.
+///
+/// Each thread seeds its state from the random keys of a `RandomState`,
+/// which the OS provides.
+fn random_u16() -> u16 {
+    use std::cell::Cell;
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    thread_local!(static STATE: Cell<u64> = Cell::new({
+        let seed = RandomState::new().build_hasher().finish();
+        // xorshift gets stuck on 0
+        seed | 1
+    }));
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x >> 48) as u16
+    })
+}
+
+// ===== wire format =====
+
+#[derive(Debug)]
+pub(super) enum Answer {
+    // The addresses, and the smallest TTL of their records.
+    Addrs(Vec<IpAddr>, Option<Duration>),
+    NoSuchDomain,
+    Failed(u8),
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(super) fn encode_query(id: u16, name: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(12 + name.len() + 2 + 4);
+    msg.extend_from_slice(&id.to_be_bytes());
+    // flags: recursion desired
+    msg.extend_from_slice(&[0x01, 0x00]);
+    // qdcount, ancount, nscount, arcount
+    msg.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid domain name"));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    if msg.len() - 12 > 255 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "domain name too long"));
+    }
+
+    msg.extend_from_slice(&qtype.to_be_bytes());
+    msg.extend_from_slice(&QCLASS_IN.to_be_bytes());
+    Ok(msg)
+}
+
+fn is_truncated(msg: &[u8]) -> bool {
+    msg.len() > 2 && msg[2] & 0x02 != 0
+}
+
+/// Parse the answer to `query`, as made by `encode_query`.
+pub(super) fn parse_response(msg: &[u8], query: &[u8]) -> io::Result<Answer> {
+    if msg.len() < 12 {
+        return Err(invalid_data("DNS answer too short"));
+    }
+    if msg[..2] != query[..2] || msg[2] & 0x80 == 0 {
+        return Err(invalid_data("DNS answer doesn't match query"));
+    }
+    // The question must be repeated, so an answer to another name, or of
+    // another type, isn't mistaken for this one. Nameservers may change
+    // the case of the name.
+    let question = &query[12..];
+    let (name, type_class) = question.split_at(question.len() - 4);
+    let matches = match msg.get(12..12 + question.len()) {
+        Some(echoed) => {
+            read_u16(msg, 4)? == 1
+                && echoed[..name.len()].eq_ignore_ascii_case(name)
+                && echoed[name.len()..] == *type_class
+        },
+        None => false,
+    };
+    if !matches {
+        return Err(invalid_data("DNS answer doesn't match question"));
+    }
+    match msg[3] & 0x0f {
+        0 => (),
+        3 => return Ok(Answer::NoSuchDomain),
+        rcode => return Ok(Answer::Failed(rcode)),
+    }
+
+    let qtype = read_u16(query, query.len() - 4)?;
+    let ancount = read_u16(msg, 6)?;
+    let mut pos = 12 + question.len();
+
+    let mut addrs = Vec::new();
+    let mut min_ttl = None;
+    for _ in 0..ancount {
+        pos = skip_name(msg, pos)?;
+        let rtype = read_u16(msg, pos)?;
+        let class = read_u16(msg, pos + 2)?;
+        let ttl = (read_u16(msg, pos + 4)? as u32) << 16 | read_u16(msg, pos + 6)? as u32;
+        let len = read_u16(msg, pos + 8)? as usize;
+        pos += 10;
+        let data = msg.get(pos..pos + len).ok_or_else(|| invalid_data("DNS record too short"))?;
+        pos += len;
+
+        // Other records, like the CNAMEs leading to the addresses, are skipped.
+        if rtype != qtype || class != QCLASS_IN {
+            continue;
+        }
+        match (rtype, len) {
+            (QTYPE_A, 4) => {
+                addrs.push(Ipv4Addr::new(data[0], data[1], data[2], data[3]).into());
+            },
+            (QTYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                addrs.push(Ipv6Addr::from(octets).into());
+            },
+            _ => return Err(invalid_data("invalid DNS address record")),
+        }
+        min_ttl = Some(min_ttl.map_or(ttl, |min: u32| min.min(ttl)));
+    }
+    Ok(Answer::Addrs(addrs, min_ttl.map(|ttl| Duration::from_secs(ttl as u64))))
+}
+
+pub(super) fn read_u16(msg: &[u8], pos: usize) -> io::Result<u16> {
+    msg.get(pos..pos + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| invalid_data("DNS message too short"))
+}
+
+/// Returns the position after the (possibly compressed) name at `pos`.
+pub(super) fn skip_name(msg: &[u8], mut pos: usize) -> io::Result<usize> {
+    loop {
+        let len = *msg.get(pos).ok_or_else(|| invalid_data("DNS name too short"))?;
+        match len {
+            0 => return Ok(pos + 1),
+            // a pointer ends the name
+            len if len & 0xc0 == 0xc0 => return Ok(pos + 2),
+            len if len & 0xc0 == 0 => pos += 1 + len as usize,
+            _ => return Err(invalid_data("invalid DNS name label")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, UdpSocket as StdUdpSocket};
+    use std::io::{Read, Write};
+    use std::thread;
+
+    use super::*;
+
+    /// Answer a query with one address, or set the truncated flag.
+    fn answer(query: &[u8], truncated: bool) -> Vec<u8> {
+        let end = skip_name(query, 12).unwrap() + 4;
+        let qtype = read_u16(query, end - 4).unwrap();
+        let mut res = query[..end].to_vec();
+        // response, recursion available
+        res[2] |= 0x80;
+        res[3] |= 0x80;
+        if truncated {
+            res[2] |= 0x02;
+            return res;
+        }
+
+        // ancount
+        res[7] = 1;
+        // a pointer to the name in the question
+        res.extend_from_slice(&[0xc0, 12]);
+        res.extend_from_slice(&qtype.to_be_bytes());
+        res.extend_from_slice(&QCLASS_IN.to_be_bytes());
+        res.extend_from_slice(&60u32.to_be_bytes());
+        if qtype == QTYPE_A {
+            res.extend_from_slice(&[0, 4, 10, 0, 0, 1]);
+        } else {
+            res.extend_from_slice(&[0, 16]);
+            res.extend_from_slice(&"fd00::1".parse::<Ipv6Addr>().unwrap().octets());
+        }
+        res
+    }
+
+    /// A nameserver on localhost answering `n` queries over UDP.
+    fn udp_nameserver(n: usize, truncated: bool) -> (StdUdpSocket, SocketAddr) {
+        let sock = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = sock.local_addr().unwrap();
+        let server = sock.try_clone().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            for _ in 0..n {
+                let (len, from) = server.recv_from(&mut buf).unwrap();
+                server.send_to(&answer(&buf[..len], truncated), from).unwrap();
+            }
+        });
+        (sock, addr)
+    }
+
+    fn resolver(nameservers: Vec<SocketAddr>) -> StubResolver {
+        let mut resolver = StubResolver::with_nameservers(nameservers);
+        resolver.set_timeout(Duration::from_millis(200));
+        resolver.set_attempts(1);
+        resolver
+    }
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let config = parse_resolv_conf("\
+            # a comment\n\
+            nameserver 10.0.0.53\n\
+            nameserver 2001:db8::53\n\
+            nameserver not-an-ip\n\
+            search example.com corp.example.com.\n\
+            options ndots:2 timeout:3 attempts:9 rotate\n\
+        ");
+
+        assert_eq!(config.nameservers, vec![
+            "10.0.0.53:53".parse::<SocketAddr>().unwrap(),
+            "[2001:db8::53]:53".parse::<SocketAddr>().unwrap(),
+        ]);
+        assert_eq!(config.search, vec!["example.com", "corp.example.com"]);
+        assert_eq!(config.ndots, 2);
+        assert_eq!(config.timeout, Duration::from_secs(3));
+        assert_eq!(config.attempts, 5);
+
+        let config = parse_resolv_conf("");
+        assert_eq!(config.nameservers, Config::default().nameservers);
+    }
+
+    #[test]
+    fn test_parse_hosts() {
+        let hosts = parse_hosts("\
+            127.0.0.1 localhost\n\
+            ::1 localhost ip6-localhost # loopback\n\
+            # 10.0.0.1 commented\n\
+            10.0.0.2 Db.Local\n\
+        ");
+
+        assert_eq!(hosts["localhost"], vec![
+            "127.0.0.1".parse::<IpAddr>().unwrap(),
+            "::1".parse::<IpAddr>().unwrap(),
+        ]);
+        assert_eq!(hosts["db.local"], vec!["10.0.0.2".parse::<IpAddr>().unwrap()]);
+        assert!(!hosts.contains_key("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_candidates() {
+        let config = Config {
+            search: vec!["example.com".to_owned()],
+            ..Config::default()
+        };
+
+        assert_eq!(config.candidates("db"), vec!["db.example.com", "db"]);
+        assert_eq!(config.candidates("hyper.rs"), vec!["hyper.rs", "hyper.rs.example.com"]);
+        assert_eq!(config.candidates("db."), vec!["db"]);
+    }
+
+    #[test]
+    fn test_encode_and_parse() {
+        let query = encode_query(0x1234, "hyper.rs", QTYPE_A).unwrap();
+        match parse_response(&answer(&query, false), &query).unwrap() {
+            Answer::Addrs(addrs, ttl) => {
+                assert_eq!(addrs, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+                assert_eq!(ttl, Some(Duration::from_secs(60)));
+            },
+            _ => panic!("expected addresses"),
+        }
+
+        // mismatched id
+        let other = encode_query(0x4321, "hyper.rs", QTYPE_A).unwrap();
+        parse_response(&answer(&other, false), &query).unwrap_err();
+
+        let mut nxdomain = answer(&query, false);
+        nxdomain[3] |= 3;
+        match parse_response(&nxdomain, &query).unwrap() {
+            Answer::NoSuchDomain => (),
+            _ => panic!("expected NXDOMAIN"),
+        }
+
+        encode_query(1, "empty..label", QTYPE_A).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_checks_question() {
+        let query = encode_query(0x1234, "hyper.rs", QTYPE_A).unwrap();
+
+        // the case of the name may change
+        let upper = encode_query(0x1234, "HYPER.rs", QTYPE_A).unwrap();
+        parse_response(&answer(&upper, false), &query).unwrap();
+
+        // another name
+        let other = encode_query(0x1234, "tokio.rs", QTYPE_A).unwrap();
+        parse_response(&answer(&other, false), &query).unwrap_err();
+
+        // another type
+        let other = encode_query(0x1234, "hyper.rs", QTYPE_AAAA).unwrap();
+        parse_response(&answer(&other, false), &query).unwrap_err();
+
+        // no question
+        let mut res = answer(&query, false);
+        res[5] = 0;
+        parse_response(&res, &query).unwrap_err();
+        parse_response(&res[..12], &query).unwrap_err();
+    }
+
+    #[test]
+    fn test_random_u16() {
+        let ids = (0..8).map(|_| random_u16()).collect::<Vec<_>>();
+        assert!(ids.iter().any(|&id| id != ids[0]), "ids all {}", ids[0]);
+    }
+
+    #[tokio::test]
+    async fn resolve_a_and_aaaa() {
+        let (_server, addr) = udp_nameserver(2, false);
+
+        let mut addrs = resolver(vec![addr])
+            .resolve("hyper.rs".parse().unwrap())
+            .await
+            .expect("resolve")
+            .collect::<Vec<_>>();
+        addrs.sort();
+
+        assert_eq!(addrs, vec![
+            "10.0.0.1".parse::<IpAddr>().unwrap(),
+            "fd00::1".parse::<IpAddr>().unwrap(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn resolve_next_nameserver_after_timeout() {
+        // Never answers.
+        let silent = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        let (_server, addr) = udp_nameserver(1, false);
+
+        let (_, v4) = resolver(vec![silent.local_addr().unwrap(), addr])
+            .resolve_split("hyper.rs".parse().unwrap())
+            .unwrap();
+        let addrs = v4.await.expect("resolve").collect::<Vec<_>>();
+
+        assert_eq!(addrs, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn resolve_truncated_over_tcp() {
+        let (_server, addr) = udp_nameserver(1, true);
+        let listener = TcpListener::bind(addr).unwrap();
+        thread::spawn(move || {
+            let (mut sock, _) = listener.accept().unwrap();
+            let mut len = [0; 2];
+            sock.read_exact(&mut len).unwrap();
+            let mut query = vec![0; u16::from_be_bytes(len) as usize];
+            sock.read_exact(&mut query).unwrap();
+            let res = answer(&query, false);
+            sock.write_all(&(res.len() as u16).to_be_bytes()).unwrap();
+            sock.write_all(&res).unwrap();
+        });
+
+        let (_, v4) = resolver(vec![addr])
+            .resolve_split("hyper.rs".parse().unwrap())
+            .unwrap();
+        let addrs = v4.await.expect("resolve").collect::<Vec<_>>();
+
+        assert_eq!(addrs, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn resolve_timeout() {
+        let silent = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let err = resolver(vec![silent.local_addr().unwrap()])
+            .resolve("hyper.rs".parse().unwrap())
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -1,15 +1,16 @@
-use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt;
 use std::error::Error as StdError;
 use std::io;
 use std::mem;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use http::uri::Scheme;
 use net2::TcpBuilder;
-use tokio_reactor::Handle;
-use tokio_tcp::{TcpStream/*, ConnectFuture*/};
+use tokio_net::driver::Handle;
+use tokio_net::tcp::TcpStream;
 use tokio_timer::Delay;
 
 use crate::common::{Future, Pin, Poll, task};
@@ -19,6 +20,10 @@ use super::dns::{self, GaiResolver, Resolve, TokioThreadpoolGaiResolver};
 // TODO: unbox me?
 type ConnectFuture = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>;
 
+/// How long to wait for the `AAAA` answer after the `A` answer, before
+/// starting to connect (RFC 8305 section 3).
+const RESOLUTION_DELAY: Duration = Duration::from_millis(50);
+
 /// A connector for the `http` scheme.
 ///
 /// Performs DNS resolution in a thread pool, and then connects over TCP.
@@ -29,16 +34,31 @@ type ConnectFuture = Pin<Box<dyn Future<Output = io::Result<TcpStream>> + Send>>
 /// transport information such as the remote socket address used.
 #[derive(Clone)]
 pub struct HttpConnector<R = GaiResolver> {
+    connect_timeout: Option<Duration>,
     enforce_http: bool,
     handle: Option<Handle>,
     happy_eyeballs_timeout: Option<Duration>,
+    ip_preference: Option<IpPreference>,
     keep_alive_timeout: Option<Duration>,
-    local_address: Option<IpAddr>,
     nodelay: bool,
     resolver: R,
-    reuse_address: bool,
     send_buffer_size: Option<usize>,
     recv_buffer_size: Option<usize>,
+    socket: Arc<SocketOptions>,
+}
+
+/// Options applied to each socket before connecting.
+#[derive(Clone, Debug, Default)]
+struct SocketOptions {
+    fast_open: bool,
+    interface: Option<String>,
+    keepalive_interval: Option<Duration>,
+    keepalive_retries: Option<u32>,
+    local_ipv4: Option<Ipv4Addr>,
+    local_ipv6: Option<Ipv6Addr>,
+    reuse_address: bool,
+    tos: Option<u8>,
+    user_timeout: Option<Duration>,
 }
 
 /// Extra information about the transport when an HttpConnector is used.
@@ -72,9 +92,21 @@ pub struct HttpConnector<R = GaiResolver> {
 /// connector to see what "extra" information it might provide to responses.
 #[derive(Clone, Debug)]
 pub struct HttpInfo {
+    local_addr: SocketAddr,
     remote_addr: SocketAddr,
 }
 
+/// The address family an `HttpConnector` tries first.
+///
+/// See [`HttpConnector::set_ip_preference`](HttpConnector::set_ip_preference).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpPreference {
+    /// Try IPv4 addresses first.
+    Ipv4,
+    /// Try IPv6 addresses first.
+    Ipv6,
+}
+
 impl HttpConnector {
     /// Construct a new HttpConnector.
     ///
@@ -101,16 +133,17 @@ impl<R> HttpConnector<R> {
     /// Takes a `Resolve` to handle DNS lookups.
     pub fn new_with_resolver(resolver: R) -> HttpConnector<R> {
         HttpConnector {
+            connect_timeout: None,
             enforce_http: true,
             handle: None,
-            happy_eyeballs_timeout: Some(Duration::from_millis(300)),
+            happy_eyeballs_timeout: Some(Duration::from_millis(250)),
+            ip_preference: None,
             keep_alive_timeout: None,
-            local_address: None,
             nodelay: false,
             resolver,
-            reuse_address: false,
             send_buffer_size: None,
             recv_buffer_size: None,
+            socket: Arc::new(SocketOptions::default()),
         }
     }
 
@@ -160,39 +193,170 @@ impl<R> HttpConnector<R> {
         self.recv_buffer_size = size;
     }
 
+    /// Set the interval between `SO_KEEPALIVE` probes (`TCP_KEEPINTVL`).
+    ///
+    /// Only has an effect if keepalive is enabled with
+    /// [`set_keepalive`](HttpConnector::set_keepalive). Only supported on
+    /// Linux and Android, connecting fails on other platforms.
+    ///
+    /// Default is `None`, using the system default.
+    #[inline]
+    pub fn set_keepalive_interval(&mut self, interval: Option<Duration>) {
+        Arc::make_mut(&mut self.socket).keepalive_interval = interval;
+    }
+
+    /// Set how many `SO_KEEPALIVE` probes may go unanswered before the
+    /// connection is dropped (`TCP_KEEPCNT`).
+    ///
+    /// Only supported on Linux and Android, connecting fails on other
+    /// platforms.
+    ///
+    /// Default is `None`, using the system default.
+    #[inline]
+    pub fn set_keepalive_retries(&mut self, retries: Option<u32>) {
+        Arc::make_mut(&mut self.socket).keepalive_retries = retries;
+    }
+
     /// Set that all sockets are bound to the configured address before connection.
     ///
+    /// The address is only used to connect to addresses of the same family.
+    /// To bind both IPv4 and IPv6 connections, use
+    /// [`set_local_addresses`](HttpConnector::set_local_addresses).
+    ///
     /// If `None`, the sockets will not be bound.
     ///
+    /// A [`LocalAddress`](super::LocalAddress) requested for a `Request`
+    /// replaces the configured address of its family.
+    ///
     /// Default is `None`.
     #[inline]
     pub fn set_local_address(&mut self, addr: Option<IpAddr>) {
-        self.local_address = addr;
+        let (ipv4, ipv6) = match addr {
+            Some(IpAddr::V4(ipv4)) => (Some(ipv4), None),
+            Some(IpAddr::V6(ipv6)) => (None, Some(ipv6)),
+            None => (None, None),
+        };
+        let socket = Arc::make_mut(&mut self.socket);
+        socket.local_ipv4 = ipv4;
+        socket.local_ipv6 = ipv6;
+    }
+
+    /// Set that all sockets are bound to the address of their family before
+    /// connection.
+    ///
+    /// This allows binding dual-stack connections, where the address
+    /// family of the remote address isn't known in advance.
+    ///
+    /// Default is `None` for both.
+    #[inline]
+    pub fn set_local_addresses(&mut self, ipv4: Option<Ipv4Addr>, ipv6: Option<Ipv6Addr>) {
+        let socket = Arc::make_mut(&mut self.socket);
+        socket.local_ipv4 = ipv4;
+        socket.local_ipv6 = ipv6;
+    }
+
+    /// Set that all sockets are bound to the network interface with this
+    /// name (`SO_BINDTODEVICE`), such as `"eth0"`.
+    ///
+    /// Binding usually requires the `CAP_NET_RAW` capability. Only supported
+    /// on Linux and Android, connecting fails on other platforms.
+    ///
+    /// Default is `None`.
+    #[inline]
+    pub fn set_interface(&mut self, interface: Option<String>) {
+        Arc::make_mut(&mut self.socket).interface = interface;
     }
 
-    /// Set timeout for [RFC 6555 (Happy Eyeballs)][RFC 6555] algorithm.
+    /// Set the type of service of all sockets (`IP_TOS`, or `IPV6_TCLASS`
+    /// for IPv6), such as for DSCP marking.
     ///
-    /// If hostname resolves to both IPv4 and IPv6 addresses and connection
-    /// cannot be established using preferred address family before timeout
-    /// elapses, then connector will in parallel attempt connection using other
-    /// address family.
+    /// The DSCP value is the upper 6 bits, so `AF41` is `34 << 2`. Only
+    /// supported on Linux and Android, connecting fails on other platforms.
     ///
-    /// If `None`, parallel connection attempts are disabled.
+    /// Default is `None`.
+    #[inline]
+    pub fn set_tos(&mut self, tos: Option<u8>) {
+        Arc::make_mut(&mut self.socket).tos = tos;
+    }
+
+    /// Set how long sent data may remain unacknowledged before the
+    /// connection is dropped (`TCP_USER_TIMEOUT`).
     ///
-    /// Default is 300 milliseconds.
+    /// Only supported on Linux and Android, connecting fails on other
+    /// platforms.
     ///
-    /// [RFC 6555]: https://tools.ietf.org/html/rfc6555
+    /// Default is `None`, using the system default.
+    #[inline]
+    pub fn set_user_timeout(&mut self, timeout: Option<Duration>) {
+        Arc::make_mut(&mut self.socket).user_timeout = timeout;
+    }
+
+    /// Set whether TCP Fast Open is used (`TCP_FASTOPEN_CONNECT`).
+    ///
+    /// With a cookie from a previous connection to the server, the first
+    /// data is sent along with the `SYN`. Only supported on Linux 4.11 and
+    /// later, connecting fails on other platforms.
+    ///
+    /// Default is `false`.
+    #[inline]
+    pub fn set_tcp_fast_open(&mut self, enabled: bool) {
+        Arc::make_mut(&mut self.socket).fast_open = enabled;
+    }
+
+    /// Set how long connecting may take, once the addresses are resolved.
+    ///
+    /// The time is split across the resolved addresses, so each one gets a
+    /// fair attempt, instead of one unreachable address using it all up.
+    /// If connecting to every address fails, the error lists how each
+    /// attempt failed.
+    ///
+    /// If `None`, there is no timeout besides the one of the system, which
+    /// may take minutes for each address.
+    ///
+    /// Default is `None`.
+    #[inline]
+    pub fn set_connect_timeout(&mut self, dur: Option<Duration>) {
+        self.connect_timeout = dur;
+    }
+
+    /// Set the connection attempt delay of the [RFC 8305 (Happy Eyeballs v2)][RFC 8305]
+    /// algorithm.
+    ///
+    /// The resolved addresses are tried in turn, alternating between IPv6
+    /// and IPv4. If a connection attempt hasn't succeeded before this delay
+    /// elapses, the next address is tried in parallel, without canceling
+    /// the attempts already in progress.
+    ///
+    /// If `None`, parallel connection attempts are disabled, and each
+    /// address is only tried after the previous one failed.
+    ///
+    /// Default is 250 milliseconds.
+    ///
+    /// [RFC 8305]: https://tools.ietf.org/html/rfc8305
     #[inline]
     pub fn set_happy_eyeballs_timeout(&mut self, dur: Option<Duration>) {
         self.happy_eyeballs_timeout = dur;
     }
 
+    /// Set which address family is tried first when connecting.
+    ///
+    /// If `None`, the addresses are sorted as in [RFC 6724][RFC 6724], which
+    /// usually tries IPv6 first.
+    ///
+    /// Default is `None`.
+    ///
+    /// [RFC 6724]: https://tools.ietf.org/html/rfc6724
+    #[inline]
+    pub fn set_ip_preference(&mut self, preference: Option<IpPreference>) {
+        self.ip_preference = preference;
+    }
+
     /// Set that all socket have `SO_REUSEADDR` set to the supplied value `reuse_address`.
     ///
     /// Default is `false`.
     #[inline]
     pub fn set_reuse_address(&mut self, reuse_address: bool) -> &mut Self {
-        self.reuse_address = reuse_address;
+        Arc::make_mut(&mut self.socket).reuse_address = reuse_address;
         self
     }
 }
@@ -239,21 +403,66 @@ where
             None => if dst.uri.scheme_part() == Some(&Scheme::HTTPS) { 443 } else { 80 },
         };
 
+        let socket = match dst.local_address() {
+            Some(ip) => {
+                let mut socket = (*self.socket).clone();
+                match ip {
+                    IpAddr::V4(v4) => socket.local_ipv4 = Some(v4),
+                    IpAddr::V6(v6) => socket.local_ipv6 = Some(v6),
+                }
+                Arc::new(socket)
+            },
+            None => self.socket.clone(),
+        };
+
+        let state = match socket.check_supported() {
+            Ok(()) => State::Lazy(self.resolver.clone(), host.into()),
+            Err(err) => State::Error(Some(err)),
+        };
+
         HttpConnecting {
-            state: State::Lazy(self.resolver.clone(), host.into(), self.local_address),
+            state,
+            connect_timeout: self.connect_timeout,
             handle: self.handle.clone(),
             happy_eyeballs_timeout: self.happy_eyeballs_timeout,
+            ip_preference: self.ip_preference,
             keep_alive_timeout: self.keep_alive_timeout,
             nodelay: self.nodelay,
             port,
-            reuse_address: self.reuse_address,
             send_buffer_size: self.send_buffer_size,
             recv_buffer_size: self.recv_buffer_size,
+            socket,
         }
     }
 }
 
+/// An `HttpConnector` is also a tower `Service`, so that tower middleware
+/// can wrap it, and be used as a connector with a
+/// [`ServiceConnector`](super::ServiceConnector).
+impl<R> tower_service::Service<Destination> for HttpConnector<R>
+where
+    R: Resolve + Clone + Send + Sync,
+    R::Future: Send,
+{
+    type Response = (TcpStream, Connected);
+    type Error = io::Error;
+    type Future = HttpConnecting<R>;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Destination) -> Self::Future {
+        Connect::connect(self, dst)
+    }
+}
+
 impl HttpInfo {
+    /// Get the local address of the transport used.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
     /// Get the remote address of the transport used.
     pub fn remote_addr(&self) -> SocketAddr {
         self.remote_addr
@@ -264,14 +473,16 @@ impl HttpInfo {
 fn invalid_url<R: Resolve>(err: InvalidUrl, handle: &Option<Handle>) -> HttpConnecting<R> {
     HttpConnecting {
         state: State::Error(Some(io::Error::new(io::ErrorKind::InvalidInput, err))),
+        connect_timeout: None,
         handle: handle.clone(),
         keep_alive_timeout: None,
         nodelay: false,
         port: 0,
         happy_eyeballs_timeout: None,
-        reuse_address: false,
+        ip_preference: None,
         send_buffer_size: None,
         recv_buffer_size: None,
+        socket: Arc::new(SocketOptions::default()),
     }
 }
 
@@ -301,24 +512,30 @@ impl StdError for InvalidUrl {
 #[must_use = "futures do nothing unless polled"]
 pub struct HttpConnecting<R: Resolve = GaiResolver> {
     state: State<R>,
+    connect_timeout: Option<Duration>,
     handle: Option<Handle>,
     happy_eyeballs_timeout: Option<Duration>,
+    ip_preference: Option<IpPreference>,
     keep_alive_timeout: Option<Duration>,
     nodelay: bool,
     port: u16,
-    reuse_address: bool,
     send_buffer_size: Option<usize>,
     recv_buffer_size: Option<usize>,
+    socket: Arc<SocketOptions>,
 }
 
 enum State<R: Resolve> {
-    Lazy(R, String, Option<IpAddr>),
-    Resolving(R::Future, Option<IpAddr>),
-    Connecting(ConnectingTcp),
+    Lazy(R, String),
+    // Falls back to resolving the name if the service has no addresses.
+    ResolvingService(dns::ServiceFuture, R, dns::Name),
+    Resolving(R::Future),
+    ResolvingSplit(ResolvingSplit<R>),
+    // The lookup of the other family may still be in progress.
+    Connecting(ConnectingTcp, Option<R::Future>),
     Error(Option<io::Error>),
 }
 
-impl<R: Resolve> Future for HttpConnecting<R>
+impl<R: Resolve + Clone> Future for HttpConnecting<R>
 where
     R::Future: Unpin,
 {
@@ -329,18 +546,33 @@ where
         loop {
             let state;
             match me.state {
-                State::Lazy(ref resolver, ref mut host, local_addr) => {
+                State::Lazy(ref resolver, ref mut host) => {
                     // If the host is already an IP addr (v4 or v6),
                     // skip resolving the dns and start connecting right away.
                     if let Some(addrs) = dns::IpAddrs::try_parse(host, me.port) {
                         state = State::Connecting(ConnectingTcp::new(
-                            local_addr, addrs, me.happy_eyeballs_timeout, me.reuse_address));
+                            addrs, me.ip_preference, me.happy_eyeballs_timeout, me.connect_timeout, me.socket.clone()), None);
+                    } else {
+                        let name = dns::Name::new(mem::replace(host, String::new()))
+                            .with_port(me.port);
+                        state = match resolver.resolve_service(name.clone()) {
+                            Some(future) => State::ResolvingService(future, resolver.clone(), name),
+                            None => resolve(resolver, name),
+                        };
+                    }
+                },
+                State::ResolvingService(ref mut future, ref resolver, ref name) => {
+                    let services = ready!(Pin::new(future).poll(cx))?;
+                    if services.is_empty() {
+                        trace!("no service addresses for {:?}, resolving name", name);
+                        state = resolve(resolver, name.clone());
                     } else {
-                        let name = dns::Name::new(mem::replace(host, String::new()));
-                        state = State::Resolving(resolver.resolve(name), local_addr);
+                        let addrs = dns::sort_services(services, dns::random_up_to);
+                        state = State::Connecting(ConnectingTcp::ordered(
+                            addrs, me.happy_eyeballs_timeout, me.connect_timeout, me.socket.clone()), None);
                     }
                 },
-                State::Resolving(ref mut future, local_addr) => {
+                State::Resolving(ref mut future) => {
                     let addrs =  ready!(Pin::new(future).poll(cx))?;
                     let port = me.port;
                     let addrs = addrs
@@ -348,10 +580,38 @@ where
                         .collect();
                     let addrs = dns::IpAddrs::new(addrs);
                     state = State::Connecting(ConnectingTcp::new(
-                        local_addr, addrs, me.happy_eyeballs_timeout, me.reuse_address));
+                        addrs, me.ip_preference, me.happy_eyeballs_timeout, me.connect_timeout, me.socket.clone()), None);
                 },
-                State::Connecting(ref mut c) => {
-                    let sock = ready!(c.poll(cx, &me.handle))?;
+                State::ResolvingSplit(ref mut resolving) => {
+                    let (addrs, pending) = ready!(resolving.poll(cx))?;
+                    let port = me.port;
+                    let addrs = addrs
+                        .into_iter()
+                        .map(|addr| SocketAddr::new(addr, port))
+                        .collect();
+                    let addrs = dns::IpAddrs::new(addrs);
+                    state = State::Connecting(ConnectingTcp::new(
+                        addrs, me.ip_preference, me.happy_eyeballs_timeout, me.connect_timeout, me.socket.clone()), pending);
+                },
+                State::Connecting(ref mut c, ref mut pending) => {
+                    let resolved = match *pending {
+                        Some(ref mut future) => Pin::new(future).poll(cx),
+                        None => Poll::Pending,
+                    };
+                    if let Poll::Ready(res) = resolved {
+                        *pending = None;
+                        match res {
+                            Ok(addrs) => {
+                                let port = me.port;
+                                c.add_addrs(addrs
+                                    .map(|addr| SocketAddr::new(addr, port))
+                                    .collect());
+                            },
+                            Err(err) => trace!("resolve error for other address family: {}", err),
+                        }
+                    }
+
+                    let sock = ready!(c.poll(cx, &me.handle, pending.is_some()))?;
 
                     if let Some(dur) = me.keep_alive_timeout {
                         sock.set_keepalive(Some(dur))?;
@@ -368,6 +628,7 @@ where
                     sock.set_nodelay(me.nodelay)?;
 
                     let extra = HttpInfo {
+                        local_addr: sock.local_addr()?,
                         remote_addr: sock.peer_addr()?,
                     };
                     let connected = Connected::new()
@@ -382,128 +643,336 @@ where
     }
 }
 
+fn resolve<R: Resolve>(resolver: &R, name: dns::Name) -> State<R> {
+    match resolver.resolve_split(name.clone()) {
+        Some((v6, v4)) => State::ResolvingSplit(ResolvingSplit::new(v6, v4)),
+        None => State::Resolving(resolver.resolve(name)),
+    }
+}
+
 impl<R: Resolve + fmt::Debug> fmt::Debug for HttpConnecting<R> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.pad("HttpConnecting")
     }
 }
 
-struct ConnectingTcp {
-    local_addr: Option<IpAddr>,
-    preferred: ConnectingTcpRemote,
-    fallback: Option<ConnectingTcpFallback>,
-    reuse_address: bool,
+/// Waits for the `AAAA` and `A` lookups of a `Resolve::resolve_split`.
+///
+/// Connecting starts as soon as IPv6 addresses are known, or once the
+/// resolution delay has elapsed after the IPv4 addresses are known.
+struct ResolvingSplit<R: Resolve> {
+    v6: Option<R::Future>,
+    v4: Option<R::Future>,
+    addrs: Vec<IpAddr>,
+    delay: Option<Delay>,
+    err: Option<io::Error>,
 }
 
-impl ConnectingTcp {
-    fn new(
-        local_addr: Option<IpAddr>,
-        remote_addrs: dns::IpAddrs,
-        fallback_timeout: Option<Duration>,
-        reuse_address: bool,
-    ) -> ConnectingTcp {
-        if let Some(fallback_timeout) = fallback_timeout {
-            let (preferred_addrs, fallback_addrs) = remote_addrs.split_by_preference();
-            if fallback_addrs.is_empty() {
-                return ConnectingTcp {
-                    local_addr,
-                    preferred: ConnectingTcpRemote::new(preferred_addrs),
-                    fallback: None,
-                    reuse_address,
-                };
+impl<R: Resolve> ResolvingSplit<R> {
+    fn new(v6: R::Future, v4: R::Future) -> Self {
+        ResolvingSplit {
+            v6: Some(v6),
+            v4: Some(v4),
+            addrs: Vec::new(),
+            delay: None,
+            err: None,
+        }
+    }
+
+    /// Resolves to the addresses to start with, and the lookup still in
+    /// progress, if any.
+    fn poll(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<(Vec<IpAddr>, Option<R::Future>)>> {
+        let v6 = match self.v6 {
+            Some(ref mut future) => Pin::new(future).poll(cx),
+            None => Poll::Pending,
+        };
+        if let Poll::Ready(res) = v6 {
+            self.v6 = None;
+            match res {
+                Ok(addrs) => {
+                    let before = self.addrs.len();
+                    self.addrs.extend(addrs);
+                    if self.addrs.len() > before {
+                        return Poll::Ready(Ok((mem::replace(&mut self.addrs, Vec::new()), self.v4.take())));
+                    }
+                },
+                Err(err) => {
+                    trace!("AAAA lookup error: {}", err);
+                    self.err = Some(err);
+                },
             }
+        }
 
-            ConnectingTcp {
-                local_addr,
-                preferred: ConnectingTcpRemote::new(preferred_addrs),
-                fallback: Some(ConnectingTcpFallback {
-                    delay: Delay::new(Instant::now() + fallback_timeout),
-                    remote: ConnectingTcpRemote::new(fallback_addrs),
-                }),
-                reuse_address,
+        let v4 = match self.v4 {
+            Some(ref mut future) => Pin::new(future).poll(cx),
+            None => Poll::Pending,
+        };
+        if let Poll::Ready(res) = v4 {
+            self.v4 = None;
+            match res {
+                Ok(addrs) => {
+                    self.addrs.extend(addrs);
+                    if self.v6.is_some() && !self.addrs.is_empty() {
+                        trace!("A lookup finished first, waiting for AAAA");
+                        self.delay = Some(tokio_timer::delay(Instant::now() + RESOLUTION_DELAY));
+                    }
+                },
+                Err(err) => {
+                    trace!("A lookup error: {}", err);
+                    self.err = Some(err);
+                },
             }
-        } else {
-            ConnectingTcp {
-                local_addr,
-                preferred: ConnectingTcpRemote::new(remote_addrs),
-                fallback: None,
-                reuse_address,
+        }
+
+        if self.v6.is_none() && self.v4.is_none() {
+            if self.addrs.is_empty() {
+                let err = self.err.take().unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no addresses resolved")
+                });
+                return Poll::Ready(Err(err));
             }
+            return Poll::Ready(Ok((mem::replace(&mut self.addrs, Vec::new()), None)));
         }
+
+        if let Some(ref mut delay) = self.delay {
+            ready!(Pin::new(delay).poll(cx));
+            return Poll::Ready(Ok((mem::replace(&mut self.addrs, Vec::new()), self.v6.take())));
+        }
+
+        Poll::Pending
     }
 }
 
-struct ConnectingTcpFallback {
-    delay: Delay,
-    remote: ConnectingTcpRemote,
+/// Connects to a list of addresses with staggered, parallel attempts,
+/// as described in RFC 8305 section 5.
+struct ConnectingTcp {
+    addrs: VecDeque<SocketAddr>,
+    attempts: Vec<Attempt>,
+    attempt_delay: Option<Duration>,
+    next_attempt: Option<Delay>,
+    // When the connect timeout runs out.
+    deadline: Option<Delay>,
+    first_v6: Option<bool>,
+    errors: Vec<(SocketAddr, io::Error)>,
+    socket: Arc<SocketOptions>,
 }
 
-struct ConnectingTcpRemote {
-    addrs: dns::IpAddrs,
-    current: Option<ConnectFuture>,
+/// A connection attempt to one address.
+struct Attempt {
+    addr: SocketAddr,
+    future: ConnectFuture,
+    timeout: Option<(Delay, Duration)>,
 }
 
-impl ConnectingTcpRemote {
-    fn new(addrs: dns::IpAddrs) -> Self {
-        Self {
-            addrs,
-            current: None,
+/// The errors of every connection attempt.
+#[derive(Debug)]
+struct ConnectError {
+    errors: Vec<(SocketAddr, io::Error)>,
+}
+
+impl ConnectingTcp {
+    fn new(
+        remote_addrs: dns::IpAddrs,
+        preference: Option<IpPreference>,
+        attempt_delay: Option<Duration>,
+        connect_timeout: Option<Duration>,
+        socket: Arc<SocketOptions>,
+    ) -> ConnectingTcp {
+        let first_v6 = preference.map(|pref| pref == IpPreference::Ipv6);
+        let addrs = remote_addrs.sort_for_happy_eyeballs(first_v6);
+        let mut connecting = ConnectingTcp::ordered(addrs, attempt_delay, connect_timeout, socket);
+        connecting.first_v6 = first_v6;
+        connecting
+    }
+
+    /// Connect to the addresses in the given order, without interleaving
+    /// the address families.
+    fn ordered(
+        remote_addrs: Vec<SocketAddr>,
+        attempt_delay: Option<Duration>,
+        connect_timeout: Option<Duration>,
+        socket: Arc<SocketOptions>,
+    ) -> ConnectingTcp {
+        ConnectingTcp {
+            addrs: remote_addrs.into(),
+            attempts: Vec::new(),
+            attempt_delay,
+            next_attempt: None,
+            deadline: connect_timeout.map(|dur| tokio_timer::delay(Instant::now() + dur)),
+            first_v6: None,
+            errors: Vec::new(),
+            socket,
         }
     }
-}
 
-impl ConnectingTcpRemote {
+    /// Add addresses resolved after connecting started.
+    fn add_addrs(&mut self, addrs: Vec<SocketAddr>) {
+        let mut remaining = self.addrs.drain(..).collect::<Vec<_>>();
+        remaining.extend(addrs);
+        self.addrs = dns::IpAddrs::new(remaining)
+            .sort_for_happy_eyeballs(self.first_v6)
+            .into();
+    }
+
+    /// The timeout of the next attempt: an equal share of the time left
+    /// for every address still to try.
+    fn attempt_timeout(&self) -> Option<Duration> {
+        self.deadline.as_ref().map(|deadline| {
+            let now = Instant::now();
+            let left = if deadline.deadline() > now {
+                deadline.deadline() - now
+            } else {
+                Duration::from_secs(0)
+            };
+            left / (self.addrs.len() as u32 + 1)
+        })
+    }
+
     // not a Future, since passing a &Handle to poll
+    //
+    // If `more_addrs` is true, waits for `add_addrs` instead of failing
+    // once all addresses were tried.
     fn poll(
         &mut self,
         cx: &mut task::Context<'_>,
-        local_addr: &Option<IpAddr>,
         handle: &Option<Handle>,
-        reuse_address: bool,
+        more_addrs: bool,
     ) -> Poll<io::Result<TcpStream>> {
-        let mut err = None;
         loop {
-            if let Some(ref mut current) = self.current {
-                match current.as_mut().poll(cx) {
+            let mut failed = false;
+            let mut i = 0;
+            while i < self.attempts.len() {
+                match self.attempts[i].poll(cx) {
                     Poll::Ready(Ok(tcp)) => {
                         debug!("connected to {:?}", tcp.peer_addr().ok());
                         return Poll::Ready(Ok(tcp));
                     },
-                    Poll::Pending => return Poll::Pending,
                     Poll::Ready(Err(e)) => {
-                        trace!("connect error {:?}", e);
-                        err = Some(e);
-                        if let Some(addr) = self.addrs.next() {
-                            debug!("connecting to {}", addr);
-                            *current = connect(&addr, local_addr, handle, reuse_address)?;
-                            continue;
-                        }
+                        let attempt = self.attempts.remove(i);
+                        trace!("connect error for {}: {:?}", attempt.addr, e);
+                        self.errors.push((attempt.addr, e));
+                        failed = true;
+                    },
+                    Poll::Pending => i += 1,
+                }
+            }
+
+            let timed_out = match self.deadline {
+                Some(ref mut deadline) => Pin::new(deadline).poll(cx).is_ready(),
+                None => false,
+            };
+
+            // Start the next attempt when nothing is in flight, an attempt
+            // just failed, or the connection attempt delay elapsed.
+            let start = !timed_out && (failed || self.attempts.is_empty() || match self.next_attempt {
+                Some(ref mut delay) => Pin::new(delay).poll(cx).is_ready(),
+                None => false,
+            });
+
+            if start {
+                if let Some(addr) = self.addrs.pop_front() {
+                    debug!("connecting to {}", addr);
+                    // Keep interleaving the families if more are added.
+                    self.first_v6 = Some(!addr.is_ipv6());
+                    match connect(&addr, &self.socket, handle) {
+                        Ok(future) => {
+                            let timeout = self.attempt_timeout()
+                                .map(|dur| (tokio_timer::delay(Instant::now() + dur), dur));
+                            self.attempts.push(Attempt {
+                                addr,
+                                future,
+                                timeout,
+                            });
+                            self.next_attempt = self.attempt_delay
+                                .map(|dur| tokio_timer::delay(Instant::now() + dur));
+                        },
+                        Err(e) => {
+                            trace!("connect error for {}: {:?}", addr, e);
+                            self.errors.push((addr, e));
+                        },
                     }
+                    continue;
                 }
-            } else if let Some(addr) = self.addrs.next() {
-                debug!("connecting to {}", addr);
-                self.current = Some(connect(&addr, local_addr, handle, reuse_address)?);
-                continue;
             }
 
-            return Poll::Ready(Err(err.take().expect("missing connect error")));
+            if self.attempts.is_empty() && (!more_addrs || timed_out) {
+                return Poll::Ready(Err(self.error(timed_out)));
+            }
+
+            return Poll::Pending;
+        }
+    }
+
+    fn error(&mut self, timed_out: bool) -> io::Error {
+        let errors = mem::replace(&mut self.errors, Vec::new());
+        let kind = match errors.last() {
+            Some(&(_, ref err)) => err.kind(),
+            None if timed_out => {
+                return io::Error::new(io::ErrorKind::TimedOut, "connect timed out");
+            },
+            None => {
+                return io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to");
+            },
+        };
+        io::Error::new(kind, ConnectError {
+            errors,
+        })
+    }
+}
+
+impl Attempt {
+    fn poll(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<TcpStream>> {
+        if let Poll::Ready(res) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(res);
+        }
+        if let Some((ref mut delay, dur)) = self.timeout {
+            ready!(Pin::new(delay).poll(cx));
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("connect timed out after {:?}", dur),
+            )));
+        }
+        Poll::Pending
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("error connecting to")?;
+        for (i, &(ref addr, ref err)) in self.errors.iter().enumerate() {
+            let sep = if i == 0 { " " } else { "; " };
+            write!(f, "{}{}: {}", sep, addr, err)?;
         }
+        Ok(())
+    }
+}
+
+impl StdError for ConnectError {
+    fn description(&self) -> &str {
+        "error connecting to every address"
     }
 }
 
-fn connect(addr: &SocketAddr, local_addr: &Option<IpAddr>, handle: &Option<Handle>, reuse_address: bool) -> io::Result<ConnectFuture> {
+fn connect(addr: &SocketAddr, socket: &SocketOptions, handle: &Option<Handle>) -> io::Result<ConnectFuture> {
     let builder = match addr {
         &SocketAddr::V4(_) => TcpBuilder::new_v4()?,
         &SocketAddr::V6(_) => TcpBuilder::new_v6()?,
     };
 
-    if reuse_address {
-        builder.reuse_address(reuse_address)?;
+    if socket.reuse_address {
+        builder.reuse_address(socket.reuse_address)?;
     }
 
-    if let Some(ref local_addr) = *local_addr {
+    socket.apply(&builder, addr)?;
+
+    let local_addr = match addr {
+        &SocketAddr::V4(_) => socket.local_ipv4.map(IpAddr::V4),
+        &SocketAddr::V6(_) => socket.local_ipv6.map(IpAddr::V6),
+    };
+
+    if let Some(local_addr) = local_addr {
         // Caller has requested this socket be bound before calling connect
-        builder.bind(SocketAddr::new(local_addr.clone(), 0))?;
+        builder.bind(SocketAddr::new(local_addr, 0))?;
     }
     else if cfg!(windows) {
         // Windows requires a socket be bound before calling connect
@@ -519,53 +988,128 @@ fn connect(addr: &SocketAddr, local_addr: &Option<IpAddr>, handle: &Option<Handl
     }
 
     let handle = match *handle {
-        Some(ref handle) => Cow::Borrowed(handle),
-        None => Cow::Owned(Handle::default()),
+        Some(ref handle) => handle.clone(),
+        None => Handle::default(),
     };
+    let addr = *addr;
+
+    let std_tcp = builder.to_tcp_stream()?;
 
-    Ok(Box::pin(TcpStream::connect_std(builder.to_tcp_stream()?, addr, &handle)))
+    Ok(Box::pin(async move {
+        TcpStream::connect_std(std_tcp, &addr, &handle).await
+    }))
 }
 
-impl ConnectingTcp {
-    fn poll(&mut self, cx: &mut task::Context<'_>, handle: &Option<Handle>) -> Poll<io::Result<TcpStream>> {
-        match self.fallback.take() {
-            None => self.preferred.poll(cx, &self.local_addr, handle, self.reuse_address),
-            Some(mut fallback) => match self.preferred.poll(cx, &self.local_addr, handle, self.reuse_address) {
-                Poll::Ready(Ok(stream)) => {
-                    // Preferred successful - drop fallback.
-                    Poll::Ready(Ok(stream))
-                }
-                Poll::Pending => match Pin::new(&mut fallback.delay).poll(cx) {
-                    Poll::Ready(()) => match fallback.remote.poll(cx, &self.local_addr, handle, self.reuse_address) {
-                        Poll::Ready(Ok(stream)) => {
-                            // Fallback successful - drop current preferred,
-                            // but keep fallback as new preferred.
-                            self.preferred = fallback.remote;
-                            Poll::Ready(Ok(stream))
-                        }
-                        Poll::Pending => {
-                            // Neither preferred nor fallback are ready.
-                            self.fallback = Some(fallback);
-                            Poll::Pending
-                        }
-                        Poll::Ready(Err(_)) => {
-                            // Fallback failed - resume with preferred only.
-                            Poll::Pending
-                        }
-                    },
-                    Poll::Pending => {
-                        // Too early to attempt fallback.
-                        self.fallback = Some(fallback);
-                        Poll::Pending
-                    }
-                }
-                Poll::Ready(Err(_)) => {
-                    // Preferred failed - use fallback as new preferred.
-                    self.preferred = fallback.remote;
-                    self.preferred.poll(cx, &self.local_addr, handle, self.reuse_address)
-                }
+// ===== impl SocketOptions =====
+
+impl SocketOptions {
+    /// Fails if an option isn't supported on this platform.
+    fn check_supported(&self) -> io::Result<()> {
+        if cfg!(any(target_os = "linux", target_os = "android")) {
+            return Ok(());
+        }
+        self.check_linux_only()
+    }
+
+    /// Fails listing every option set that only Linux supports.
+    fn check_linux_only(&self) -> io::Result<()> {
+        let mut unsupported = Vec::new();
+        if self.interface.is_some() {
+            unsupported.push("SO_BINDTODEVICE (HttpConnector::set_interface)");
+        }
+        if self.keepalive_interval.is_some() {
+            unsupported.push("TCP_KEEPINTVL (HttpConnector::set_keepalive_interval)");
+        }
+        if self.keepalive_retries.is_some() {
+            unsupported.push("TCP_KEEPCNT (HttpConnector::set_keepalive_retries)");
+        }
+        if self.tos.is_some() {
+            unsupported.push("IP_TOS (HttpConnector::set_tos)");
+        }
+        if self.user_timeout.is_some() {
+            unsupported.push("TCP_USER_TIMEOUT (HttpConnector::set_user_timeout)");
+        }
+        if self.fast_open {
+            unsupported.push("TCP_FASTOPEN_CONNECT (HttpConnector::set_tcp_fast_open)");
+        }
+
+        if unsupported.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("socket options not supported on this platform: {}", unsupported.join(", ")),
+        ))
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn apply(&self, builder: &TcpBuilder, addr: &SocketAddr) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        use libc::{c_int, c_uint};
+
+        // Not in all versions of libc.
+        const TCP_FASTOPEN_CONNECT: c_int = 30;
+
+        let fd = builder.as_raw_fd();
+        if let Some(ref interface) = self.interface {
+            sockopt::set_bytes(fd, libc::SOL_SOCKET, libc::SO_BINDTODEVICE, interface.as_bytes())?;
+        }
+        if let Some(interval) = self.keepalive_interval {
+            let secs = interval.as_secs().max(1).min(c_int::max_value() as u64) as c_int;
+            sockopt::set(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, secs)?;
+        }
+        if let Some(retries) = self.keepalive_retries {
+            let retries = retries.min(c_int::max_value() as u32) as c_int;
+            sockopt::set(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, retries)?;
+        }
+        if let Some(tos) = self.tos {
+            match *addr {
+                SocketAddr::V4(_) => sockopt::set(fd, libc::IPPROTO_IP, libc::IP_TOS, tos as c_int)?,
+                SocketAddr::V6(_) => sockopt::set(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos as c_int)?,
             }
         }
+        if let Some(timeout) = self.user_timeout {
+            let millis = timeout.as_millis().min(c_uint::max_value() as u128) as c_uint;
+            sockopt::set(fd, libc::IPPROTO_TCP, libc::TCP_USER_TIMEOUT, millis)?;
+        }
+        if self.fast_open {
+            sockopt::set(fd, libc::IPPROTO_TCP, TCP_FASTOPEN_CONNECT, 1 as c_int)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn apply(&self, _builder: &TcpBuilder, _addr: &SocketAddr) -> io::Result<()> {
+        self.check_supported()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sockopt {
+    use std::io;
+    use std::mem;
+    use std::os::unix::io::RawFd;
+
+    use libc::{c_int, c_void, socklen_t};
+
+    pub(super) fn set<T: Copy>(fd: RawFd, level: c_int, name: c_int, value: T) -> io::Result<()> {
+        let ptr = &value as *const T as *const c_void;
+        set_raw(fd, level, name, ptr, mem::size_of::<T>())
+    }
+
+    pub(super) fn set_bytes(fd: RawFd, level: c_int, name: c_int, value: &[u8]) -> io::Result<()> {
+        set_raw(fd, level, name, value.as_ptr() as *const c_void, value.len())
+    }
+
+    fn set_raw(fd: RawFd, level: c_int, name: c_int, ptr: *const c_void, len: usize) -> io::Result<()> {
+        // Safety: `ptr` points to `len` readable bytes, which the kernel
+        // copies before returning.
+        let ret = unsafe { libc::setsockopt(fd, level, name, ptr, len as socklen_t) };
+        if ret == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
     }
 }
 
@@ -582,9 +1126,7 @@ mod tests {
     #[test]
     fn test_errors_missing_authority() {
         let uri = "/foo/bar?baz".parse().unwrap();
-        let dst = Destination {
-            uri,
-        };
+        let dst = Destination::new(uri);
         let connector = HttpConnector::new(1);
 
         assert_eq!(connector.connect(dst).wait().unwrap_err().kind(), io::ErrorKind::InvalidInput);
@@ -593,9 +1135,7 @@ mod tests {
     #[test]
     fn test_errors_enforce_http() {
         let uri = "https://example.domain/foo/bar?baz".parse().unwrap();
-        let dst = Destination {
-            uri,
-        };
+        let dst = Destination::new(uri);
         let connector = HttpConnector::new(1);
 
         assert_eq!(connector.connect(dst).wait().unwrap_err().kind(), io::ErrorKind::InvalidInput);
@@ -605,9 +1145,7 @@ mod tests {
     #[test]
     fn test_errors_missing_scheme() {
         let uri = "example.domain".parse().unwrap();
-        let dst = Destination {
-            uri,
-        };
+        let dst = Destination::new(uri);
         let connector = HttpConnector::new(1);
 
         assert_eq!(connector.connect(dst).wait().unwrap_err().kind(), io::ErrorKind::InvalidInput);
@@ -623,7 +1161,7 @@ mod tests {
 
         use futures::{Async, Poll};
         use tokio::runtime::current_thread::Runtime;
-        use tokio_reactor::Handle;
+        use tokio_net::driver::Handle;
 
         use super::dns;
         use super::ConnectingTcp;
@@ -688,7 +1226,7 @@ mod tests {
             }
 
             let addrs = hosts.iter().map(|host| (host.clone(), addr.port()).into()).collect();
-            let connecting_tcp = ConnectingTcp::new(None, dns::IpAddrs::new(addrs), Some(fallback_timeout), false);
+            let connecting_tcp = ConnectingTcp::new(None, dns::IpAddrs::new(addrs), None, Some(fallback_timeout), false);
             let fut = ConnectingTcpFuture(connecting_tcp);
 
             let start = Instant::now();
@@ -762,5 +1300,32 @@ mod tests {
         }
     }
     */
-}
 
+    use std::time::Duration;
+    use super::SocketOptions;
+
+    #[test]
+    fn linux_only_socket_options() {
+        let mut socket = SocketOptions::default();
+        socket.reuse_address = true;
+        socket.check_linux_only().expect("portable options");
+
+        socket.interface = Some("lo".to_string());
+        socket.keepalive_interval = Some(Duration::from_secs(10));
+        socket.keepalive_retries = Some(3);
+        socket.tos = Some(34 << 2);
+        socket.user_timeout = Some(Duration::from_secs(30));
+        socket.fast_open = true;
+        let err = socket.check_linux_only().unwrap_err().to_string();
+        for option in &[
+            "SO_BINDTODEVICE",
+            "TCP_KEEPINTVL",
+            "TCP_KEEPCNT",
+            "IP_TOS",
+            "TCP_USER_TIMEOUT",
+            "TCP_FASTOPEN_CONNECT",
+        ] {
+            assert!(err.contains(option), "{} missing from {:?}", option, err);
+        }
+    }
+}
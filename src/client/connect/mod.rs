@@ -4,20 +4,31 @@
 //!
 //! - A default [`HttpConnector`](HttpConnector) that does DNS resolution and
 //!   establishes connections over TCP.
+//! - A [`RoutingConnector`](RoutingConnector) dispatching to other
+//!   connectors by scheme, to use several of them with one `Client`.
+//! - A [`ServiceConnector`](ServiceConnector) using a tower `Service` as a
+//!   connector, and a [`ConnectService`](ConnectService) using a connector
+//!   as a tower `Service`.
 //! - The [`Connect`](Connect) trait and related types to build custom connectors.
 use std::convert::TryFrom;
 use std::error::Error as StdError;
+use std::net::IpAddr;
+use std::time::Duration;
 use std::{fmt, mem};
 
 use bytes::{BufMut, Bytes, BytesMut};
-use ::http::{uri, Response, Uri};
+use ::http::{uri, Response, Uri, Version};
 use tokio_io::{AsyncRead, AsyncWrite};
 
 use crate::common::{Future, Unpin};
 
 #[cfg(feature = "runtime")] pub mod dns;
 #[cfg(feature = "runtime")] mod http;
-#[cfg(feature = "runtime")] pub use self::http::{HttpConnector, HttpInfo};
+#[cfg(feature = "runtime")] pub use self::http::{HttpConnector, HttpInfo, IpPreference};
+mod routing;
+pub use self::routing::{RoutedTransport, RoutingConnector, RoutingFuture};
+mod service;
+pub use self::service::{ConnectService, ServiceConnecting, ServiceConnector};
 
 /// Connect to a destination, returning an IO transport.
 ///
@@ -38,11 +49,38 @@ pub trait Connect: Send + Sync {
 /// A set of properties to describe where and how to try to connect.
 ///
 /// This type is passed an argument for the [`Connect`](Connect) trait.
+///
+/// Besides the `Uri`, it carries hints from the `Client` about the
+/// connection it wants, such as the protocols to negotiate with ALPN.
 #[derive(Clone, Debug)]
 pub struct Destination {
     pub(super) uri: Uri,
+    pub(super) alpn_protocols: &'static [&'static str],
+    pub(super) is_connect: bool,
+    pub(super) local_address: Option<IpAddr>,
+    pub(super) pool_partition: String,
 }
 
+/// A local address to connect from.
+///
+/// Insert this in the extensions of a `Request` to ask the connector to
+/// bind the connection to this address, as with
+/// [`Destination::local_address`](Destination::local_address). The
+/// `Client` pools connections bound to different local addresses
+/// separately.
+///
+/// # Example
+///
+/// ```
+/// use hyper::{Body, Request};
+/// use hyper::client::connect::LocalAddress;
+///
+/// let mut req = Request::new(Body::empty());
+/// req.extensions_mut().insert(LocalAddress::new([10, 0, 0, 2].into()));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LocalAddress(IpAddr);
+
 /// Extra information about the connected transport.
 ///
 /// This can be used to inform recipients about things like if ALPN
@@ -52,10 +90,45 @@ pub struct Connected {
     pub(super) alpn: Alpn,
     pub(super) is_proxied: bool,
     pub(super) extra: Option<Extra>,
+    pub(super) authorities: Vec<String>,
 }
 
 pub(super) struct Extra(Box<dyn ExtraInner>);
 
+/// Information about the connection a `Response` was received on.
+///
+/// The `Client` sets this value in the extensions of every `Response`,
+/// whatever the connector.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(async_await)]
+/// # async fn doc() -> hyper::Result<()> {
+/// use hyper::Uri;
+/// use hyper::client::{Client, connect::ConnectionInfo};
+///
+/// let client = Client::new();
+/// let res = client.get(Uri::from_static("http://example.com")).await?;
+/// if let Some(info) = res.extensions().get::<ConnectionInfo>() {
+///     println!("connection #{}, reused = {}", info.id(), info.is_reused());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConnectionInfo {
+    pub(super) id: u64,
+    pub(super) version: Version,
+    pub(super) connect_duration: Duration,
+    pub(super) is_reused: bool,
+}
+
+/// The ALPN protocols of a `Client` that may upgrade to HTTP/2.
+pub(super) const ALPN_AUTO: &[&str] = &["h2", "http/1.1"];
+/// The ALPN protocols of an `http2_only` `Client`.
+pub(super) const ALPN_H2: &[&str] = &["h2"];
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(super) enum Alpn {
     H2,
@@ -72,7 +145,21 @@ impl Destination {
     pub fn try_from_uri(uri: Uri) -> crate::Result<Self> {
         uri.authority_part().ok_or(crate::error::Parse::Uri)?;
         uri.scheme_part().ok_or(crate::error::Parse::Uri)?;
-        Ok(Destination { uri })
+        Ok(Destination::new(uri))
+    }
+
+    pub(super) fn new(uri: Uri) -> Destination {
+        let pool_partition = match (uri.scheme_part(), uri.authority_part()) {
+            (Some(scheme), Some(auth)) => format!("{}://{}", scheme, auth),
+            _ => String::new(),
+        };
+        Destination {
+            uri,
+            alpn_protocols: ALPN_AUTO,
+            is_connect: false,
+            local_address: None,
+            pool_partition,
+        }
     }
 
     /// Get the protocol scheme.
@@ -242,15 +329,58 @@ impl Destination {
         }
     }
 
-    /*
+    /// Get the protocols the `Client` is willing to speak, in order of
+    /// preference, as ALPN protocol IDs.
+    ///
+    /// A TLS connector should offer these in its ALPN extension, and
+    /// report an `h2` selection with
+    /// [`Connected::negotiated_h2`](Connected::negotiated_h2).
+    pub fn alpn_protocols(&self) -> &[&'static str] {
+        self.alpn_protocols
+    }
+
     /// Returns whether this connection must negotiate HTTP/2 via ALPN.
+    ///
+    /// This is the case for a `Client` configured with `http2_only`.
     pub fn must_h2(&self) -> bool {
-        match self.alpn {
-            Alpn::Http1 => false,
-            Alpn::H2 => true,
-        }
+        !self.alpn_protocols.contains(&"http/1.1")
+    }
+
+    /// Returns whether the connection is for a `CONNECT` request.
+    ///
+    /// A proxy connector should then not expect to forward requests in
+    /// absolute-form on the connection.
+    pub fn is_connect(&self) -> bool {
+        self.is_connect
+    }
+
+    /// Get the local address the connection was asked to be bound to,
+    /// with a [`LocalAddress`](LocalAddress) in the request extensions.
+    pub fn local_address(&self) -> Option<IpAddr> {
+        self.local_address
+    }
+
+    /// Get the pool partition the connection will be stored in.
+    ///
+    /// Connections are only shared by requests in the same partition. It
+    /// names the origin of the request, like `https://hyper.rs`, and
+    /// includes the requested local address, if any. Changing the scheme
+    /// or host of the `Destination` doesn't change its partition.
+    pub fn pool_partition(&self) -> &str {
+        &self.pool_partition
+    }
+}
+
+impl LocalAddress {
+    /// Construct a new `LocalAddress`.
+    pub fn new(ip: IpAddr) -> LocalAddress {
+        LocalAddress(ip)
+    }
+
+    /// Get the IP address.
+    pub fn ip(&self) -> IpAddr {
+        self.0
     }
-    */
 }
 
 impl TryFrom<Uri> for Destination {
@@ -268,6 +398,7 @@ impl Connected {
             alpn: Alpn::None,
             is_proxied: false,
             extra: None,
+            authorities: Vec::new(),
         }
     }
 
@@ -311,6 +442,44 @@ impl Connected {
         self
     }
 
+    /// Set the other hosts this connection is valid for.
+    ///
+    /// When HTTP/2 connection coalescing is enabled on the `Client`, an
+    /// HTTP/2 connection may be reused for requests to any of these hosts
+    /// with the same scheme and port (see [RFC 7540 section 9.1.1][rfc]).
+    ///
+    /// Hosts may use a leading wildcard label, such as `*.example.com`,
+    /// matching like a TLS certificate name. A connector should only list
+    /// hosts that resolve to the connected address, and that the server's
+    /// certificate covers.
+    ///
+    /// [rfc]: https://tools.ietf.org/html/rfc7540#section-9.1.1
+    pub fn authorities<I, S>(mut self, authorities: I) -> Connected
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.authorities.extend(authorities
+            .into_iter()
+            .map(|host| host.as_ref().to_ascii_lowercase()));
+        self
+    }
+
+    /// Whether the `host` is one of the `authorities`.
+    pub(super) fn is_valid_for(&self, host: &str) -> bool {
+        self.authorities.iter().any(|pattern| {
+            if pattern.starts_with("*.") {
+                // The wildcard only matches a single, non-empty label.
+                let suffix = &pattern[1..];
+                host.len() > suffix.len()
+                    && host.ends_with(suffix)
+                    && !host[..host.len() - suffix.len()].contains('.')
+            } else {
+                pattern == host
+            }
+        })
+    }
+
     // Don't public expose that `Connected` is `Clone`, unsure if we want to
     // keep that contract...
     pub(super) fn clone(&self) -> Connected {
@@ -318,10 +487,37 @@ impl Connected {
             alpn: self.alpn.clone(),
             is_proxied: self.is_proxied,
             extra: self.extra.clone(),
+            authorities: self.authorities.clone(),
         }
     }
 }
 
+// ===== impl ConnectionInfo =====
+
+impl ConnectionInfo {
+    /// An identifier of the connection, unique within the process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The HTTP version spoken on the connection, such as negotiated by
+    /// ALPN.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// How long the connector took to establish the connection.
+    pub fn connect_duration(&self) -> Duration {
+        self.connect_duration
+    }
+
+    /// Whether the connection was used for previous requests, instead of
+    /// being established for this one.
+    pub fn is_reused(&self) -> bool {
+        self.is_reused
+    }
+}
+
 // ===== impl Extra =====
 
 impl Extra {
@@ -395,9 +591,7 @@ mod tests {
 
     #[test]
     fn test_destination_set_scheme() {
-        let mut dst = Destination {
-            uri: "http://hyper.rs".parse().expect("initial parse"),
-        };
+        let mut dst = Destination::new("http://hyper.rs".parse().expect("initial parse"));
 
         assert_eq!(dst.scheme(), "http");
         assert_eq!(dst.host(), "hyper.rs");
@@ -413,9 +607,7 @@ mod tests {
 
     #[test]
     fn test_destination_set_host() {
-        let mut dst = Destination {
-            uri: "http://hyper.rs".parse().expect("initial parse"),
-        };
+        let mut dst = Destination::new("http://hyper.rs".parse().expect("initial parse"));
 
         assert_eq!(dst.scheme(), "http");
         assert_eq!(dst.host(), "hyper.rs");
@@ -456,9 +648,7 @@ mod tests {
         // -----------------
 
         // Also test that an exist port is set correctly.
-        let mut dst = Destination {
-            uri: "http://hyper.rs:8080".parse().expect("initial parse 2"),
-        };
+        let mut dst = Destination::new("http://hyper.rs:8080".parse().expect("initial parse 2"));
 
         assert_eq!(dst.scheme(), "http");
         assert_eq!(dst.host(), "hyper.rs");
@@ -499,9 +689,7 @@ mod tests {
 
     #[test]
     fn test_destination_set_port() {
-        let mut dst = Destination {
-            uri: "http://hyper.rs".parse().expect("initial parse"),
-        };
+        let mut dst = Destination::new("http://hyper.rs".parse().expect("initial parse"));
 
         assert_eq!(dst.scheme(), "http");
         assert_eq!(dst.host(), "hyper.rs");
@@ -518,9 +706,7 @@ mod tests {
         assert_eq!(dst.port(), Some(8080));
 
         // Also test that an exist port is set correctly.
-        let mut dst = Destination {
-            uri: "http://hyper.rs:8080".parse().expect("initial parse 2"),
-        };
+        let mut dst = Destination::new("http://hyper.rs:8080".parse().expect("initial parse 2"));
 
         assert_eq!(dst.scheme(), "http");
         assert_eq!(dst.host(), "hyper.rs");
@@ -537,6 +723,23 @@ mod tests {
         assert_eq!(dst.port(), None);
     }
 
+    #[test]
+    fn test_destination_hints() {
+        let mut dst = Destination::new("https://hyper.rs:8443/a".parse().expect("initial parse"));
+
+        assert_eq!(dst.alpn_protocols(), &["h2", "http/1.1"]);
+        assert!(!dst.must_h2());
+        assert!(!dst.is_connect());
+        assert_eq!(dst.local_address(), None);
+        assert_eq!(dst.pool_partition(), "https://hyper.rs:8443");
+
+        dst.set_host("some.proxy").expect("set host");
+        assert_eq!(dst.pool_partition(), "https://hyper.rs:8443", "partition doesn't follow host");
+
+        dst.alpn_protocols = super::ALPN_H2;
+        assert!(dst.must_h2());
+    }
+
     #[test]
     fn test_try_from_destination() {
         let uri: http::Uri = "http://hyper.rs".parse().expect("initial parse");
@@ -621,4 +824,18 @@ mod tests {
         assert_eq!(res2.extensions().get::<Ex1>(), Some(&Ex1(99)));
         assert_eq!(res2.extensions().get::<Ex2>(), Some(&Ex2("hiccup")));
     }
+
+    #[test]
+    fn test_connected_authorities() {
+        let c1 = Connected::new()
+            .authorities(&["a.example.com", "*.Cdn.Example.com"]);
+
+        assert!(c1.is_valid_for("a.example.com"));
+        assert!(c1.is_valid_for("img.cdn.example.com"));
+        assert!(!c1.is_valid_for("b.example.com"));
+        assert!(!c1.is_valid_for("cdn.example.com"));
+        assert!(!c1.is_valid_for("a.img.cdn.example.com"));
+
+        assert!(!Connected::new().is_valid_for("a.example.com"));
+    }
 }
@@ -0,0 +1,342 @@
+//! A connector dispatching to other connectors.
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use futures_util::future;
+use futures_util::try_future::TryFutureExt;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use crate::common::{Future, Pin, Poll, task};
+use crate::upgrade::Io;
+use super::{Connect, Connected, Destination};
+
+/// A connector dispatching each `Destination` to one of several other
+/// connectors.
+///
+/// Routes are tried in the order they were added, and the first matching
+/// one is used. A route matches a scheme, or any `Destination` accepted by
+/// a predicate. Destinations matching no route are given to the fallback
+/// connector, if any, or fail to connect.
+///
+/// The transports of the connectors are boxed into a
+/// [`RoutedTransport`](RoutedTransport), and the `Connected` of each
+/// connector is returned as is.
+///
+/// # Example
+///
+/// ```
+/// use hyper::Client;
+/// use hyper::client::HttpConnector;
+/// use hyper::client::connect::RoutingConnector;
+///
+/// # fn doc(tls: HttpConnector, unix: HttpConnector) {
+/// let connector = RoutingConnector::new()
+///     .scheme("http", HttpConnector::new(4))
+///     .scheme("https", tls)
+///     .route(|dst| dst.host().ends_with(".internal"), unix);
+///
+/// let client = Client::builder().build::<_, hyper::Body>(connector);
+/// # drop(client);
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct RoutingConnector {
+    routes: Vec<Route>,
+    fallback: Option<Arc<dyn DynConnect>>,
+}
+
+/// The transport of a `RoutingConnector`.
+///
+/// This holds a trait object of the transport of the connector that was
+/// used. If the type is known, it can be recovered with
+/// [`downcast`](RoutedTransport::downcast).
+pub struct RoutedTransport {
+    io: Box<dyn Io + Send>,
+}
+
+/// A future of the connection established by a `RoutingConnector`.
+#[must_use = "futures do nothing unless polled"]
+pub struct RoutingFuture {
+    inner: BoxFuture,
+}
+
+#[derive(Clone)]
+struct Route {
+    matches: Matches,
+    connector: Arc<dyn DynConnect>,
+}
+
+#[derive(Clone)]
+enum Matches {
+    Scheme(String),
+    Predicate(Arc<dyn Fn(&Destination) -> bool + Send + Sync>),
+}
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+type BoxFuture = Pin<Box<dyn Future<Output = Result<(RoutedTransport, Connected), BoxError>> + Send>>;
+
+/// An object-safe `Connect`.
+trait DynConnect: Send + Sync {
+    fn connect_boxed(&self, dst: Destination) -> BoxFuture;
+}
+
+impl<C> DynConnect for C
+where
+    C: Connect,
+    C::Error: 'static,
+    C::Future: 'static,
+{
+    fn connect_boxed(&self, dst: Destination) -> BoxFuture {
+        Box::pin(Connect::connect(self, dst)
+            .map_ok(|(io, connected)| (RoutedTransport::new(io), connected))
+            .map_err(Into::into))
+    }
+}
+
+impl RoutingConnector {
+    /// Construct a new `RoutingConnector` without routes.
+    pub fn new() -> RoutingConnector {
+        RoutingConnector::default()
+    }
+
+    /// Route destinations with the scheme `scheme` to `connector`.
+    ///
+    /// Schemes are matched case-insensitively.
+    pub fn scheme<C>(mut self, scheme: &str, connector: C) -> RoutingConnector
+    where
+        C: Connect + 'static,
+        C::Error: 'static,
+        C::Future: 'static,
+    {
+        self.routes.push(Route {
+            matches: Matches::Scheme(scheme.to_ascii_lowercase()),
+            connector: Arc::new(connector),
+        });
+        self
+    }
+
+    /// Route destinations accepted by `predicate` to `connector`.
+    pub fn route<F, C>(mut self, predicate: F, connector: C) -> RoutingConnector
+    where
+        F: Fn(&Destination) -> bool + Send + Sync + 'static,
+        C: Connect + 'static,
+        C::Error: 'static,
+        C::Future: 'static,
+    {
+        self.routes.push(Route {
+            matches: Matches::Predicate(Arc::new(predicate)),
+            connector: Arc::new(connector),
+        });
+        self
+    }
+
+    /// Route destinations matching no other route to `connector`.
+    pub fn fallback<C>(mut self, connector: C) -> RoutingConnector
+    where
+        C: Connect + 'static,
+        C::Error: 'static,
+        C::Future: 'static,
+    {
+        self.fallback = Some(Arc::new(connector));
+        self
+    }
+}
+
+impl Connect for RoutingConnector {
+    type Transport = RoutedTransport;
+    type Error = BoxError;
+    type Future = RoutingFuture;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let connector = self.routes
+            .iter()
+            .find(|route| route.matches(&dst))
+            .map(|route| &route.connector)
+            .or_else(|| self.fallback.as_ref());
+
+        let inner = match connector {
+            Some(connector) => connector.connect_boxed(dst),
+            None => {
+                debug!("no route to connect to {}://{}", dst.scheme(), dst.host());
+                let err = io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no connector for scheme {:?}", dst.scheme()),
+                );
+                Box::pin(future::err(err.into()))
+            },
+        };
+        RoutingFuture {
+            inner,
+        }
+    }
+}
+
+impl fmt::Debug for RoutingConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RoutingConnector")
+            .field("routes", &self.routes)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl Route {
+    fn matches(&self, dst: &Destination) -> bool {
+        match self.matches {
+            Matches::Scheme(ref scheme) => dst.scheme().eq_ignore_ascii_case(scheme),
+            Matches::Predicate(ref predicate) => predicate(dst),
+        }
+    }
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.matches {
+            Matches::Scheme(ref scheme) => f.debug_tuple("Scheme").field(scheme).finish(),
+            Matches::Predicate(_) => f.pad("Predicate"),
+        }
+    }
+}
+
+// ===== impl RoutedTransport =====
+
+impl RoutedTransport {
+    fn new<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(io: T) -> RoutedTransport {
+        RoutedTransport {
+            io: Box::new(io),
+        }
+    }
+
+    /// Tries to downcast the internal trait object to the type passed.
+    ///
+    /// On error, returns the `RoutedTransport` back.
+    pub fn downcast<T: AsyncRead + AsyncWrite + Unpin + 'static>(self) -> Result<T, RoutedTransport> {
+        match self.io.__hyper_downcast() {
+            Ok(t) => Ok(*t),
+            Err(io) => Err(RoutedTransport {
+                io,
+            }),
+        }
+    }
+}
+
+impl AsyncRead for RoutedTransport {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.io.prepare_uninitialized_buffer(buf)
+    }
+
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for RoutedTransport {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+impl fmt::Debug for RoutedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RoutedTransport")
+            .finish()
+    }
+}
+
+// ===== impl RoutingFuture =====
+
+impl Future for RoutingFuture {
+    type Output = Result<(RoutedTransport, Connected), BoxError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for RoutingFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("RoutingFuture")
+    }
+}
+
+#[cfg(all(test, feature = "runtime"))]
+mod tests {
+    use std::net::TcpListener;
+
+    use tokio_net::tcp::TcpStream;
+
+    use super::*;
+    use super::super::HttpConnector;
+
+    /// Connects with an `HttpConnector`, as if through a proxy.
+    struct Proxy(HttpConnector);
+
+    impl Connect for Proxy {
+        type Transport = TcpStream;
+        type Error = io::Error;
+        type Future = Pin<Box<dyn Future<Output = io::Result<(TcpStream, Connected)>> + Send>>;
+
+        fn connect(&self, dst: Destination) -> Self::Future {
+            Box::pin(self.0.connect(dst).map_ok(|(io, connected)| (io, connected.proxy(true))))
+        }
+    }
+
+    fn dst(uri: &str) -> Destination {
+        Destination::try_from_uri(uri.parse().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn routes_by_scheme() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let connector = RoutingConnector::new()
+            .scheme("HTTP", HttpConnector::new(1));
+
+        let (io, connected) = connector
+            .connect(dst(&format!("http://{}", addr)))
+            .await
+            .expect("connect http");
+        assert!(!connected.is_proxied);
+        let io = io.downcast::<TcpStream>().expect("downcast TcpStream");
+        assert_eq!(io.peer_addr().unwrap(), addr);
+
+        let err = connector
+            .connect(dst(&format!("https://{}", addr)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no connector for scheme \"https\"");
+    }
+
+    #[tokio::test]
+    async fn routes_by_predicate_and_keeps_connected() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut http = HttpConnector::new(1);
+        http.enforce_http(false);
+        let connector = RoutingConnector::new()
+            .route(move |dst| dst.port() == Some(addr.port()), Proxy(http))
+            .fallback(HttpConnector::new(1));
+
+        let (_, connected) = connector
+            .connect(dst(&format!("https://{}", addr)))
+            .await
+            .expect("connect by predicate");
+        assert!(connected.is_proxied);
+
+        // the fallback is an HttpConnector, enforcing http
+        connector
+            .connect(dst("https://127.0.0.1:1"))
+            .await
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,228 @@
+//! Adapters between connectors and tower `Service`s.
+use std::error::Error as StdError;
+use std::fmt;
+
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower_service::Service;
+
+use crate::common::{Future, Pin, Poll, Unpin, task};
+use super::{Connect, Connected, Destination};
+
+/// A connector calling a tower `Service` of `Destination`s.
+///
+/// The service responds with the transport and its `Connected`, as a
+/// `Connect` does, which allows wrapping connectors in tower middleware,
+/// such as timeouts or rate limits. The service is cloned for every
+/// connection, and called once it is ready.
+///
+/// # Example
+///
+/// ```
+/// use hyper::Client;
+/// use hyper::client::HttpConnector;
+/// use hyper::client::connect::ServiceConnector;
+///
+/// // any tower middleware could wrap the HttpConnector here
+/// let connector = ServiceConnector::new(HttpConnector::new(4));
+/// let client = Client::builder().build::<_, hyper::Body>(connector);
+/// # drop(client);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServiceConnector<S> {
+    service: S,
+}
+
+/// A tower `Service` of `Destination`s calling a `Connect`.
+///
+/// This allows wrapping any connector in tower middleware, which can then
+/// be used as a connector again with a `ServiceConnector`.
+///
+/// # Example
+///
+/// ```
+/// use hyper::client::HttpConnector;
+/// use hyper::client::connect::{ConnectService, ServiceConnector};
+///
+/// let service = ConnectService::new(HttpConnector::new(4));
+/// // any tower middleware could wrap the service here
+/// let connector = ServiceConnector::new(service);
+/// # drop(connector);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConnectService<C> {
+    connector: C,
+}
+
+/// A future of the connection established by a `ServiceConnector`.
+#[must_use = "futures do nothing unless polled"]
+pub struct ServiceConnecting<S: Service<Destination>> {
+    state: State<S>,
+}
+
+enum State<S: Service<Destination>> {
+    Ready(S, Option<Destination>),
+    Calling(Pin<Box<S::Future>>),
+}
+
+impl<S> ServiceConnector<S> {
+    /// Construct a new `ServiceConnector` calling `service`.
+    pub fn new(service: S) -> ServiceConnector<S> {
+        ServiceConnector {
+            service,
+        }
+    }
+
+    /// Get a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+
+    /// Consume this connector, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<S, T> Connect for ServiceConnector<S>
+where
+    S: Service<Destination, Response=(T, Connected)> + Clone + Send + Sync,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    S::Future: Send,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Transport = T;
+    type Error = S::Error;
+    type Future = ServiceConnecting<S>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        ServiceConnecting {
+            state: State::Ready(self.service.clone(), Some(dst)),
+        }
+    }
+}
+
+// ===== impl ConnectService =====
+
+impl<C> ConnectService<C> {
+    /// Construct a new `ConnectService` calling `connector`.
+    pub fn new(connector: C) -> ConnectService<C> {
+        ConnectService {
+            connector,
+        }
+    }
+
+    /// Get a reference to the inner connector.
+    pub fn get_ref(&self) -> &C {
+        &self.connector
+    }
+
+    /// Consume this service, returning the inner connector.
+    pub fn into_inner(self) -> C {
+        self.connector
+    }
+}
+
+impl<C: Connect> Service<Destination> for ConnectService<C> {
+    type Response = (C::Transport, Connected);
+    type Error = C::Error;
+    type Future = C::Future;
+
+    fn poll_ready(&mut self, _cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Destination) -> Self::Future {
+        self.connector.connect(dst)
+    }
+}
+
+// ===== impl ServiceConnecting =====
+
+impl<S> Future for ServiceConnecting<S>
+where
+    S: Service<Destination>,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let future = match self.state {
+                State::Ready(ref mut service, ref mut dst) => {
+                    ready!(service.poll_ready(cx))?;
+                    service.call(dst.take().expect("polled after complete"))
+                },
+                State::Calling(ref mut future) => return future.as_mut().poll(cx),
+            };
+            self.state = State::Calling(Box::pin(future));
+        }
+    }
+}
+
+// The service is never pinned, only its future, which is boxed.
+impl<S: Service<Destination>> Unpin for ServiceConnecting<S> {}
+
+impl<S: Service<Destination>> fmt::Debug for ServiceConnecting<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("ServiceConnecting")
+    }
+}
+
+#[cfg(all(test, feature = "runtime"))]
+mod tests {
+    use std::io;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use super::super::HttpConnector;
+
+    /// Counts the calls to an `HttpConnector`, like a tower middleware.
+    #[derive(Clone)]
+    struct Counting {
+        http: HttpConnector,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Service<Destination> for Counting {
+        type Response = <HttpConnector as Service<Destination>>::Response;
+        type Error = io::Error;
+        type Future = <HttpConnector as Service<Destination>>::Future;
+
+        fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), io::Error>> {
+            self.http.poll_ready(cx)
+        }
+
+        fn call(&mut self, dst: Destination) -> Self::Future {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.http.call(dst)
+        }
+    }
+
+    #[tokio::test]
+    async fn connects_with_service() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let connector = ServiceConnector::new(Counting {
+            http: HttpConnector::new(1),
+            calls: calls.clone(),
+        });
+
+        let dst = Destination::try_from_uri(format!("http://{}", addr).parse().unwrap()).unwrap();
+        let (io, _) = connector.connect(dst).await.expect("connect");
+        assert_eq!(io.peer_addr().unwrap(), addr);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn connects_with_connect_service() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let connector = ServiceConnector::new(ConnectService::new(HttpConnector::new(1)));
+
+        let dst = Destination::try_from_uri(format!("http://{}", addr).parse().unwrap()).unwrap();
+        let (io, _) = connector.connect(dst).await.expect("connect");
+        assert_eq!(io.peer_addr().unwrap(), addr);
+    }
+}
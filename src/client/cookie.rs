@@ -0,0 +1,917 @@
+//! Cookies, and the stores keeping them for a `Client`.
+//!
+//! A `Client` built with a [`CookieStore`](CookieStore) stores the cookies
+//! of every `Set-Cookie` header it receives, and sends the matching ones
+//! in a `Cookie` header, as described in [RFC 6265][rfc].
+//!
+//! Two stores are provided:
+//!
+//! - A [`MemoryStore`](MemoryStore) keeping cookies for as long as it lives.
+//! - A [`PersistentStore`](PersistentStore), which can also be saved to and
+//!   loaded from the Netscape `cookies.txt` format used by curl.
+//!
+//! A cookie can't be set on a public suffix, such as `com`, since it would
+//! be sent to every site under it. By default, only top-level domains are
+//! treated as public suffixes, while a store can be given a complete list
+//! with [`PublicSuffixes`](PublicSuffixes).
+//!
+//! # Example
+//!
+//! ```
+//! use std::sync::Arc;
+//! use hyper::Client;
+//! use hyper::client::cookie::MemoryStore;
+//!
+//! let cookies = Arc::new(MemoryStore::new());
+//! let client = Client::builder()
+//!     .cookie_store(cookies.clone())
+//!     .build_http::<hyper::Body>();
+//! # drop(client);
+//! ```
+//!
+//! [rfc]: https://tools.ietf.org/html/rfc6265
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http::{HeaderValue, Uri};
+
+/// A store of the cookies of a `Client`.
+pub trait CookieStore: Send + Sync + 'static {
+    /// Store the cookies of the `Set-Cookie` headers of a response to a
+    /// request to `uri`.
+    fn set_cookies(&self, set_cookie: &mut dyn Iterator<Item=&HeaderValue>, uri: &Uri);
+
+    /// Get the `Cookie` header to send with a request to `uri`, if any
+    /// cookie matches.
+    fn cookies(&self, uri: &Uri) -> Option<HeaderValue>;
+}
+
+/// Decides which domains are public suffixes, such as `com` or `co.uk`,
+/// under which anyone can register a domain.
+///
+/// This is implemented for closures, so a list like the one of
+/// <https://publicsuffix.org> can be plugged in.
+pub trait PublicSuffixes: Send + Sync + 'static {
+    /// Returns whether `domain`, lowercase and without a leading dot, is a
+    /// public suffix.
+    fn is_public_suffix(&self, domain: &str) -> bool;
+}
+
+/// The default `PublicSuffixes`, of only the top-level domains, without a
+/// dot.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TopLevelDomains;
+
+/// A cookie, as set by a `Set-Cookie` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    expires: Option<SystemTime>,
+    same_site: Option<SameSite>,
+}
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    /// `SameSite=Strict`
+    Strict,
+    /// `SameSite=Lax`
+    Lax,
+    /// `SameSite=None`
+    None,
+}
+
+/// A `CookieStore` keeping cookies in memory.
+///
+/// This is a `PersistentStore` which is never saved.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    inner: PersistentStore,
+}
+
+/// A `CookieStore` which can be saved and loaded.
+///
+/// Cookies are saved in the Netscape `cookies.txt` format, as used by curl
+/// with `--cookie-jar`. Session cookies, without `Expires` or `Max-Age`,
+/// are not saved. The format doesn't keep the `SameSite` attribute.
+pub struct PersistentStore {
+    jar: Mutex<Jar>,
+    public_suffixes: Box<dyn PublicSuffixes>,
+}
+
+#[derive(Debug, Default)]
+struct Jar {
+    // With the order they were first stored in.
+    cookies: Vec<(u64, Cookie)>,
+    next: u64,
+}
+
+// ===== impl Cookie =====
+
+impl Cookie {
+    /// Parse a `Set-Cookie` header of a response to a request to `uri`.
+    ///
+    /// Returns `None` if the header isn't a valid cookie, or if its
+    /// `Domain` doesn't include the host of `uri`, or is a top-level domain.
+    pub fn parse(set_cookie: &str, uri: &Uri) -> Option<Cookie> {
+        Cookie::parse_with_suffixes(set_cookie, uri, &TopLevelDomains)
+    }
+
+    /// Parse a `Set-Cookie` header, as `parse` does, but rejecting any
+    /// `Domain` that is one of `public_suffixes`.
+    ///
+    /// A public suffix `Domain` is still allowed if it is the host of `uri`
+    /// itself, but only for that host, as described in RFC 6265, section
+    /// 5.3.
+    pub fn parse_with_suffixes(
+        set_cookie: &str,
+        uri: &Uri,
+        public_suffixes: &dyn PublicSuffixes,
+    ) -> Option<Cookie> {
+        let host = uri.host()?.to_ascii_lowercase();
+        let mut parts = set_cookie.split(';');
+        let pair = parts.next()?;
+        let eq = pair.find('=')?;
+        let name = pair[..eq].trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_owned(),
+            value: pair[eq + 1..].trim().to_owned(),
+            domain: host,
+            host_only: true,
+            path: default_path(uri.path()),
+            secure: false,
+            http_only: false,
+            expires: None,
+            same_site: None,
+        };
+        let mut domain = None;
+        let mut expires = None;
+        let mut max_age = None;
+
+        for attr in parts {
+            let (key, val) = match attr.find('=') {
+                Some(eq) => (attr[..eq].trim(), attr[eq + 1..].trim()),
+                None => (attr.trim(), ""),
+            };
+            if key.eq_ignore_ascii_case("expires") {
+                if let Some(time) = parse_date(val) {
+                    expires = Some(time);
+                }
+            } else if key.eq_ignore_ascii_case("max-age") {
+                if let Some(secs) = parse_max_age(val) {
+                    max_age = Some(secs);
+                }
+            } else if key.eq_ignore_ascii_case("domain") {
+                let val = if val.starts_with('.') { &val[1..] } else { val };
+                if !val.is_empty() {
+                    domain = Some(val.to_ascii_lowercase());
+                }
+            } else if key.eq_ignore_ascii_case("path") {
+                if val.starts_with('/') {
+                    cookie.path = val.to_owned();
+                }
+            } else if key.eq_ignore_ascii_case("secure") {
+                cookie.secure = true;
+            } else if key.eq_ignore_ascii_case("httponly") {
+                cookie.http_only = true;
+            } else if key.eq_ignore_ascii_case("samesite") {
+                cookie.same_site = if val.eq_ignore_ascii_case("strict") {
+                    Some(SameSite::Strict)
+                } else if val.eq_ignore_ascii_case("lax") {
+                    Some(SameSite::Lax)
+                } else if val.eq_ignore_ascii_case("none") {
+                    Some(SameSite::None)
+                } else {
+                    None
+                };
+            }
+        }
+
+        // Max-Age has precedence over Expires.
+        cookie.expires = match max_age {
+            Some(secs) if secs <= 0 => Some(UNIX_EPOCH),
+            Some(secs) => Some(add_saturating(SystemTime::now(), secs as u64)),
+            None => expires,
+        };
+
+        if let Some(domain) = domain {
+            if public_suffixes.is_public_suffix(&domain) {
+                if domain == cookie.domain {
+                    return Some(cookie);
+                }
+                trace!("cookie domain {:?} is a public suffix", domain);
+                return None;
+            }
+            if !domain_match(&cookie.domain, &domain) {
+                trace!("cookie domain {:?} doesn't match host {:?}", domain, cookie.domain);
+                return None;
+            }
+            cookie.domain = domain;
+            cookie.host_only = false;
+        }
+        Some(cookie)
+    }
+
+    /// Get the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Get the domain of the cookie.
+    ///
+    /// Without a `Domain` attribute, this is the host that set the cookie.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Returns whether the cookie is only sent to the host that set it,
+    /// because it had no `Domain` attribute.
+    pub fn is_host_only(&self) -> bool {
+        self.host_only
+    }
+
+    /// Get the path of the cookie.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns whether the cookie is only sent over secure connections.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Returns whether the cookie had the `HttpOnly` attribute.
+    ///
+    /// This is only meaningful for browsers, so such cookies are sent as
+    /// any other.
+    pub fn is_http_only(&self) -> bool {
+        self.http_only
+    }
+
+    /// Get when the cookie expires, from its `Max-Age` or `Expires`.
+    ///
+    /// A session cookie, without either, has no expiry.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Get the `SameSite` attribute of the cookie.
+    pub fn same_site(&self) -> Option<SameSite> {
+        self.same_site
+    }
+
+    /// Returns whether the cookie has expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires.map(|expires| expires <= SystemTime::now()).unwrap_or(false)
+    }
+
+    /// Returns whether the cookie should be sent with a request to `uri`.
+    pub fn matches(&self, uri: &Uri) -> bool {
+        let host = match uri.host() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        };
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_ok
+            && path_match(uri.path(), &self.path)
+            && (!self.secure || is_secure(uri))
+            && !self.is_expired()
+    }
+}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// The default path of a cookie, from the path of the request.
+fn default_path(path: &str) -> String {
+    if !path.starts_with('/') {
+        return "/".to_owned();
+    }
+    match path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(idx) => path[..idx].to_owned(),
+    }
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    // IP addresses only match exactly.
+    let is_ip = host.starts_with('[') || host.parse::<IpAddr>().is_ok();
+    !is_ip
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path || (
+        path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/'))
+    )
+}
+
+fn is_secure(uri: &Uri) -> bool {
+    match uri.scheme_str() {
+        Some(scheme) => scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("wss"),
+        None => false,
+    }
+}
+
+fn parse_max_age(val: &str) -> Option<i64> {
+    let digits = if val.starts_with('-') { &val[1..] } else { val };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // Values too large only mean a long time, or the past.
+    match val.parse::<i64>() {
+        Ok(secs) => Some(secs),
+        Err(_) if digits.len() < val.len() => Some(0),
+        Err(_) => Some(::std::i64::MAX),
+    }
+}
+
+/// Parse a date with the lenient algorithm of RFC 6265, section 5.1.1.
+fn parse_date(val: &str) -> Option<SystemTime> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+
+    let tokens = val
+        .split(|c: char| c.is_ascii() && is_date_delimiter(c as u8))
+        .filter(|token| !token.is_empty());
+    for token in tokens {
+        if time.is_none() {
+            if let Some(hms) = parse_time(token) {
+                time = Some(hms);
+                continue;
+            }
+        }
+        if day.is_none() {
+            if let Some(d) = parse_digits(token, 1, 2) {
+                day = Some(d);
+                continue;
+            }
+        }
+        if month.is_none() {
+            if let Some(m) = parse_month(token) {
+                month = Some(m);
+                continue;
+            }
+        }
+        if year.is_none() {
+            if let Some(y) = parse_digits(token, 2, 4) {
+                year = Some(y);
+                continue;
+            }
+        }
+    }
+
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    if year >= 70 && year <= 99 {
+        year += 1900;
+    } else if year <= 69 {
+        year += 2000;
+    }
+    if day < 1 || day > days_in_month(year, month) || year < 1601
+        || hour > 23 || minute > 59 || second > 59
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year as i64, month, day as i64);
+    let secs = days * 86_400 + (hour * 3600 + minute * 60 + second) as i64;
+    if secs >= 0 {
+        Some(add_saturating(UNIX_EPOCH, secs as u64))
+    } else {
+        // A date before the epoch is expired all the same.
+        Some(UNIX_EPOCH.checked_sub(Duration::from_secs(-secs as u64)).unwrap_or(UNIX_EPOCH))
+    }
+}
+
+/// The latest expiry time kept, the end of the year 9999.
+///
+/// It is the latest date `parse_date` accepts, and can be represented by
+/// every platform's `SystemTime`.
+const MAX_EXPIRES_SECS: u64 = 253_402_300_799;
+
+/// Adds `secs` to `time`, saturating at the latest expiry time, so that a
+/// cookie meant to last "forever" isn't taken for a session cookie.
+fn add_saturating(time: SystemTime, secs: u64) -> SystemTime {
+    let max = UNIX_EPOCH + Duration::from_secs(MAX_EXPIRES_SECS);
+    match time.checked_add(Duration::from_secs(secs)) {
+        Some(expires) if expires < max => expires,
+        _ => max,
+    }
+}
+
+fn is_date_delimiter(b: u8) -> bool {
+    match b {
+        0x09 | 0x20..=0x2F | 0x3B..=0x40 | 0x5B..=0x60 | 0x7B..=0x7E => true,
+        _ => false,
+    }
+}
+
+/// Parse `min` to `max` digits, optionally followed by non-digits.
+fn parse_digits(token: &str, min: usize, max: usize) -> Option<u32> {
+    let len = token.bytes().take_while(|b| b.is_ascii_digit()).count();
+    if len < min || len > max {
+        return None;
+    }
+    token[..len].parse().ok()
+}
+
+fn parse_time(token: &str) -> Option<(u32, u32, u32)> {
+    let mut fields = token.splitn(3, ':');
+    let hour = fields.next()?;
+    let minute = fields.next()?;
+    let second = fields.next()?;
+    if hour.len() > 2 || minute.len() > 2 {
+        return None;
+    }
+    Some((
+        parse_digits(hour, 1, 2)?,
+        parse_digits(minute, 1, 2)?,
+        parse_digits(second, 1, 2)?,
+    ))
+}
+
+fn parse_month(token: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun",
+        "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let prefix = token.get(..3)?;
+    MONTHS
+        .iter()
+        .position(|month| prefix.eq_ignore_ascii_case(month))
+        .map(|idx| idx as u32 + 1)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// The days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// ===== impl PublicSuffixes =====
+
+impl PublicSuffixes for TopLevelDomains {
+    fn is_public_suffix(&self, domain: &str) -> bool {
+        !domain.contains('.')
+    }
+}
+
+impl<F> PublicSuffixes for F
+where
+    F: Fn(&str) -> bool + Send + Sync + 'static,
+{
+    fn is_public_suffix(&self, domain: &str) -> bool {
+        self(domain)
+    }
+}
+
+// ===== impl Jar =====
+
+impl Jar {
+    /// Store a cookie, as in RFC 6265, section 5.3.
+    fn insert(&mut self, cookie: Cookie) {
+        let existing = self.cookies.iter().position(|&(_, ref stored)| {
+            stored.name == cookie.name
+                && stored.domain == cookie.domain
+                && stored.path == cookie.path
+        });
+        // A replaced cookie keeps its creation order.
+        let created = match existing {
+            Some(idx) => self.cookies.remove(idx).0,
+            None => {
+                self.next += 1;
+                self.next
+            },
+        };
+        // An expired cookie only removes the existing one.
+        if !cookie.is_expired() {
+            self.cookies.push((created, cookie));
+        }
+    }
+
+    fn set_cookies(
+        &mut self,
+        set_cookie: &mut dyn Iterator<Item=&HeaderValue>,
+        uri: &Uri,
+        public_suffixes: &dyn PublicSuffixes,
+    ) {
+        for value in set_cookie {
+            let cookie = value
+                .to_str()
+                .ok()
+                .and_then(|s| Cookie::parse_with_suffixes(s, uri, public_suffixes));
+            match cookie {
+                Some(cookie) => self.insert(cookie),
+                None => debug!("ignoring invalid Set-Cookie from {}: {:?}", uri, value),
+            }
+        }
+    }
+
+    /// The `Cookie` header for `uri`, as in RFC 6265, section 5.4.
+    fn header(&mut self, uri: &Uri) -> Option<HeaderValue> {
+        self.cookies.retain(|&(_, ref cookie)| !cookie.is_expired());
+        let mut matching = self.cookies
+            .iter()
+            .filter(|&&(_, ref cookie)| cookie.matches(uri))
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            return None;
+        }
+        // Longer paths first, then the oldest cookies.
+        matching.sort_by(|a, b| {
+            b.1.path.len().cmp(&a.1.path.len()).then(a.0.cmp(&b.0))
+        });
+        let header = matching
+            .iter()
+            .map(|&&(_, ref cookie)| cookie.to_string())
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&header).ok()
+    }
+}
+
+// ===== impl MemoryStore =====
+
+impl MemoryStore {
+    /// Construct an empty `MemoryStore`.
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// Set the public suffixes on which cookies can't be set.
+    ///
+    /// Default is `TopLevelDomains`.
+    pub fn set_public_suffixes<P: PublicSuffixes>(&mut self, public_suffixes: P) {
+        self.inner.set_public_suffixes(public_suffixes);
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&self) {
+        self.inner.clear();
+    }
+}
+
+impl CookieStore for MemoryStore {
+    fn set_cookies(&self, set_cookie: &mut dyn Iterator<Item=&HeaderValue>, uri: &Uri) {
+        self.inner.set_cookies(set_cookie, uri);
+    }
+
+    fn cookies(&self, uri: &Uri) -> Option<HeaderValue> {
+        self.inner.cookies(uri)
+    }
+}
+
+// ===== impl PersistentStore =====
+
+impl PersistentStore {
+    /// Construct an empty `PersistentStore`.
+    pub fn new() -> PersistentStore {
+        PersistentStore::default()
+    }
+
+    /// Load a `PersistentStore` from cookies in the Netscape format.
+    ///
+    /// Expired cookies are skipped.
+    pub fn load<R: BufRead>(reader: R) -> io::Result<PersistentStore> {
+        let mut jar = Jar::default();
+        for line in reader.lines() {
+            let line = line?;
+            let (line, http_only) = if line.starts_with("#HttpOnly_") {
+                (&line["#HttpOnly_".len()..], true)
+            } else {
+                (&line[..], false)
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() != 7 {
+                return Err(invalid_line(line));
+            }
+            let expires = match fields[4].parse::<u64>() {
+                Ok(0) => None,
+                Ok(secs) => Some(add_saturating(UNIX_EPOCH, secs)),
+                Err(_) => return Err(invalid_line(line)),
+            };
+            let host_only = fields[1] != "TRUE";
+            let domain = fields[0].trim_start_matches('.').to_ascii_lowercase();
+            jar.insert(Cookie {
+                name: fields[5].to_owned(),
+                value: fields[6].to_owned(),
+                domain,
+                host_only,
+                path: fields[2].to_owned(),
+                secure: fields[3] == "TRUE",
+                http_only,
+                expires,
+                same_site: None,
+            });
+        }
+        Ok(PersistentStore {
+            jar: Mutex::new(jar),
+            public_suffixes: Box::new(TopLevelDomains),
+        })
+    }
+
+    /// Set the public suffixes on which cookies can't be set.
+    ///
+    /// Default is `TopLevelDomains`. The cookies already stored, or loaded,
+    /// aren't checked again.
+    pub fn set_public_suffixes<P: PublicSuffixes>(&mut self, public_suffixes: P) {
+        self.public_suffixes = Box::new(public_suffixes);
+    }
+
+    /// Save the cookies that aren't session cookies in the Netscape format.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let jar = self.jar.lock().unwrap();
+        writeln!(writer, "# Netscape HTTP Cookie File")?;
+        for &(_, ref cookie) in &jar.cookies {
+            let expires = match cookie.expires {
+                Some(expires) if !cookie.is_expired() => expires,
+                _ => continue,
+            };
+            let secs = expires
+                .duration_since(UNIX_EPOCH)
+                .map(|dur| dur.as_secs())
+                .unwrap_or(0);
+            writeln!(
+                writer,
+                "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                if cookie.host_only { "" } else { "." },
+                cookie.domain,
+                if cookie.host_only { "FALSE" } else { "TRUE" },
+                cookie.path,
+                if cookie.secure { "TRUE" } else { "FALSE" },
+                secs,
+                cookie.name,
+                cookie.value,
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&self) {
+        *self.jar.lock().unwrap() = Jar::default();
+    }
+}
+
+impl CookieStore for PersistentStore {
+    fn set_cookies(&self, set_cookie: &mut dyn Iterator<Item=&HeaderValue>, uri: &Uri) {
+        self.jar.lock().unwrap().set_cookies(set_cookie, uri, &*self.public_suffixes);
+    }
+
+    fn cookies(&self, uri: &Uri) -> Option<HeaderValue> {
+        self.jar.lock().unwrap().header(uri)
+    }
+}
+
+impl Default for PersistentStore {
+    fn default() -> PersistentStore {
+        PersistentStore {
+            jar: Mutex::default(),
+            public_suffixes: Box::new(TopLevelDomains),
+        }
+    }
+}
+
+impl fmt::Debug for PersistentStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PersistentStore")
+            .field("jar", &self.jar)
+            .finish()
+    }
+}
+
+fn invalid_line(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid cookie line: {:?}", line),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(s: &str) -> Uri {
+        s.parse().unwrap()
+    }
+
+    fn cookie(set_cookie: &str, from: &str) -> Cookie {
+        Cookie::parse(set_cookie, &uri(from)).expect("parse")
+    }
+
+    fn send(store: &dyn CookieStore, to: &str) -> Option<String> {
+        store.cookies(&uri(to)).map(|value| value.to_str().unwrap().to_owned())
+    }
+
+    fn set(store: &dyn CookieStore, set_cookie: &str, from: &str) {
+        let value = HeaderValue::from_str(set_cookie).unwrap();
+        store.set_cookies(&mut Some(&value).into_iter(), &uri(from));
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let c = cookie(
+            "sid = a1b2 ; Domain=.Hyper.rs; Path=/api; Secure; HttpOnly; SameSite=Lax; Max-Age=60",
+            "https://www.hyper.rs/docs/index.html",
+        );
+        assert_eq!(c.name(), "sid");
+        assert_eq!(c.value(), "a1b2");
+        assert_eq!(c.domain(), "hyper.rs");
+        assert!(!c.is_host_only());
+        assert_eq!(c.path(), "/api");
+        assert!(c.is_secure());
+        assert!(c.is_http_only());
+        assert_eq!(c.same_site(), Some(SameSite::Lax));
+        assert!(c.expires().unwrap() > SystemTime::now());
+
+        let c = cookie("lang=en", "http://hyper.rs/docs/index.html");
+        assert_eq!(c.domain(), "hyper.rs");
+        assert!(c.is_host_only());
+        assert_eq!(c.path(), "/docs");
+        assert_eq!(c.expires(), None);
+
+        assert!(Cookie::parse("no-equals", &uri("http://hyper.rs")).is_none());
+        assert!(Cookie::parse("=value", &uri("http://hyper.rs")).is_none());
+        assert!(Cookie::parse("a=b; Domain=example.com", &uri("http://hyper.rs")).is_none());
+        assert!(Cookie::parse("a=b; Domain=per.rs", &uri("http://hyper.rs")).is_none());
+    }
+
+    #[test]
+    fn public_suffix_domains() {
+        assert!(Cookie::parse("a=b; Domain=rs", &uri("http://hyper.rs")).is_none());
+        assert!(Cookie::parse("a=b; Domain=.RS", &uri("http://hyper.rs")).is_none());
+
+        // Only for the host itself.
+        let c = cookie("a=b; Domain=localhost", "http://localhost/");
+        assert_eq!(c.domain(), "localhost");
+        assert!(c.is_host_only());
+
+        let co_uk = |domain: &str| domain == "co.uk" || !domain.contains('.');
+        let from = uri("http://www.example.co.uk/");
+        assert!(Cookie::parse("a=b; Domain=co.uk", &from).is_some());
+        assert!(Cookie::parse_with_suffixes("a=b; Domain=co.uk", &from, &co_uk).is_none());
+        assert!(Cookie::parse_with_suffixes("a=b; Domain=example.co.uk", &from, &co_uk).is_some());
+
+        let mut store = MemoryStore::new();
+        store.set_public_suffixes(co_uk);
+        set(&store, "a=1; Domain=co.uk", "http://www.example.co.uk/");
+        set(&store, "b=2; Domain=example.co.uk", "http://www.example.co.uk/");
+        assert_eq!(send(&store, "http://example.co.uk/"), Some("b=2".into()));
+        assert_eq!(send(&store, "http://other.co.uk/"), None);
+    }
+
+    #[test]
+    fn parse_dates() {
+        let expected = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        for date in &[
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "06 november 1994 08:49:37",
+        ] {
+            assert_eq!(parse_date(date), Some(expected), "{:?}", date);
+        }
+        assert_eq!(parse_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(UNIX_EPOCH));
+        assert_eq!(parse_date("Sat, 31 Feb 2019 08:49:37 GMT"), None);
+        assert_eq!(parse_date("Sun, 06 Nov 1994 25:49:37 GMT"), None);
+        assert_eq!(parse_date("tomorrow"), None);
+
+        // Max-Age wins over Expires
+        let c = cookie("a=b; Max-Age=0; Expires=Sun, 06 Nov 2094 08:49:37 GMT", "http://hyper.rs");
+        assert!(c.is_expired());
+    }
+
+    #[test]
+    fn far_future_expiry_saturates() {
+        let max = UNIX_EPOCH + Duration::from_secs(MAX_EXPIRES_SECS);
+        assert_eq!(parse_date("Fri, 31 Dec 9999 23:59:59 GMT"), Some(max));
+
+        for set_cookie in &[
+            "a=b; Max-Age=9223372036854775807",
+            "a=b; Max-Age=99999999999999999999999",
+        ] {
+            let c = cookie(set_cookie, "http://hyper.rs");
+            assert_eq!(c.expires(), Some(max), "{:?}", set_cookie);
+            assert!(!c.is_expired());
+        }
+
+        let store = PersistentStore::new();
+        set(&store, "forever=1; Max-Age=9223372036854775807", "http://hyper.rs/");
+        let mut saved = Vec::new();
+        store.save(&mut saved).unwrap();
+        let text = String::from_utf8(saved.clone()).unwrap();
+        assert!(text.contains(&format!("\t{}\tforever\t1", MAX_EXPIRES_SECS)), "{}", text);
+
+        let loaded = PersistentStore::load(&saved[..]).unwrap();
+        assert_eq!(send(&loaded, "http://hyper.rs/"), Some("forever=1".into()));
+    }
+
+    #[test]
+    fn domain_and_path_matching() {
+        let store = MemoryStore::new();
+        set(&store, "host=1", "http://hyper.rs/");
+        set(&store, "domain=2; Domain=hyper.rs", "http://hyper.rs/");
+        set(&store, "docs=3; Path=/docs", "http://hyper.rs/");
+        set(&store, "secure=4; Secure", "http://hyper.rs/");
+
+        assert_eq!(send(&store, "http://hyper.rs/"), Some("host=1; domain=2".into()));
+        assert_eq!(send(&store, "http://www.hyper.rs/"), Some("domain=2".into()));
+        assert_eq!(send(&store, "http://nothyper.rs/"), None);
+        assert_eq!(
+            send(&store, "http://hyper.rs/docs/guide"),
+            Some("docs=3; host=1; domain=2".into()),
+        );
+        assert_eq!(send(&store, "http://hyper.rs/docsearch"), Some("host=1; domain=2".into()));
+        assert_eq!(
+            send(&store, "https://hyper.rs/"),
+            Some("host=1; domain=2; secure=4".into()),
+        );
+    }
+
+    #[test]
+    fn replace_and_expire() {
+        let store = MemoryStore::new();
+        set(&store, "a=1", "http://hyper.rs/");
+        set(&store, "b=2", "http://hyper.rs/");
+        set(&store, "a=3", "http://hyper.rs/");
+        assert_eq!(send(&store, "http://hyper.rs/"), Some("a=3; b=2".into()));
+
+        set(&store, "a=gone; Expires=Thu, 01 Jan 1970 00:00:00 GMT", "http://hyper.rs/");
+        assert_eq!(send(&store, "http://hyper.rs/"), Some("b=2".into()));
+
+        store.clear();
+        assert_eq!(send(&store, "http://hyper.rs/"), None);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let store = PersistentStore::new();
+        set(&store, "session=1", "http://hyper.rs/");
+        set(&store, "kept=2; Domain=hyper.rs; Path=/docs; Secure; HttpOnly; Max-Age=3600", "https://hyper.rs/");
+
+        let mut saved = Vec::new();
+        store.save(&mut saved).unwrap();
+        let text = String::from_utf8(saved.clone()).unwrap();
+        assert!(!text.contains("session"), "{}", text);
+        assert!(text.contains("#HttpOnly_.hyper.rs\tTRUE\t/docs\tTRUE\t"), "{}", text);
+
+        let loaded = PersistentStore::load(&saved[..]).unwrap();
+        assert_eq!(send(&loaded, "https://www.hyper.rs/docs"), Some("kept=2".into()));
+        assert_eq!(send(&loaded, "http://hyper.rs/docs"), None);
+
+        PersistentStore::load(&b"hyper.rs\tFALSE\t/\n"[..]).unwrap_err();
+    }
+}
@@ -0,0 +1,163 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+use futures_util::future::{self, FutureExt};
+use http::{request, Response};
+
+use crate::body::Body;
+use crate::common::{Future, Pin};
+
+/// A hook into every request sent by a `Client`, and its response.
+///
+/// Interceptors are added to a [`Builder`](super::Builder), and run in
+/// order: `on_request` from the first added to the last, and `on_response`
+/// from the last to the first, so the first interceptor wraps all the
+/// others.
+///
+/// Every attempt at a request is intercepted on its own. When a request is
+/// retried, as with
+/// [`retry_canceled_requests`](super::Builder::retry_canceled_requests),
+/// `on_response` first sees the canceled attempt's error, and may replace it
+/// with a response, or fail with an error of its own, instead of retrying.
+/// Otherwise the interceptors see the request again as it was originally
+/// given, except for its extensions, which keep the changes of the previous
+/// attempt. The `Client` doesn't follow redirects, so each request made to
+/// follow one is intercepted as well.
+///
+/// # Example
+///
+/// ```
+/// # #![feature(async_await)]
+/// use hyper::header::{HeaderValue, AUTHORIZATION};
+/// use hyper::http::request::Parts;
+/// use hyper::client::{Client, Interceptor, InterceptFuture};
+///
+/// struct Auth(HeaderValue);
+///
+/// impl Interceptor for Auth {
+///     fn on_request<'a>(&'a self, req: &'a mut Parts) -> InterceptFuture<'a> {
+///         Box::pin(async move {
+///             req.headers.insert(AUTHORIZATION, self.0.clone());
+///             Ok(())
+///         })
+///     }
+/// }
+///
+/// let client = Client::builder()
+///     .interceptor(Auth(HeaderValue::from_static("Bearer 3xampl3")))
+///     .build_http::<hyper::Body>();
+/// # drop(client);
+/// ```
+pub trait Interceptor: Send + Sync + 'static {
+    /// Change a request before a connection is chosen for it.
+    ///
+    /// Only the head of the request is available, since the type of its
+    /// body is chosen when building the `Client`. The URI may be changed,
+    /// to send the request to another host.
+    ///
+    /// An error fails the request without sending it. The interceptors
+    /// that ran before still see the error in `on_response`.
+    fn on_request<'a>(&'a self, _req: &'a mut request::Parts) -> InterceptFuture<'a> {
+        Box::pin(future::ok(()))
+    }
+
+    /// Inspect or replace the response to a request, or its error.
+    ///
+    /// An error replaces the response with an error of this interceptor.
+    fn on_response<'a>(&'a self, _res: &'a mut crate::Result<Response<Body>>) -> InterceptFuture<'a> {
+        Box::pin(future::ok(()))
+    }
+}
+
+/// The future returned by the methods of an `Interceptor`.
+pub type InterceptFuture<'a> = Pin<Box<dyn Future<Output=Result<(), Box<dyn StdError + Send + Sync>>> + Send + 'a>>;
+
+/// The interceptors of a `Client`.
+#[derive(Clone)]
+pub(super) struct Chain {
+    interceptors: Arc<[Arc<dyn Interceptor>]>,
+}
+
+impl Chain {
+    pub(super) fn new(interceptors: Vec<Arc<dyn Interceptor>>) -> Chain {
+        Chain {
+            interceptors: interceptors.into(),
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.interceptors.is_empty()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.interceptors.len()
+    }
+
+    /// Run `on_request` of every interceptor.
+    ///
+    /// On error, also returns how many interceptors ran, whose
+    /// `on_response` must see the error.
+    pub(super) fn request<'a>(&'a self, req: &'a mut request::Parts)
+        -> impl Future<Output=Result<(), (usize, crate::Error)>> + Send + 'a
+    {
+        async move {
+            for (ran, interceptor) in self.interceptors.iter().enumerate() {
+                if let Err(err) = interceptor.on_request(req).await {
+                    debug!("interceptor failed request: {}", err);
+                    return Err((ran, crate::Error::new_user_interceptor(err)));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Run `on_response` of the first `ran` interceptors, in reverse.
+    pub(super) fn response<'a>(&'a self, ran: usize, res: crate::Result<Response<Body>>)
+        -> impl Future<Output=crate::Result<Response<Body>>> + Send + 'a
+    {
+        self.run_response(ran, res).map(|(res, _failed)| res)
+    }
+
+    /// Run `on_response` of the first `ran` interceptors with the error of
+    /// a canceled attempt, before it is retried.
+    ///
+    /// Returns `None` if the attempt should be retried, or the result to
+    /// return instead if an interceptor replaced the error with a response,
+    /// or failed with an error of its own.
+    pub(super) fn canceled<'a>(&'a self, ran: usize, reason: crate::Error)
+        -> impl Future<Output=Option<crate::Result<Response<Body>>>> + Send + 'a
+    {
+        self.run_response(ran, Err(reason)).map(|(res, failed)| {
+            match res {
+                Err(_) if !failed => None,
+                res => Some(res),
+            }
+        })
+    }
+
+    /// Runs `on_response`, also returning whether any interceptor failed.
+    fn run_response<'a>(&'a self, ran: usize, mut res: crate::Result<Response<Body>>)
+        -> impl Future<Output=(crate::Result<Response<Body>>, bool)> + Send + 'a
+    {
+        async move {
+            let mut failed = false;
+            for interceptor in self.interceptors[..ran].iter().rev() {
+                if let Err(err) = interceptor.on_response(&mut res).await {
+                    debug!("interceptor failed response: {}", err);
+                    res = Err(crate::Error::new_user_interceptor(err));
+                    failed = true;
+                }
+            }
+            (res, failed)
+        }
+    }
+}
+
+impl fmt::Debug for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Chain")
+            .field("len", &self.interceptors.len())
+            .finish()
+    }
+}
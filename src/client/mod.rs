@@ -62,34 +62,47 @@
 use std::fmt;
 use std::mem;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use futures_channel::oneshot;
 use futures_util::future::{self, FutureExt as _, Either};
 use futures_util::try_future::TryFutureExt as _;
-use http::{Method, Request, Response, Uri, Version};
-use http::header::{HeaderValue, HOST};
+use http::{HeaderMap, Method, Request, Response, Uri, Version};
+use http::header::{HeaderValue, COOKIE, HOST, SET_COOKIE};
 use http::uri::Scheme;
 
 use crate::body::{Body, Payload};
 use crate::common::{lazy as hyper_lazy, Lazy, Future, Pin, Poll, task};
-use self::connect::{Alpn, Connect, Connected, Destination};
+use crate::error::Reason;
+use self::cookie::CookieStore;
+use self::connect::{Alpn, Connect, Connected, ConnectionInfo, Destination, LocalAddress, ALPN_AUTO, ALPN_H2};
+use self::intercept::Chain;
 use self::pool::{Key as PoolKey, Pool, Poolable, Pooled, Reservation};
 
 #[cfg(feature = "runtime")] pub use self::connect::HttpConnector;
+pub use self::intercept::{InterceptFuture, Interceptor};
 
 pub mod conn;
 pub mod connect;
+pub mod cookie;
 pub(crate) mod dispatch;
+mod intercept;
 mod pool;
 #[cfg(test)]
 mod tests;
 
+/// How many times a request is retried after the server refused its HTTP/2
+/// stream, so a server refusing everything doesn't keep it looping.
+const MAX_REFUSED_STREAM_RETRIES: usize = 3;
+
 /// A Client to make outgoing HTTP requests.
 pub struct Client<C, B = Body> {
     config: Config,
     conn_builder: conn::Builder,
     connector: Arc<C>,
+    cookies: Option<Arc<dyn CookieStore>>,
+    interceptors: Chain,
     pool: Pool<PoolClient<B>>,
 }
 
@@ -238,47 +251,104 @@ where C: Connect + Sync + 'static,
             other => return ResponseFuture::error_version(other),
         };
 
-        let domain = match extract_domain(req.uri_mut(), is_http_connect) {
-            Ok(s) => s,
+        let pool_key = match pool_key(&mut req) {
+            Ok(key) => key,
             Err(err) => {
                 return ResponseFuture::new(Box::new(future::err(err)));
             }
         };
 
-        let pool_key = Arc::new(domain.to_string());
         ResponseFuture::new(Box::new(self.retryably_send_request(req, pool_key)))
     }
 
     fn retryably_send_request(&self, req: Request<B>, pool_key: PoolKey) -> impl Future<Output=crate::Result<Response<Body>>> {
         let client = self.clone();
-        let uri = req.uri().clone();
-
-        let mut send_fut = client.send_request(req, pool_key.clone());
-        future::poll_fn(move |cx| loop {
-            match ready!(Pin::new(&mut send_fut).poll(cx)) {
-                Ok(resp) => return Poll::Ready(Ok(resp)),
-                Err(ClientError::Normal(err)) => return Poll::Ready(Err(err)),
-                Err(ClientError::Canceled {
-                    connection_reused,
-                    mut req,
-                    reason,
-                }) => {
-                    if !client.config.retry_canceled_requests || !connection_reused {
-                        // if client disabled, don't retry
-                        // a fresh connection means we definitely can't retry
-                        return Poll::Ready(Err(reason));
-                    }
+        let interceptors = client.interceptors.clone();
 
-                    trace!("unstarted request canceled, trying again (reason={:?})", reason);
-                    *req.uri_mut() = uri.clone();
-                    send_fut = client.send_request(req, pool_key.clone());
+        async move {
+            // Each attempt is intercepted from the request as it was given,
+            // not as the interceptors changed it for the previous attempt.
+            let original = if interceptors.is_empty() {
+                None
+            } else {
+                Some(OriginalHead::new(&req))
+            };
+            let uri = req.uri().clone();
+            let mut req = req;
+            let mut refused_retries = 0;
+            loop {
+                let (mut parts, body) = req.into_parts();
+                if let Err((ran, err)) = interceptors.request(&mut parts).await {
+                    return interceptors.response(ran, Err(err)).await;
                 }
+                let ran = interceptors.len();
+                let mut intercepted = Request::from_parts(parts, body);
+
+                // The interceptors may have changed the URI, and so the
+                // pool key.
+                let key = if original.is_some() {
+                    self::pool_key(&mut intercepted)
+                } else {
+                    Ok(pool_key.clone())
+                };
+                let res = match key {
+                    Ok(key) => client.send_request(intercepted, key).await,
+                    Err(err) => Err(ClientError::Normal(err)),
+                };
+
+                let res = match res {
+                    Ok(resp) => Ok(resp),
+                    Err(ClientError::Normal(err)) => Err(err),
+                    Err(ClientError::Canceled {
+                        connection_reused,
+                        req: mut canceled,
+                        reason,
+                    }) => {
+                        // A stream refused by the server was never processed,
+                        // even on a fresh connection.
+                        let retry = if reason.h2_reason() == Some(Reason::REFUSED_STREAM) {
+                            refused_retries += 1;
+                            refused_retries <= MAX_REFUSED_STREAM_RETRIES
+                        } else {
+                            connection_reused
+                        };
+                        if !client.config.retry_canceled_requests || !retry {
+                            // if client disabled, don't retry
+                            // a fresh connection means we definitely can't retry
+                            Err(reason)
+                        } else {
+                            trace!("unstarted request canceled, trying again (reason={:?})", reason);
+                            // The interceptors see this attempt fail, and
+                            // may answer it instead of retrying.
+                            if let Some(res) = interceptors.canceled(ran, reason).await {
+                                return res;
+                            }
+                            match original {
+                                Some(ref original) => original.restore(&mut canceled),
+                                None => *canceled.uri_mut() = uri.clone(),
+                            }
+                            req = canceled;
+                            continue;
+                        }
+                    }
+                };
+                return interceptors.response(ran, res).await;
             }
-        })
+        }
     }
 
     fn send_request(&self, mut req: Request<B>, pool_key: PoolKey) -> impl Future<Output=Result<Response<Body>, ClientError<B>>> + Unpin {
-        let conn = self.connection_for(req.uri().clone(), pool_key);
+        // Cookies are added last, once interceptors have chosen the URI.
+        let cookies = self.cookies.clone().map(|store| {
+            if !req.headers().contains_key(COOKIE) {
+                if let Some(value) = store.cookies(req.uri()) {
+                    req.headers_mut().insert(COOKIE, value);
+                }
+            }
+            (store, req.uri().clone())
+        });
+
+        let conn = self.connection_for(self.destination(&req, &pool_key), pool_key);
 
         let set_host = self.config.set_host;
         let executor = self.conn_builder.exec.clone();
@@ -309,9 +379,6 @@ where C: Connect + Sync + 'static,
                 } else {
                     origin_form(req.uri_mut());
                 };
-            } else if req.method() == &Method::CONNECT {
-                debug!("client does not support CONNECT requests over HTTP2");
-                return Either::Left(future::err(ClientError::Normal(crate::Error::new_user_unsupported_request_method())));
             }
 
             let fut = pooled.send_request_retryable(req)
@@ -319,10 +386,17 @@ where C: Connect + Sync + 'static,
 
             // If the Connector included 'extra' info, add to Response...
             let extra_info = pooled.conn_info.extra.clone();
+            let is_reused = pooled.is_reused();
             let fut = fut.map_ok(move |mut res| {
+                if let Some((store, uri)) = cookies {
+                    store.set_cookies(&mut res.headers().get_all(SET_COOKIE).iter(), &uri);
+                }
                 if let Some(extra) = extra_info {
                     extra.set(&mut res);
                 }
+                if let Some(info) = res.extensions_mut().get_mut::<ConnectionInfo>() {
+                    info.is_reused = is_reused;
+                }
                 res
             });
 
@@ -334,10 +408,10 @@ where C: Connect + Sync + 'static,
             // To counteract this, we must check if our senders 'want' channel
             // has been closed after having tried to send. If so, error out...
             if pooled.is_closed() {
-                return Either::Right(Either::Left(fut));
+                return Either::Left(fut);
             }
 
-            Either::Right(Either::Right(fut
+            Either::Right(fut
                 .map_ok(move |mut res| {
                     // If pooled is HTTP/2, we can toss this reference immediately.
                     //
@@ -381,11 +455,24 @@ where C: Connect + Sync + 'static,
                         }
                     }
                     res
-                })))
+                }))
         })
     }
 
-    fn connection_for(&self, uri: Uri, pool_key: PoolKey)
+    fn destination(&self, req: &Request<B>, pool_key: &PoolKey) -> Destination {
+        let mut dst = Destination::new(req.uri().clone());
+        dst.alpn_protocols = if self.config.ver == Ver::Http2 {
+            ALPN_H2
+        } else {
+            ALPN_AUTO
+        };
+        dst.is_connect = req.method() == &Method::CONNECT;
+        dst.local_address = req.extensions().get::<LocalAddress>().map(LocalAddress::ip);
+        dst.pool_partition = pool_key.to_string();
+        dst
+    }
+
+    fn connection_for(&self, dst: Destination, pool_key: PoolKey)
         -> impl Future<Output=Result<Pooled<PoolClient<B>>, ClientError<B>>>
     {
         // This actually races 2 different futures to try to get a ready
@@ -402,7 +489,7 @@ where C: Connect + Sync + 'static,
         //   connection future is spawned into the runtime to complete,
         //   and then be inserted into the pool as an idle connection.
         let checkout = self.pool.checkout(pool_key.clone());
-        let connect = self.connect_to(uri, pool_key);
+        let connect = self.connect_to(dst, pool_key);
 
         let executor = self.conn_builder.exec.clone();
         // The order of the `select` is depended on below...
@@ -464,7 +551,7 @@ where C: Connect + Sync + 'static,
             })
     }
 
-    fn connect_to(&self, uri: Uri, pool_key: PoolKey)
+    fn connect_to(&self, dst: Destination, pool_key: PoolKey)
         -> impl Lazy<Output=crate::Result<Pooled<PoolClient<B>>>> + Unpin
     {
         let executor = self.conn_builder.exec.clone();
@@ -473,9 +560,6 @@ where C: Connect + Sync + 'static,
         let ver = self.config.ver;
         let is_ver_h2 = ver == Ver::Http2;
         let connector = self.connector.clone();
-        let dst = Destination {
-            uri,
-        };
         hyper_lazy(move || {
             // Try to take a "connecting lock".
             //
@@ -489,6 +573,9 @@ where C: Connect + Sync + 'static,
                     return Either::Right(future::err(canceled));
                 }
             };
+            // Counted until the connection task ends, or connecting fails.
+            let slot = pool.slot();
+            let connect_start = Instant::now();
             Either::Left(connector.connect(dst)
                 .map_err(crate::Error::new_connect)
                 .and_then(move |(io, connected)| {
@@ -512,6 +599,12 @@ where C: Connect + Sync + 'static,
                         connecting
                     };
                     let is_h2 = is_ver_h2 || connected.alpn == Alpn::H2;
+                    let connected = connected.extra(ConnectionInfo {
+                        id: next_connection_id(),
+                        version: if is_h2 { Version::HTTP_2 } else { Version::HTTP_11 },
+                        connect_duration: connect_start.elapsed(),
+                        is_reused: false,
+                    });
                     Either::Left(conn_builder
                         .http2_only(is_h2)
                         .handshake(io)
@@ -519,7 +612,7 @@ where C: Connect + Sync + 'static,
                             trace!("handshake complete, spawning background dispatcher task");
                             let bg = executor.execute(conn.map_err(|e| {
                                 debug!("client connection error: {}", e)
-                            }).map(|_| ()));
+                            }).map(move |_| drop(slot)));
 
                             // This task is critical, so an execute error
                             // should be returned.
@@ -547,12 +640,46 @@ where C: Connect + Sync + 'static,
     }
 }
 
+fn next_connection_id() -> u64 {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed) as u64
+}
+
+/// A `Client` is a tower `Service`, ready when its pool can serve another
+/// request, as limited by
+/// [`Builder::max_connections`](Builder::max_connections).
+///
+/// Readiness can't know where the next request goes, so an idle connection
+/// to any host makes the `Client` ready. A request to another host then
+/// closes that idle connection to open its own.
+impl<C, B> tower_service::Service<Request<B>> for Client<C, B>
+where C: Connect + Sync + 'static,
+      C::Transport: 'static,
+      C::Future: 'static,
+      B: Payload + Unpin + Send + 'static,
+      B::Data: Send,
+{
+    type Response = Response<Body>;
+    type Error = crate::Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.pool.poll_capacity(cx).map(Ok)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        self.request(req)
+    }
+}
+
 impl<C, B> Clone for Client<C, B> {
     fn clone(&self) -> Client<C, B> {
         Client {
             config: self.config.clone(),
             conn_builder: self.conn_builder.clone(),
             connector: self.connector.clone(),
+            cookies: self.cookies.clone(),
+            interceptors: self.interceptors.clone(),
             pool: self.pool.clone(),
         }
     }
@@ -690,6 +817,45 @@ where
     fn can_share(&self) -> bool {
         self.is_http2()
     }
+
+    fn is_saturated(&self) -> bool {
+        match self.tx {
+            PoolTx::Http1(_) => false,
+            PoolTx::Http2(ref tx) => tx.is_saturated(),
+        }
+    }
+
+    fn is_valid_for(&self, host: &str) -> bool {
+        self.conn_info.is_valid_for(host)
+    }
+}
+
+/// The head of a request before any interceptor changed it.
+///
+/// Extensions can't be cloned, so a retry keeps those of the last attempt.
+struct OriginalHead {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    headers: HeaderMap,
+}
+
+impl OriginalHead {
+    fn new<B>(req: &Request<B>) -> OriginalHead {
+        OriginalHead {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            headers: req.headers().clone(),
+        }
+    }
+
+    fn restore<B>(&self, req: &mut Request<B>) {
+        *req.method_mut() = self.method.clone();
+        *req.uri_mut() = self.uri.clone();
+        *req.version_mut() = self.version;
+        *req.headers_mut() = self.headers.clone();
+    }
 }
 
 // ===== impl ClientError =====
@@ -781,6 +947,16 @@ fn authority_form(uri: &mut Uri) {
     };
 }
 
+fn pool_key<B>(req: &mut Request<B>) -> crate::Result<PoolKey> {
+    let is_http_connect = req.method() == &Method::CONNECT;
+    let domain = extract_domain(req.uri_mut(), is_http_connect)?;
+    // Connections bound to a requested local address are kept apart.
+    Ok(match req.extensions().get::<LocalAddress>() {
+        Some(local) => Arc::new(format!("{} {}", domain, local.ip())),
+        None => Arc::new(domain),
+    })
+}
+
 fn extract_domain(uri: &mut Uri, is_http_connect: bool) -> crate::Result<String> {
     let uri_clone = uri.clone();
     match (uri_clone.scheme_part(), uri_clone.authority_part()) {
@@ -840,6 +1016,8 @@ fn set_scheme(uri: &mut Uri, scheme: Scheme) {
 pub struct Builder {
     client_config: Config,
     conn_builder: conn::Builder,
+    cookies: Option<Arc<dyn CookieStore>>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
     pool_config: pool::Config,
 }
 
@@ -852,10 +1030,15 @@ impl Default for Builder {
                 ver: Ver::Auto,
             },
             conn_builder: conn::Builder::new(),
+            cookies: None,
+            interceptors: Vec::new(),
             pool_config: pool::Config {
                 enabled: true,
                 keep_alive_timeout: Some(Duration::from_secs(90)),
                 max_idle_per_host: ::std::usize::MAX,
+                max_http2_per_host: 1,
+                http2_coalescing: false,
+                max_connections: ::std::usize::MAX,
             },
         }
     }
@@ -974,6 +1157,116 @@ impl Builder {
         self
     }
 
+    /// Sets whether to use an adaptive flow control.
+    ///
+    /// Enabling this will override the limit set in
+    /// `http2_initial_connection_window_size`. The connection window starts
+    /// at the spec default, and grows as the bandwidth-delay product
+    /// measured with `PING` frames allows, up to
+    /// `http2_max_adaptive_window_size`.
+    ///
+    /// Only the connection window adapts. Stream windows can't be resized
+    /// once the connection is up, so each stream keeps the window set with
+    /// `http2_initial_stream_window_size`.
+    ///
+    /// Default is false.
+    pub fn http2_adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        self.conn_builder.http2_adaptive_window(enabled);
+        self
+    }
+
+    /// Sets the largest window an adaptive flow control may grow to.
+    ///
+    /// Passing `None` will do nothing. Does nothing unless
+    /// `http2_adaptive_window` is enabled.
+    ///
+    /// Default is 16MB.
+    pub fn http2_max_adaptive_window_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        self.conn_builder.http2_max_adaptive_window_size(sz.into());
+        self
+    }
+
+    /// Sets the [`SETTINGS_MAX_FRAME_SIZE`][spec] option for HTTP2, the
+    /// largest frame payload this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_FRAME_SIZE
+    pub fn http2_max_frame_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        self.conn_builder.http2_max_frame_size(sz.into());
+        self
+    }
+
+    /// Sets the [`SETTINGS_MAX_HEADER_LIST_SIZE`][spec] option for HTTP2,
+    /// the largest header list this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16MB.
+    ///
+    /// The HPACK table size (`SETTINGS_HEADER_TABLE_SIZE`) and the send
+    /// buffer size of each stream are not supported, since h2 0.2 has no
+    /// option for them. The table size stays at the spec default of 4KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_HEADER_LIST_SIZE
+    pub fn http2_max_header_list_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        self.conn_builder.http2_max_header_list_size(sz.into());
+        self
+    }
+
+    /// Sets an interval for HTTP2 Ping frames should be sent to keep a
+    /// connection alive.
+    ///
+    /// If a ping is not acknowledged in time, the connection is closed and
+    /// removed from the pool. Pass `None` to disable HTTP2 keep-alive.
+    ///
+    /// Default is currently disabled.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_interval(&mut self, interval: impl Into<Option<Duration>>) -> &mut Self {
+        self.conn_builder.http2_keep_alive_interval(interval);
+        self
+    }
+
+    /// Sets a timeout for receiving an acknowledgement of the keep-alive ping.
+    ///
+    /// If the ping is not acknowledged within the timeout, the connection will
+    /// be closed. Does nothing if `http2_keep_alive_interval` is disabled.
+    ///
+    /// Default is 20 seconds.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.conn_builder.http2_keep_alive_timeout(timeout);
+        self
+    }
+
+    /// Sets whether HTTP2 keep-alive should apply while the connection is idle.
+    ///
+    /// If disabled, keep-alive pings are only sent while there are open
+    /// request/responses streams. If enabled, pings are also sent when no
+    /// streams are active. Does nothing if `http2_keep_alive_interval` is
+    /// disabled.
+    ///
+    /// Default is `false`.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_while_idle(&mut self, enabled: bool) -> &mut Self {
+        self.conn_builder.http2_keep_alive_while_idle(enabled);
+        self
+    }
+
     /// Sets the maximum idle connection per host allowed in the pool.
     ///
     /// Default is `usize::MAX` (no limit).
@@ -982,6 +1275,70 @@ impl Builder {
         self
     }
 
+    /// Sets the maximum number of HTTP/2 connections opened to the same host.
+    ///
+    /// An HTTP/2 connection is shared by all requests to its host. Once every
+    /// connection has as many open streams as the server's
+    /// `SETTINGS_MAX_CONCURRENT_STREAMS` allows, another connection is opened,
+    /// up to this limit. Requests are spread across the connections that
+    /// still have capacity.
+    ///
+    /// Default is `1`.
+    pub fn http2_max_connections_per_host(&mut self, max: usize) -> &mut Self {
+        self.pool_config.max_http2_per_host = max;
+        self
+    }
+
+    /// Sets the maximum number of connections the pool keeps open, to all
+    /// hosts, including those still connecting.
+    ///
+    /// When a new connection is needed at this limit, the idle connection
+    /// used least recently is closed to make room. Used as a tower
+    /// `Service`, the `Client` isn't ready while every connection is busy.
+    /// Requests sent anyway still open connections past the limit.
+    ///
+    /// Default is `usize::MAX` (no limit).
+    pub fn max_connections(&mut self, max: usize) -> &mut Self {
+        self.pool_config.max_connections = max;
+        self
+    }
+
+    /// Sets whether HTTP/2 connections may be reused across hosts.
+    ///
+    /// If enabled, a request to a host without a pooled connection can use
+    /// an HTTP/2 connection to another host, with the same scheme and port,
+    /// whose connector reported it as valid for this host through
+    /// [`Connected::authorities`](connect::Connected::authorities).
+    ///
+    /// Default is `false`.
+    pub fn http2_connection_coalescing(&mut self, enabled: bool) -> &mut Self {
+        self.pool_config.http2_coalescing = enabled;
+        self
+    }
+
+    /// Add an interceptor of every request and response.
+    ///
+    /// Interceptors run in the order they are added. See
+    /// [`Interceptor`](Interceptor) for details.
+    pub fn interceptor<I: Interceptor>(&mut self, interceptor: I) -> &mut Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Store cookies of responses, and send them with requests.
+    ///
+    /// The `Set-Cookie` headers of every response are given to `store`,
+    /// and requests without a `Cookie` header get one with the matching
+    /// cookies of `store`. This happens after the interceptors change a
+    /// request, and before they see its response. See the
+    /// [`cookie`](cookie) module for the provided stores.
+    ///
+    /// Default is no cookie store.
+    pub fn cookie_store<S: CookieStore>(&mut self, store: Arc<S>) -> &mut Self {
+        self.cookies = Some(store);
+        self
+    }
+
     /// Set whether to retry requests that get disrupted before ever starting
     /// to write.
     ///
@@ -989,6 +1346,10 @@ impl Builder {
     /// connection, and then encounters an error immediately as the idle
     /// connection was found to be unusable.
     ///
+    /// This also includes HTTP/2 requests without a body whose stream is
+    /// reset by the server with `REFUSED_STREAM`, since those were never
+    /// processed, even on a new connection. These are retried up to 3 times.
+    ///
     /// When this is set to `false`, the related `ResponseFuture` would instead
     /// resolve to an `Error::Cancel`.
     ///
@@ -1048,6 +1409,8 @@ impl Builder {
             config: self.client_config,
             conn_builder: self.conn_builder.clone(),
             connector: Arc::new(connector),
+            cookies: self.cookies.clone(),
+            interceptors: Chain::new(self.interceptors.clone()),
             pool: Pool::new(self.pool_config, &self.conn_builder.exec),
         }
     }
@@ -1058,6 +1421,8 @@ impl fmt::Debug for Builder {
         f.debug_struct("Builder")
             .field("client_config", &self.client_config)
             .field("conn_builder", &self.conn_builder)
+            .field("cookies", &self.cookies.is_some())
+            .field("interceptors", &self.interceptors.len())
             .field("pool_config", &self.pool_config)
             .finish()
     }
@@ -2,12 +2,15 @@ use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Mutex, Weak};
+use std::task::Waker;
 use std::time::{Duration, Instant};
 
 use futures_channel::oneshot;
 #[cfg(feature = "runtime")]
 use tokio_timer::Interval;
 
+use http::Uri;
+
 use crate::common::{Exec, Future, Pin, Poll, Unpin, task};
 use super::Ver;
 
@@ -16,6 +19,7 @@ use super::Ver;
 pub(super) struct Pool<T> {
     // If the pool is disabled, this is None.
     inner: Option<Arc<Mutex<PoolInner<T>>>>,
+    capacity: Arc<Capacity>,
 }
 
 // Before using a pooled connection, make sure the sender is not dead.
@@ -30,6 +34,11 @@ pub(super) trait Poolable: Unpin + Send + Sized + 'static {
     /// Allows for HTTP/2 to return a shared reservation.
     fn reserve(self) -> Reservation<Self>;
     fn can_share(&self) -> bool;
+    /// Whether a shared connection is already using all the streams its
+    /// peer allows, so another connection should be preferred.
+    fn is_saturated(&self) -> bool;
+    /// Whether a shared connection may also be used for requests to `host`.
+    fn is_valid_for(&self, host: &str) -> bool;
 }
 
 /// When checking out a pooled connection, it might be that the connection
@@ -61,6 +70,12 @@ struct PoolInner<T> {
     // state, waiting to receive a new Request to send on the socket.
     idle: HashMap<Key, Vec<Idle<T>>>,
     max_idle_per_host: usize,
+    // The number of shared (HTTP/2) connections allowed for the same host,
+    // only opened when the existing ones are saturated.
+    max_shared_per_host: usize,
+    // Whether shared connections of other keys can be used for a key
+    // whose host they are valid for.
+    coalesce: bool,
     // These are outstanding Checkouts that are waiting for a socket to be
     // able to send a Request one. This is used when "racing" for a new
     // connection.
@@ -78,6 +93,26 @@ struct PoolInner<T> {
     #[cfg(feature = "runtime")]
     exec: Exec,
     timeout: Option<Duration>,
+    capacity: Arc<Capacity>,
+}
+
+/// Counts the open connections of a pool, including those connecting.
+struct Capacity {
+    max: usize,
+    state: Mutex<CapacityState>,
+}
+
+struct CapacityState {
+    open: usize,
+    // Tasks waiting for a connection to close or become idle.
+    waiters: Vec<Waker>,
+}
+
+/// A connection counted in the capacity of a pool, until dropped.
+///
+/// This is held by the task of the connection.
+pub(super) struct Slot {
+    capacity: Arc<Capacity>,
 }
 
 // This is because `Weak::new()` *allocates* space for `T`, even if it
@@ -89,10 +124,20 @@ pub(super) struct Config {
     pub(super) enabled: bool,
     pub(super) keep_alive_timeout: Option<Duration>,
     pub(super) max_idle_per_host: usize,
+    pub(super) max_http2_per_host: usize,
+    pub(super) http2_coalescing: bool,
+    pub(super) max_connections: usize,
 }
 
 impl<T> Pool<T> {
     pub fn new(config: Config, __exec: &Exec) -> Pool<T> {
+        let capacity = Arc::new(Capacity {
+            max: config.max_connections,
+            state: Mutex::new(CapacityState {
+                open: 0,
+                waiters: Vec::new(),
+            }),
+        });
         let inner = if config.enabled {
              Some(Arc::new(Mutex::new(PoolInner {
                 connecting: HashSet::new(),
@@ -100,10 +145,13 @@ impl<T> Pool<T> {
                 #[cfg(feature = "runtime")]
                 idle_interval_ref: None,
                 max_idle_per_host: config.max_idle_per_host,
+                max_shared_per_host: config.max_http2_per_host,
+                coalesce: config.http2_coalescing,
                 waiters: HashMap::new(),
                 #[cfg(feature = "runtime")]
                 exec: __exec.clone(),
                 timeout: config.keep_alive_timeout,
+                capacity: capacity.clone(),
              })))
         } else {
             None
@@ -111,6 +159,7 @@ impl<T> Pool<T> {
 
         Pool {
             inner,
+            capacity,
         }
     }
 
@@ -118,6 +167,51 @@ impl<T> Pool<T> {
         self.inner.is_some()
     }
 
+    /// Count a new connection, closing the oldest idle connection if the
+    /// pool is at capacity.
+    pub(super) fn slot(&self) -> Slot {
+        let open = {
+            let mut state = self.capacity.state.lock().unwrap();
+            state.open += 1;
+            state.open
+        };
+        if open > self.capacity.max {
+            if let Some(ref enabled) = self.inner {
+                enabled.lock().unwrap().evict_oldest();
+            }
+        }
+        Slot {
+            capacity: self.capacity.clone(),
+        }
+    }
+
+    /// Returns `Ready` when the pool can serve another request, because
+    /// it is below its maximum of connections, or has an idle one.
+    ///
+    /// The request isn't known yet, so an idle connection to any host
+    /// counts. If the request then needs a new connection, that idle
+    /// connection is closed to make room for it.
+    pub(super) fn poll_capacity(&self, cx: &mut task::Context<'_>) -> Poll<()> {
+        {
+            let mut state = self.capacity.state.lock().unwrap();
+            if state.open < self.capacity.max {
+                return Poll::Ready(());
+            }
+            // Register before looking at the idle connections, since the
+            // `PoolInner` wakes while locked, and must not wait on this lock.
+            if !state.waiters.iter().any(|w| w.will_wake(cx.waker())) {
+                state.waiters.push(cx.waker().clone());
+            }
+        }
+        if let Some(ref enabled) = self.inner {
+            if enabled.lock().unwrap().idle.values().any(|list| !list.is_empty()) {
+                return Poll::Ready(());
+            }
+        }
+        trace!("pool at capacity");
+        Poll::Pending
+    }
+
     #[cfg(test)]
     pub(super) fn no_timer(&self) {
         // Prevent an actual interval from being created for this pool...
@@ -266,10 +360,15 @@ impl<T: Poolable> Pool<T> {
 struct IdlePopper<'a, T: 'a> {
     key: &'a Key,
     list: &'a mut Vec<Idle<T>>,
+    max_shared: usize,
 }
 
 impl<'a, T: Poolable + 'a> IdlePopper<'a, T> {
     fn pop(self, expiration: &Expiration) -> Option<Idle<T>> {
+        if self.list.last().map(|entry| entry.value.can_share()).unwrap_or(false) {
+            return self.pop_shared(expiration);
+        }
+
         while let Some(entry) = self.list.pop() {
             // If the connection has been closed, or is older than our idle
             // timeout, simply drop it and keep looking...
@@ -309,13 +408,69 @@ impl<'a, T: Poolable + 'a> IdlePopper<'a, T> {
 
         None
     }
+
+    /// Shared connections always stay in the list, so pick the first one
+    /// with spare streams. Since the reservation is pushed back to the end,
+    /// requests are spread across the connections in turn.
+    ///
+    /// If every connection is saturated and more are allowed, returns `None`
+    /// so that a new connection is made.
+    fn pop_shared(self, expiration: &Expiration) -> Option<Idle<T>> {
+        let key = self.key;
+        self.list.retain(|entry| {
+            if !entry.value.is_open() {
+                trace!("removing closed connection for {:?}", key);
+                false
+            } else if expiration.expires(entry.idle_at) {
+                trace!("removing expired connection for {:?}", key);
+                false
+            } else {
+                true
+            }
+        });
+
+        if self.list.is_empty() {
+            return None;
+        }
+
+        let idx = match self.list.iter().position(|entry| !entry.value.is_saturated()) {
+            Some(idx) => idx,
+            None if self.list.len() < self.max_shared => {
+                trace!("all HTTP/2 connections saturated for {:?}", key);
+                return None;
+            },
+            None => 0,
+        };
+
+        let entry = self.list.remove(idx);
+        let value = match entry.value.reserve() {
+            Reservation::Shared(to_reinsert, to_checkout) => {
+                self.list.push(Idle {
+                    idle_at: Instant::now(),
+                    value: to_reinsert,
+                });
+                to_checkout
+            },
+            Reservation::Unique(unique) => {
+                unique
+            }
+        };
+
+        Some(Idle {
+            idle_at: entry.idle_at,
+            value,
+        })
+    }
 }
 
 impl<T: Poolable> PoolInner<T> {
     fn put(&mut self, key: Key, value: T, __pool_ref: &Arc<Mutex<PoolInner<T>>>) {
-        if value.can_share() && self.idle.contains_key(&key) {
-            trace!("put; existing idle HTTP/2 connection for {:?}", key);
-            return;
+        if value.can_share() {
+            let shared = self.idle.get(&key).map(|list| list.len()).unwrap_or(0);
+            if shared >= self.max_shared_per_host {
+                trace!("put; existing idle HTTP/2 connection for {:?}", key);
+                return;
+            }
         }
         trace!("put; add idle connection for {:?}", key);
         let mut remove_waiters = false;
@@ -372,6 +527,7 @@ impl<T: Poolable> PoolInner<T> {
                         idle_at: Instant::now(),
                     });
                 }
+                self.capacity.wake();
 
                 #[cfg(feature = "runtime")]
                 {
@@ -382,6 +538,52 @@ impl<T: Poolable> PoolInner<T> {
         }
     }
 
+    /// Look for a shared connection of another key, with the same scheme,
+    /// port and partition, that is valid for the host of `key`.
+    fn coalesced(&mut self, key: &Key, expiration: &Expiration) -> Option<Idle<T>> {
+        let (scheme, host, port, partition) = key_origin(key)?;
+        for (other, list) in self.idle.iter_mut() {
+            if other == key {
+                continue;
+            }
+            match key_origin(other) {
+                Some((ref s, _, p, part)) if *s == scheme && p == port && part == partition => (),
+                _ => continue,
+            }
+
+            let idx = list.iter().position(|entry| {
+                entry.value.can_share()
+                    && entry.value.is_open()
+                    && !expiration.expires(entry.idle_at)
+                    && !entry.value.is_saturated()
+                    && entry.value.is_valid_for(&host)
+            });
+
+            if let Some(idx) = idx {
+                let entry = list.remove(idx);
+                trace!("coalescing {:?} onto HTTP/2 connection for {:?}", key, other);
+                let value = match entry.value.reserve() {
+                    Reservation::Shared(to_reinsert, to_checkout) => {
+                        list.push(Idle {
+                            idle_at: Instant::now(),
+                            value: to_reinsert,
+                        });
+                        to_checkout
+                    },
+                    Reservation::Unique(unique) => {
+                        unique
+                    }
+                };
+                return Some(Idle {
+                    idle_at: entry.idle_at,
+                    value,
+                });
+            }
+        }
+
+        None
+    }
+
     /// A `Connecting` task is complete. Not necessarily successfully,
     /// but the lock is going away, so clean up.
     fn connected(&mut self, key: &Key) {
@@ -428,6 +630,30 @@ impl<T: Poolable> PoolInner<T> {
 }
 
 impl<T> PoolInner<T> {
+    /// Close the idle connection that was used least recently, to make
+    /// room for a new one.
+    fn evict_oldest(&mut self) {
+        let oldest = self.idle
+            .iter()
+            .flat_map(|(key, list)| {
+                list.iter().enumerate().map(move |(idx, entry)| (entry.idle_at, key, idx))
+            })
+            .min_by_key(|&(idle_at, _, _)| idle_at)
+            .map(|(_, key, idx)| (key.clone(), idx));
+
+        if let Some((key, idx)) = oldest {
+            trace!("pool at capacity, evicting idle connection for {:?}", key);
+            let mut remove_key = false;
+            if let Some(list) = self.idle.get_mut(&key) {
+                list.remove(idx);
+                remove_key = list.is_empty();
+            }
+            if remove_key {
+                self.idle.remove(&key);
+            }
+        }
+    }
+
     /// Any `FutureResponse`s that were created will have made a `Checkout`,
     /// and possibly inserted into the pool that it is waiting for an idle
     /// connection. If a user ever dropped that future, we need to clean out
@@ -480,10 +706,36 @@ impl<T> Clone for Pool<T> {
     fn clone(&self) -> Pool<T> {
         Pool {
             inner: self.inner.clone(),
+            capacity: self.capacity.clone(),
+        }
+    }
+}
+
+impl Capacity {
+    fn wake(&self) {
+        let waiters = {
+            let mut state = self.state.lock().unwrap();
+            ::std::mem::replace(&mut state.waiters, Vec::new())
+        };
+        for waker in waiters {
+            waker.wake();
         }
     }
 }
 
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.capacity.state.lock().unwrap().open -= 1;
+        self.capacity.wake();
+    }
+}
+
+impl fmt::Debug for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad("Slot")
+    }
+}
+
 /// A wrapped poolable value that tries to reinsert to the Pool on Drop.
 // Note: The bounds `T: Poolable` is needed for the Drop impl.
 pub(super) struct Pooled<T: Poolable> {
@@ -594,32 +846,36 @@ impl<T: Poolable> Checkout<T> {
         let entry = {
             let mut inner = self.pool.inner.as_ref()?.lock().unwrap();
             let expiration = Expiration::new(inner.timeout);
+            let max_shared = inner.max_shared_per_host;
             let maybe_entry = inner.idle.get_mut(&self.key)
-                .and_then(|list| {
+                .map(|list| {
                     trace!("take? {:?}: expiration = {:?}", self.key, expiration.0);
                     // A block to end the mutable borrow on list,
-                    // so the map below can check is_empty()
-                    {
+                    // so the tuple below can check is_empty()
+                    let entry = {
                         let popper = IdlePopper {
                             key: &self.key,
                             list,
+                            max_shared,
                         };
                         popper.pop(&expiration)
-                    }
-                        .map(|e| (e, list.is_empty()))
+                    };
+                    (entry, list.is_empty())
                 });
 
-            let (entry, empty) = if let Some((e, empty)) = maybe_entry {
-                (Some(e), empty)
-            } else {
-                // No entry found means nuke the list for sure.
-                (None, true)
-            };
+            // Saturated HTTP/2 connections stay in the list, even when none
+            // could be checked out.
+            let (entry, empty) = maybe_entry.unwrap_or((None, true));
             if empty {
                 //TODO: This could be done with the HashMap::entry API instead.
                 inner.idle.remove(&self.key);
             }
 
+            let entry = match entry {
+                None if inner.coalesce => inner.coalesced(&self.key, &expiration),
+                entry => entry,
+            };
+
             if entry.is_none() && self.waiter.is_none() {
                 let (tx, rx) = oneshot::channel();
                 trace!("checkout waiting for idle connection: {:?}", self.key);
@@ -699,6 +955,21 @@ impl<T: Poolable> Drop for Connecting<T> {
     }
 }
 
+/// Split a key into its origin and partition.
+fn key_origin(key: &str) -> Option<(String, String, u16, &str)> {
+    let mut parts = key.splitn(2, ' ');
+    let uri = parts.next()?.parse::<Uri>().ok()?;
+    let partition = parts.next().unwrap_or("");
+    let scheme = uri.scheme_str()?.to_ascii_lowercase();
+    let port = match (uri.port_u16(), &scheme[..]) {
+        (Some(port), _) => port,
+        (None, "http") => 80,
+        (None, "https") => 443,
+        (None, _) => return None,
+    };
+    Some((scheme, uri.host()?.to_ascii_lowercase(), port, partition))
+}
+
 struct Expiration(Option<Duration>);
 
 impl Expiration {
@@ -802,6 +1073,14 @@ mod tests {
         fn can_share(&self) -> bool {
             false
         }
+
+        fn is_saturated(&self) -> bool {
+            false
+        }
+
+        fn is_valid_for(&self, _host: &str) -> bool {
+            false
+        }
     }
 
     fn c<T: Poolable>(key: Key) -> Connecting<T> {
@@ -820,6 +1099,9 @@ mod tests {
                 enabled: true,
                 keep_alive_timeout: Some(Duration::from_millis(100)),
                 max_idle_per_host: max_idle,
+                max_http2_per_host: 1,
+                http2_coalescing: false,
+                max_connections: ::std::usize::MAX,
             },
             &Exec::Default,
         );
@@ -902,6 +1184,9 @@ mod tests {
                 enabled: true,
                 keep_alive_timeout: Some(Duration::from_millis(100)),
                 max_idle_per_host: ::std::usize::MAX,
+                max_http2_per_host: 1,
+                http2_coalescing: false,
+                max_connections: ::std::usize::MAX,
             },
             &Exec::Default,
         );
@@ -922,7 +1207,7 @@ mod tests {
 
         // Let the timer tick passed the expiration...
         rt
-            .block_on(Delay::new(Instant::now() + Duration::from_millis(200)))
+            .block_on(tokio_timer::delay(Instant::now() + Duration::from_millis(200)))
             .expect("rt block_on 200ms");
 
         assert!(pool.locked().idle.get(&key).is_none());
@@ -990,6 +1275,14 @@ mod tests {
         fn can_share(&self) -> bool {
             false
         }
+
+        fn is_saturated(&self) -> bool {
+            false
+        }
+
+        fn is_valid_for(&self, _host: &str) -> bool {
+            false
+        }
     }
 
     #[test]
@@ -1004,4 +1297,214 @@ mod tests {
         assert!(!pool.locked().idle.contains_key(&key));
     }
     */
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use crate::common::{Exec, task};
+    use super::{Key, Pool, Poolable, Pooled, Reservation, Ver};
+
+    /// Test shared reservations, where each clone of a connection sees
+    /// whether it is saturated.
+    #[derive(Clone, Debug)]
+    struct Shared {
+        id: usize,
+        saturated: Arc<AtomicBool>,
+        /// The hosts its certificate would be valid for.
+        hosts: &'static [&'static str],
+    }
+
+    impl Shared {
+        fn new(id: usize) -> Shared {
+            Shared::valid_for(id, &[])
+        }
+
+        fn valid_for(id: usize, hosts: &'static [&'static str]) -> Shared {
+            Shared {
+                id,
+                saturated: Arc::new(AtomicBool::new(false)),
+                hosts,
+            }
+        }
+
+        fn set_saturated(&self, saturated: bool) {
+            self.saturated.store(saturated, Ordering::SeqCst);
+        }
+    }
+
+    impl Poolable for Shared {
+        fn is_open(&self) -> bool {
+            true
+        }
+
+        fn reserve(self) -> Reservation<Self> {
+            Reservation::Shared(self.clone(), self)
+        }
+
+        fn can_share(&self) -> bool {
+            true
+        }
+
+        fn is_saturated(&self) -> bool {
+            self.saturated.load(Ordering::SeqCst)
+        }
+
+        fn is_valid_for(&self, host: &str) -> bool {
+            self.hosts.contains(&host)
+        }
+    }
+
+    fn shared_pool(max_http2_per_host: usize, http2_coalescing: bool) -> Pool<Shared> {
+        pool_with(max_http2_per_host, http2_coalescing, ::std::usize::MAX)
+    }
+
+    fn pool_with(max_http2_per_host: usize, http2_coalescing: bool, max_connections: usize) -> Pool<Shared> {
+        let pool = Pool::new(super::Config {
+                enabled: true,
+                keep_alive_timeout: None,
+                max_idle_per_host: ::std::usize::MAX,
+                max_http2_per_host,
+                http2_coalescing,
+                max_connections,
+            },
+            &Exec::Default,
+        );
+        pool.no_timer();
+        pool
+    }
+
+    fn key(s: &str) -> Key {
+        Arc::new(s.to_string())
+    }
+
+    /// Inserts a new HTTP/2 connection, as the client does once connected.
+    fn connected(pool: &Pool<Shared>, key: &Key, conn: &Shared) {
+        let connecting = pool.connecting(key, Ver::Http2).expect("connecting");
+        drop(pool.pooled(connecting, conn.clone()));
+    }
+
+    fn checkout_now(pool: &Pool<Shared>, key: &Key) -> Option<Pooled<Shared>> {
+        pool.checkout(key.clone()).checkout()
+    }
+
+    fn checkout_id(pool: &Pool<Shared>, key: &Key) -> usize {
+        checkout_now(pool, key).expect("checkout").id
+    }
+
+    #[test]
+    fn pool_shared_saturated_makes_new_connection() {
+        let pool = shared_pool(2, false);
+        let key = key("http://a.example");
+        let conn1 = Shared::new(1);
+        connected(&pool, &key, &conn1);
+        assert_eq!(checkout_id(&pool, &key), 1);
+
+        conn1.set_saturated(true);
+        assert!(checkout_now(&pool, &key).is_none(), "saturated conn checked out");
+
+        connected(&pool, &key, &Shared::new(2));
+        assert_eq!(pool.locked().idle[&key].len(), 2);
+        assert_eq!(checkout_id(&pool, &key), 2);
+    }
+
+    #[test]
+    fn pool_shared_max_per_host() {
+        let pool = shared_pool(2, false);
+        let key = key("http://a.example");
+        let conn1 = Shared::new(1);
+        let conn2 = Shared::new(2);
+        connected(&pool, &key, &conn1);
+        connected(&pool, &key, &conn2);
+
+        // A connection beyond the limit isn't kept.
+        connected(&pool, &key, &Shared::new(3));
+        assert_eq!(pool.locked().idle[&key].len(), 2);
+
+        // With no more connections allowed, a saturated one is used anyway.
+        conn1.set_saturated(true);
+        conn2.set_saturated(true);
+        let id = checkout_id(&pool, &key);
+        assert!(id == 1 || id == 2, "checked out {}", id);
+    }
+
+    #[test]
+    fn pool_shared_rotates_connections() {
+        let pool = shared_pool(2, false);
+        let key = key("http://a.example");
+        connected(&pool, &key, &Shared::new(1));
+        connected(&pool, &key, &Shared::new(2));
+
+        let ids = (0..4).map(|_| checkout_id(&pool, &key)).collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2, 1, 2]);
+
+        // A saturated connection is skipped while the other has room.
+        pool.locked().idle[&key][0].value.set_saturated(true);
+        let ids = (0..2).map(|_| checkout_id(&pool, &key)).collect::<Vec<_>>();
+        assert_eq!(ids, [2, 2]);
+    }
+
+    #[test]
+    fn pool_coalesces_valid_authority() {
+        let pool = shared_pool(1, true);
+        let conn = Shared::valid_for(1, &["a.example", "b.example"]);
+        connected(&pool, &key("https://a.example"), &conn);
+
+        assert_eq!(checkout_id(&pool, &key("https://b.example")), 1);
+        assert_eq!(checkout_id(&pool, &key("https://B.example:443")), 1);
+        assert!(checkout_now(&pool, &key("https://c.example")).is_none());
+
+        conn.set_saturated(true);
+        assert!(checkout_now(&pool, &key("https://b.example")).is_none());
+    }
+
+    #[test]
+    fn pool_coalesces_only_same_origin_and_partition() {
+        let pool = shared_pool(1, true);
+        let conn = Shared::valid_for(1, &["a.example", "b.example"]);
+        connected(&pool, &key("https://a.example 10.0.0.1"), &conn);
+
+        assert_eq!(checkout_id(&pool, &key("https://b.example 10.0.0.1")), 1);
+        // scheme
+        assert!(checkout_now(&pool, &key("http://b.example 10.0.0.1")).is_none());
+        // port
+        assert!(checkout_now(&pool, &key("https://b.example:8443 10.0.0.1")).is_none());
+        // partition
+        assert!(checkout_now(&pool, &key("https://b.example")).is_none());
+        assert!(checkout_now(&pool, &key("https://b.example 10.0.0.2")).is_none());
+    }
+
+    #[test]
+    fn pool_coalescing_disabled() {
+        let pool = shared_pool(1, false);
+        let conn = Shared::valid_for(1, &["a.example", "b.example"]);
+        connected(&pool, &key("https://a.example"), &conn);
+
+        assert!(checkout_now(&pool, &key("https://b.example")).is_none());
+    }
+
+    #[test]
+    fn pool_capacity_registers_each_task_once() {
+        let pool = pool_with(1, false, 1);
+        let slot = pool.slot();
+
+        let waker = futures_util::task::noop_waker();
+        let mut cx = task::Context::from_waker(&waker);
+        for _ in 0..3 {
+            assert!(pool.poll_capacity(&mut cx).is_pending());
+        }
+        assert_eq!(pool.capacity.state.lock().unwrap().waiters.len(), 1);
+
+        drop(slot);
+        assert!(pool.poll_capacity(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn pool_capacity_counts_idle_of_any_host() {
+        let pool = pool_with(1, false, 1);
+        let _slot = pool.slot();
+        connected(&pool, &key("http://a.example"), &Shared::new(1));
+
+        let waker = futures_util::task::noop_waker();
+        let mut cx = task::Context::from_waker(&waker);
+        assert!(pool.poll_capacity(&mut cx).is_ready());
+    }
 }
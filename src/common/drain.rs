@@ -1,5 +1,6 @@
 use std::mem;
 
+use futures_util::FutureExt as _;
 use tokio_sync::{mpsc, watch};
 
 use super::{Future, Never, Poll, Pin, task};
@@ -99,7 +100,10 @@ where
         loop {
             match mem::replace(&mut me.state, State::Draining) {
                 State::Watch(on_drain) => {
-                    match me.watch.rx.poll_ref(cx) {
+                    let recv = me.watch.rx.recv_ref();
+                    futures_util::pin_mut!(recv);
+
+                    match recv.poll_unpin(cx) {
                         Poll::Ready(None) => {
                             // Drain has been triggered!
                             on_drain(unsafe { Pin::new_unchecked(&mut me.future) });
@@ -13,6 +13,7 @@ pub(crate) mod exec;
 pub(crate) mod io;
 mod lazy;
 mod never;
+pub(crate) mod sync_wrapper;
 pub(crate) mod task;
 
 pub(crate) use self::buf::StaticBuf;
@@ -0,0 +1,23 @@
+//! A wrapper making any type `Sync`, by only allowing mutable access to it.
+//!
+//! Futures built from `async fn`s, like the h2 handshake, are often `Send`
+//! but not `Sync`. Since a future is only ever polled through `&mut`, it
+//! can be stored in a `SyncWrapper` without making its owner `!Sync`.
+
+/// A value only reachable through `&mut self`, so that `&SyncWrapper` can
+/// be shared between threads without sharing the value.
+pub(crate) struct SyncWrapper<T>(T);
+
+impl<T> SyncWrapper<T> {
+    pub(crate) fn new(value: T) -> SyncWrapper<T> {
+        SyncWrapper(value)
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+// SAFETY: A `&SyncWrapper<T>` gives no access to the `T`, so sharing one
+// between threads can't share the `T`.
+unsafe impl<T> Sync for SyncWrapper<T> {}
@@ -73,6 +73,8 @@ pub(crate) enum User {
     MakeService,
     /// Error from future of user's Service.
     Service,
+    /// Error from user's client Interceptor.
+    Interceptor,
     /// User tried to send a certain header in an unexpected context.
     ///
     /// For example, sending both `content-length` and `transfer-encoding`.
@@ -92,6 +94,9 @@ pub(crate) enum User {
     /// User polled for an upgrade, but low-level API is not using upgrades.
     ManualUpgrade,
 
+    /// User tried to push a response to a client that disabled server push.
+    PushDisabled,
+
     /// Error trying to call `Executor::execute`.
     Execute,
 }
@@ -156,21 +161,35 @@ impl Error {
         &self.inner.kind
     }
 
-    pub(crate) fn h2_reason(&self) -> h2::Reason {
-        // Find an h2::Reason somewhere in the cause stack, if it exists,
-        // otherwise assume an INTERNAL_ERROR.
+    /// Returns the HTTP/2 error code of this error, if it has one.
+    ///
+    /// This is the `Reason` of an HTTP/2 stream or connection reset found
+    /// anywhere in the cause chain, whether received from the peer or a
+    /// `StreamReset` returned by user code.
+    ///
+    /// A client receiving `Reason::REFUSED_STREAM` can know the server never
+    /// processed the request.
+    pub fn h2_reason(&self) -> Option<Reason> {
+        // Find an h2::Reason somewhere in the cause stack, if it exists.
         let mut cause = self.source();
         while let Some(err) = cause {
             if let Some(h2_err) = err.downcast_ref::<h2::Error>() {
-                return h2_err
-                    .reason()
-                    .unwrap_or(h2::Reason::INTERNAL_ERROR);
+                return h2_err.reason().map(Reason::from_h2);
+            }
+            if let Some(reset) = err.downcast_ref::<StreamReset>() {
+                return Some(reset.reason());
             }
             cause = err.source();
         }
+        None
+    }
 
-        // else
-        h2::Reason::INTERNAL_ERROR
+    /// The h2 `Reason` to reset a stream with because of this error,
+    /// `INTERNAL_ERROR` unless it has one.
+    pub(crate) fn h2_reset_reason(&self) -> h2::Reason {
+        self.h2_reason()
+            .map(Reason::into_h2)
+            .unwrap_or(h2::Reason::INTERNAL_ERROR)
     }
 
     pub(crate) fn new_canceled() -> Error {
@@ -254,6 +273,10 @@ impl Error {
         Error::new_user(User::ManualUpgrade)
     }
 
+    pub(crate) fn new_user_push_disabled() -> Error {
+        Error::new_user(User::PushDisabled)
+    }
+
     pub(crate) fn new_user_make_service<E: Into<Cause>>(cause: E) -> Error {
         Error::new_user(User::MakeService).with(cause)
     }
@@ -262,6 +285,10 @@ impl Error {
         Error::new_user(User::Service).with(cause)
     }
 
+    pub(crate) fn new_user_interceptor<E: Into<Cause>>(cause: E) -> Error {
+        Error::new_user(User::Interceptor).with(cause)
+    }
+
     pub(crate) fn new_user_body<E: Into<Cause>>(cause: E) -> Error {
         Error::new_user(User::Body).with(cause)
     }
@@ -331,6 +358,7 @@ impl StdError for Error {
             Kind::User(User::Body) => "error from user's Payload stream",
             Kind::User(User::MakeService) => "error from user's MakeService",
             Kind::User(User::Service) => "error from user's Service",
+            Kind::User(User::Interceptor) => "error from user's Interceptor",
             Kind::User(User::UnexpectedHeader) => "user sent unexpected header",
             Kind::User(User::UnsupportedVersion) => "request has unsupported HTTP version",
             Kind::User(User::UnsupportedRequestMethod) => "request has unsupported HTTP method",
@@ -338,6 +366,7 @@ impl StdError for Error {
             Kind::User(User::AbsoluteUriRequired) => "client requires absolute-form URIs",
             Kind::User(User::NoUpgrade) => "no upgrade available",
             Kind::User(User::ManualUpgrade) => "upgrade expected but low level API in use",
+            Kind::User(User::PushDisabled) => "client disabled server push",
             Kind::User(User::Execute) => "executor failed to spawn task",
         }
     }
@@ -351,6 +380,132 @@ impl StdError for Error {
     }
 }
 
+/// An HTTP/2 error code, sent in `RST_STREAM` and `GOAWAY` frames.
+///
+/// See [the spec](https://http2.github.io/http2-spec/#ErrorCodes) for the
+/// meaning of each code. Codes not listed here are kept as is.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Reason(u32);
+
+impl Reason {
+    /// The associated condition is not a result of an error.
+    pub const NO_ERROR: Reason = Reason(0);
+    /// The endpoint detected an unspecific protocol error.
+    pub const PROTOCOL_ERROR: Reason = Reason(1);
+    /// The endpoint encountered an unexpected internal error.
+    pub const INTERNAL_ERROR: Reason = Reason(2);
+    /// The endpoint detected that its peer violated the flow-control protocol.
+    pub const FLOW_CONTROL_ERROR: Reason = Reason(3);
+    /// The endpoint sent a SETTINGS frame but did not receive a response in
+    /// a timely manner.
+    pub const SETTINGS_TIMEOUT: Reason = Reason(4);
+    /// The endpoint received a frame after a stream was half-closed.
+    pub const STREAM_CLOSED: Reason = Reason(5);
+    /// The endpoint received a frame with an invalid size.
+    pub const FRAME_SIZE_ERROR: Reason = Reason(6);
+    /// The endpoint refused the stream prior to performing any application
+    /// processing.
+    pub const REFUSED_STREAM: Reason = Reason(7);
+    /// Used by the endpoint to indicate that the stream is no longer needed.
+    pub const CANCEL: Reason = Reason(8);
+    /// The endpoint is unable to maintain the header compression context for
+    /// the connection.
+    pub const COMPRESSION_ERROR: Reason = Reason(9);
+    /// The connection established in response to a CONNECT request was reset
+    /// or abnormally closed.
+    pub const CONNECT_ERROR: Reason = Reason(10);
+    /// The endpoint detected that its peer is exhibiting a behavior that
+    /// might be generating excessive load.
+    pub const ENHANCE_YOUR_CALM: Reason = Reason(11);
+    /// The underlying transport has properties that do not meet minimum
+    /// security requirements.
+    pub const INADEQUATE_SECURITY: Reason = Reason(12);
+    /// The endpoint requires that HTTP/1.1 be used instead of HTTP/2.
+    pub const HTTP_1_1_REQUIRED: Reason = Reason(13);
+
+    fn from_h2(reason: h2::Reason) -> Reason {
+        Reason(reason.into())
+    }
+
+    fn into_h2(self) -> h2::Reason {
+        self.0.into()
+    }
+}
+
+impl From<u32> for Reason {
+    fn from(code: u32) -> Reason {
+        Reason(code)
+    }
+}
+
+impl From<Reason> for u32 {
+    fn from(reason: Reason) -> u32 {
+        reason.0
+    }
+}
+
+impl fmt::Debug for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.into_h2(), f)
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.into_h2(), f)
+    }
+}
+
+/// An error to reset an HTTP/2 stream with a chosen `Reason`.
+///
+/// When a `Service` errors, its HTTP/2 stream is reset with
+/// `Reason::INTERNAL_ERROR`. Returning a `StreamReset` instead, directly or
+/// as the cause of another error, resets the stream with its reason. For
+/// instance, `Reason::REFUSED_STREAM` tells the client the request was not
+/// processed and may be retried, and `Reason::CANCEL` that the response is
+/// no longer wanted.
+///
+/// On HTTP/1 connections, this is handled like any other error.
+///
+/// # Example
+///
+/// ```
+/// use hyper::error::{Reason, StreamReset};
+///
+/// let err = StreamReset::new(Reason::REFUSED_STREAM);
+/// assert_eq!(err.reason(), Reason::REFUSED_STREAM);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamReset {
+    reason: Reason,
+}
+
+impl StreamReset {
+    /// Create a `StreamReset` with the given `Reason`.
+    pub fn new(reason: Reason) -> StreamReset {
+        StreamReset {
+            reason,
+        }
+    }
+
+    /// Returns the `Reason` the stream will be reset with.
+    pub fn reason(&self) -> Reason {
+        self.reason
+    }
+}
+
+impl fmt::Display for StreamReset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "stream reset by user: {}", self.reason)
+    }
+}
+
+impl StdError for StreamReset {
+    fn description(&self) -> &str {
+        "stream reset by user"
+    }
+}
+
 #[doc(hidden)]
 impl From<Parse> for Error {
     fn from(err: Parse) -> Error {
@@ -420,13 +575,14 @@ mod tests {
     #[test]
     fn h2_reason_unknown() {
         let closed = Error::new_closed();
-        assert_eq!(closed.h2_reason(), h2::Reason::INTERNAL_ERROR);
+        assert_eq!(closed.h2_reason(), None);
+        assert_eq!(closed.h2_reset_reason(), h2::Reason::INTERNAL_ERROR);
     }
 
     #[test]
     fn h2_reason_one_level() {
         let body_err = Error::new_user_body(h2::Error::from(h2::Reason::ENHANCE_YOUR_CALM));
-        assert_eq!(body_err.h2_reason(), h2::Reason::ENHANCE_YOUR_CALM);
+        assert_eq!(body_err.h2_reason(), Some(Reason::ENHANCE_YOUR_CALM));
     }
 
     #[test]
@@ -434,6 +590,13 @@ mod tests {
         let recvd = Error::new_h2(h2::Error::from(h2::Reason::HTTP_1_1_REQUIRED));
         // Suppose a user were proxying the received error
         let svc_err = Error::new_user_service(recvd);
-        assert_eq!(svc_err.h2_reason(), h2::Reason::HTTP_1_1_REQUIRED);
+        assert_eq!(svc_err.h2_reason(), Some(Reason::HTTP_1_1_REQUIRED));
+    }
+
+    #[test]
+    fn h2_reason_stream_reset() {
+        let svc_err = Error::new_user_service(StreamReset::new(Reason::REFUSED_STREAM));
+        assert_eq!(svc_err.h2_reason(), Some(Reason::REFUSED_STREAM));
+        assert_eq!(svc_err.h2_reset_reason(), h2::Reason::REFUSED_STREAM);
     }
 }
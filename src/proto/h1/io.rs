@@ -613,10 +613,10 @@ impl<T: Buf> Buf for BufDeque<T> {
 
     #[inline]
     fn bytes(&self) -> &[u8] {
-        for buf in &self.bufs {
-            return buf.bytes();
+        match self.bufs.front() {
+            Some(buf) => buf.bytes(),
+            None => &[],
         }
-        &[]
     }
 
     #[inline]
@@ -1,126 +1,188 @@
-use bytes::IntoBuf;
-//use futures::{Async, Future, Poll, Stream};
-//use futures::future::{self, Either};
-//use futures::sync::{mpsc, oneshot};
-use h2::client::{Builder, Handshake, SendRequest};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bytes::{Buf, Bytes};
+use futures_channel::{mpsc, oneshot};
+use futures_util::future::{self, Either, FutureExt as _};
+use futures_core::Stream;
+use futures_util::stream::StreamExt as _;
+use h2::client::{Builder, SendRequest};
 use tokio_io::{AsyncRead, AsyncWrite};
 
 use crate::headers::content_length_parse_all;
 use crate::body::Payload;
 use crate::common::{Exec, Future, Never, Pin, Poll, task};
+use crate::common::sync_wrapper::SyncWrapper;
 use crate::headers;
 use crate::proto::Dispatched;
-use super::{PipeToSendStream, SendBuf};
+use crate::upgrade::{self, Upgraded};
+use super::{ClientShared, H2Upgraded, PipeToSendStream, SendBuf, SettingsObserver, StreamWaker, Waiter};
+use super::ping;
 use crate::{Body, Request, Response};
 
 type ClientRx<B> = crate::client::dispatch::Receiver<Request<B>, Response<Body>>;
-///// An mpsc channel is used to help notify the `Connection` task when *all*
-///// other handles to it have been dropped, so that it can shutdown.
-//type ConnDropRef = mpsc::Sender<Never>;
 
-///// A oneshot channel watches the `Connection` task, and when it completes,
-///// the "dispatch" task will be notified and can shutdown sooner.
-//type ConnEof = oneshot::Receiver<Never>;
+/// An mpsc channel is used to help notify the `Connection` task when *all*
+/// other handles to it have been dropped, so that it can shutdown.
+type ConnDropRef = mpsc::Sender<Never>;
+
+/// A oneshot channel watches the `Connection` task, and when it completes,
+/// the "dispatch" task will be notified and can shutdown sooner.
+type ConnEof = oneshot::Receiver<Never>;
+
+/// The sending side of a connection's `PushedResponses`.
+#[derive(Clone)]
+pub(crate) struct PushTx {
+    tx: mpsc::UnboundedSender<crate::Result<PushedResponse>>,
+    taken: Arc<AtomicBool>,
+}
+
+/// The h2 handshake is an `async fn`, whose future is `Send` but not `Sync`.
+type Handshaking<T, B> = SyncWrapper<Pin<Box<dyn Future<Output=Result<(
+    SendRequest<SendBuf<B>>,
+    h2::client::Connection<T, SendBuf<B>>,
+), h2::Error>> + Send>>>;
 
 pub(crate) struct Client<T, B>
 where
     B: Payload,
 {
     executor: Exec,
+    ping_config: ping::Config,
+    push_tx: Option<PushTx>,
+    shared: ClientShared,
     rx: ClientRx<B>,
-    state: State<T, SendBuf<B::Data>>,
+    state: State<SettingsObserver<T>, B::Data>,
 }
 
-enum State<T, B> where B: IntoBuf {
-    Handshaking(Handshake<T, B>),
-    //Ready(SendRequest<B>, ConnDropRef, ConnEof),
+enum State<T, B>
+where
+    B: Buf,
+{
+    Handshaking(Handshaking<T, B>),
+    Ready {
+        tx: SendRequest<SendBuf<B>>,
+        conn_drop_ref: ConnDropRef,
+        conn_eof: ConnEof,
+        ping: ping::Recorder,
+    },
 }
 
 impl<T, B> Client<T, B>
 where
-    T: AsyncRead + AsyncWrite + Send + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     B: Payload,
 {
-    pub(crate) fn new(io: T, rx: ClientRx<B>, builder: &Builder, exec: Exec) -> Client<T, B> {
-        unimplemented!("proto::h2::Client::new");
-        /*
-        let handshake = builder.handshake(io);
+    pub(crate) fn new(
+        io: T,
+        rx: ClientRx<B>,
+        builder: &Builder,
+        ping_config: ping::Config,
+        push_tx: Option<PushTx>,
+        shared: ClientShared,
+        exec: Exec,
+    ) -> Client<T, B> {
+        let io = SettingsObserver::client(io, shared.peer_settings.clone());
+        let handshake = builder.handshake::<_, SendBuf<B::Data>>(io);
 
         Client {
             executor: exec,
+            ping_config,
+            push_tx,
+            shared,
             rx: rx,
-            state: State::Handshaking(handshake),
+            state: State::Handshaking(SyncWrapper::new(Box::pin(handshake))),
         }
-        */
     }
 }
 
 impl<T, B> Future for Client<T, B>
 where
-    T: AsyncRead + AsyncWrite + Send + 'static,
-    B: Payload + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    B: Payload + Unpin + 'static,
 {
     type Output = crate::Result<Dispatched>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
-        unimplemented!("impl Future for proto::h2::Client");
-        /*
+        let me = &mut *self;
         loop {
-            let next = match self.state {
+            let next = match me.state {
                 State::Handshaking(ref mut h) => {
-                    let (request_tx, conn) = try_ready!(h.poll().map_err(crate::Error::new_h2));
+                    let (request_tx, mut conn) = ready!(h.get_mut().as_mut().poll(cx))
+                        .map_err(crate::Error::new_h2)?;
+
+                    let (ping, ponger) = if me.ping_config.is_enabled() {
+                        let pp = conn.ping_pong().expect("conn.ping_pong");
+                        let (recorder, ponger) = ping::channel(pp, me.ping_config.clone());
+                        (recorder, Some(ponger))
+                    } else {
+                        (ping::disabled(), None)
+                    };
+
                     // An mpsc channel is used entirely to detect when the
                     // 'Client' has been dropped. This is to get around a bug
                     // in h2 where dropping all SendRequests won't notify a
                     // parked Connection.
-                    let (tx, rx) = mpsc::channel(0);
-                    let (cancel_tx, cancel_rx) = oneshot::channel();
-                    let rx = rx.into_future()
-                        .map(|(msg, _)| match msg {
+                    let (conn_drop_ref, rx) = mpsc::channel(1);
+                    let (cancel_tx, conn_eof) = oneshot::channel();
+
+                    let conn_drop_rx = rx.into_future()
+                        .map(|(item, _rx)| match item {
                             Some(never) => match never {},
                             None => (),
-                        })
-                        .map_err(|_| -> Never { unreachable!("mpsc cannot error") });
-                    let fut = conn
-                        .inspect(move |_| {
-                            drop(cancel_tx);
-                            trace!("connection complete")
-                        })
-                        .map_err(|e| debug!("connection error: {}", e))
-                        .select2(rx)
-                        .then(|res| match res {
-                            Ok(Either::A(((), _))) |
-                            Err(Either::A(((), _))) => {
+                        });
+
+                    let conn = Conn::new(ponger, conn)
+                        .map(|res| {
+                            if let Err(e) = res {
+                                debug!("connection error: {}", e);
+                            }
+                        });
+
+                    let fut = async move {
+                        pin_utils::pin_mut!(conn);
+                        match future::select(conn, conn_drop_rx).await {
+                            Either::Left(((), _)) => {
                                 // conn has finished either way
-                                Either::A(future::ok(()))
                             },
-                            Ok(Either::B(((), conn))) => {
+                            Either::Right(((), conn)) => {
                                 // mpsc has been dropped, hopefully polling
                                 // the connection some more should start shutdown
                                 // and then close
                                 trace!("send_request dropped, starting conn shutdown");
-                                Either::B(conn)
-                            }
-                            Err(Either::B((never, _))) => match never {},
-                        });
-                    self.executor.execute(fut)?;
-                    State::Ready(request_tx, tx, cancel_rx)
+                                conn.await;
+                            },
+                        }
+                        trace!("connection complete");
+                        drop(cancel_tx);
+                    };
+                    me.executor.execute(fut)?;
+
+                    State::Ready {
+                        tx: request_tx,
+                        conn_drop_ref,
+                        conn_eof,
+                        ping,
+                    }
                 },
-                State::Ready(ref mut tx, ref conn_dropper, ref mut cancel_rx) => {
-                    match tx.poll_ready() {
-                        Ok(Async::Ready(())) => (),
-                        Ok(Async::NotReady) => return Ok(Async::NotReady),
-                        Err(err) => {
+                State::Ready { ref mut tx, ref conn_drop_ref, ref mut conn_eof, ref ping } => {
+                    match tx.poll_ready(cx) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Err(err)) => {
+                            ping.ensure_not_timed_out()?;
                             return if err.reason() == Some(::h2::Reason::NO_ERROR) {
                                 trace!("connection gracefully shutdown");
-                                Ok(Async::Ready(Dispatched::Shutdown))
+                                Poll::Ready(Ok(Dispatched::Shutdown))
                             } else {
-                                Err(crate::Error::new_h2(err))
+                                Poll::Ready(Err(crate::Error::new_h2(err)))
                             };
                         }
                     }
-                    match self.rx.poll() {
-                        Ok(Async::Ready(Some((req, cb)))) => {
+
+                    match me.rx.poll_next(cx) {
+                        Poll::Ready(Some((req, cb))) => {
                             // check that future hasn't been canceled already
                             if cb.is_canceled() {
                                 trace!("request callback is canceled");
@@ -129,11 +191,28 @@ where
                             let (head, body) = req.into_parts();
                             let mut req = ::http::Request::from_parts(head, ());
                             super::strip_connection_headers(req.headers_mut(), true);
+                            // A CONNECT keeps the stream open for the
+                            // upgraded IO, instead of sending a body.
+                            let is_connect = req.method() == &::http::Method::CONNECT;
                             if let Some(len) = body.content_length() {
-                                headers::set_content_length_if_missing(req.headers_mut(), len);
+                                if !is_connect {
+                                    headers::set_content_length_if_missing(req.headers_mut(), len);
+                                }
                             }
                             let eos = body.is_end_stream();
-                            let (fut, body_tx) = match tx.send_request(req, eos) {
+                            // A request without a body can be rebuilt, in case
+                            // the server refuses the stream without processing it.
+                            let retry_head = if eos && !is_connect {
+                                let mut head = Request::new(());
+                                *head.method_mut() = req.method().clone();
+                                *head.uri_mut() = req.uri().clone();
+                                *head.version_mut() = req.version();
+                                *head.headers_mut() = req.headers().clone();
+                                Some(head)
+                            } else {
+                                None
+                            };
+                            let (mut fut, body_tx) = match tx.send_request(req, !is_connect && eos) {
                                 Ok(ok) => ok,
                                 Err(err) => {
                                     debug!("client send request error: {}", err);
@@ -141,65 +220,307 @@ where
                                     continue;
                                 }
                             };
-                            if !eos {
-                                let mut pipe = PipeToSendStream::new(body, body_tx)
-                                    .map_err(|e| debug!("client request body error: {}", e));
+                            let stream_ref = me.shared.open_stream();
+                            let mut retry = None;
+                            let send_stream = if is_connect {
+                                Some(body_tx)
+                            } else if !eos {
+                                let mut pipe = Box::pin(PipeToSendStream::new(body, body_tx))
+                                    .map(|res| {
+                                        if let Err(e) = res {
+                                            debug!("client request body error: {}", e);
+                                        }
+                                    });
 
                                 // eagerly see if the body pipe is ready and
                                 // can thus skip allocating in the executor
-                                match pipe.poll() {
-                                    Ok(Async::Ready(())) | Err(()) => (),
-                                    Ok(Async::NotReady) => {
-                                        let conn_drop_ref = conn_dropper.clone();
-                                        let pipe = pipe.then(move |x| {
-                                                drop(conn_drop_ref);
-                                                x
-                                            });
-                                        self.executor.execute(pipe)?;
+                                match Pin::new(&mut pipe).poll(cx) {
+                                    Poll::Ready(()) => (),
+                                    Poll::Pending => {
+                                        let conn_drop_ref = conn_drop_ref.clone();
+                                        let pipe = pipe.map(move |x| {
+                                            drop(conn_drop_ref);
+                                            x
+                                        });
+                                        me.executor.execute(pipe)?;
                                     }
                                 }
-                            }
+                                None
+                            } else {
+                                retry = retry_head.map(|head| head.map(|()| body));
+                                None
+                            };
 
-                            let fut = fut
-                                .then(move |result| {
+                            let stream_waker = if let Some(ref push_tx) = me.push_tx {
+                                let pushes = fut.push_promises();
+                                let push_tx = push_tx.clone();
+                                let ping = ping.clone();
+                                let stream_waker = StreamWaker::new();
+                                me.executor.execute(forward_pushes(pushes, push_tx, ping, stream_waker.clone()))?;
+                                Some(stream_waker)
+                            } else {
+                                None
+                            };
+
+                            let ping = ping.clone();
+                            let body_waker = stream_waker.clone();
+                            let fut = future::poll_fn(move |cx| {
+                                    StreamWaker::poll(stream_waker.as_ref(), Waiter::Response, cx, |cx| {
+                                        Pin::new(&mut fut).poll(cx)
+                                    })
+                                })
+                                .map(move |result| {
                                     match result {
                                         Ok(res) => {
-                                            let content_length = content_length_parse_all(res.headers());
-                                            let res = res.map(|stream|
-                                                crate::Body::h2(stream, content_length));
+                                            ping.record_non_data();
+                                            let rtt = ping.rtt();
+                                            let mut res = match send_stream {
+                                                Some(send_stream) if res.status().is_success() => {
+                                                    let (head, recv_stream) = res.into_parts();
+                                                    let mut res = Response::from_parts(head, Body::empty());
+                                                    let (pending, on_upgrade) = upgrade::pending();
+                                                    let io = H2Upgraded::new(ping, send_stream, recv_stream);
+                                                    pending.fulfill(Upgraded::new(Box::new(io), Bytes::new()));
+                                                    res.body_mut().set_on_upgrade(on_upgrade);
+                                                    res
+                                                },
+                                                _ => {
+                                                    let content_length = content_length_parse_all(res.headers());
+                                                    let mut res = res.map(|stream|
+                                                        crate::Body::h2(stream, content_length, ping));
+                                                    res.body_mut().set_h2_stream_ref(stream_ref);
+                                                    if let Some(waker) = body_waker {
+                                                        res.body_mut().set_h2_stream_waker(waker);
+                                                    }
+                                                    res
+                                                },
+                                            };
+                                            if let Some(rtt) = rtt {
+                                                res.extensions_mut().insert(rtt);
+                                            }
                                             Ok(res)
                                         },
                                         Err(err) => {
+                                            ping.ensure_not_timed_out().map_err(|e| (e, None))?;
                                             debug!("client response error: {}", err);
-                                            Err((crate::Error::new_h2(err), None))
+                                            // REFUSED_STREAM means the server
+                                            // never processed the request.
+                                            let retry = match err.reason() {
+                                                Some(::h2::Reason::REFUSED_STREAM) => retry,
+                                                _ => None,
+                                            };
+                                            Err((crate::Error::new_h2(err), retry))
                                         }
                                     }
                                 });
-                            self.executor.execute(cb.send_when(fut))?;
+                            me.executor.execute(cb.send_when(fut))?;
                             continue;
                         },
 
-                        Ok(Async::NotReady) => {
-                            match cancel_rx.poll() {
-                                Ok(Async::Ready(never)) => match never {},
-                                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Poll::Ready(None) => {
+                            trace!("client::dispatch::Sender dropped");
+                            return Poll::Ready(Ok(Dispatched::Shutdown));
+                        },
+
+                        Poll::Pending => {
+                            match ready!(Pin::new(conn_eof).poll(cx)) {
+                                Ok(never) => match never {},
                                 Err(_conn_is_eof) => {
                                     trace!("connection task is closed, closing dispatch task");
-                                    return Ok(Async::Ready(Dispatched::Shutdown));
+                                    return Poll::Ready(Ok(Dispatched::Shutdown));
                                 }
                             }
                         },
-
-                        Ok(Async::Ready(None)) => {
-                            trace!("client::dispatch::Sender dropped");
-                            return Ok(Async::Ready(Dispatched::Shutdown));
-                        },
-                        Err(never) => match never {},
                     }
                 },
             };
-            self.state = next;
+            me.state = next;
         }
-        */
+    }
+}
+
+async fn forward_pushes(
+    mut pushes: h2::client::PushPromises,
+    push_tx: PushTx,
+    ping: ping::Recorder,
+    stream_waker: Arc<StreamWaker>,
+) {
+    let mut next = future::poll_fn(|cx| {
+        StreamWaker::poll(Some(&stream_waker), Waiter::Pushes, cx, |cx| pushes.poll_push_promise(cx))
+    });
+    while let Some(res) = (&mut next).await {
+        // Pushes nobody will poll would hold on to a concurrent stream each.
+        // Dropping the promise instead resets its stream with CANCEL.
+        if !push_tx.taken.load(Ordering::Acquire) {
+            trace!("pushed responses not taken, canceling push");
+            continue;
+        }
+        let pushed = res
+            .map(|promise| {
+                ping.record_non_data();
+                PushedResponse::new(promise, ping.clone())
+            })
+            .map_err(crate::Error::new_h2);
+        if push_tx.tx.unbounded_send(pushed).is_err() {
+            trace!("pushed responses receiver dropped, canceling push");
+        }
+    }
+}
+
+/// A stream of responses pushed by the server over HTTP/2.
+///
+/// Yielded by `SendRequest::pushed_responses`, when server push has been
+/// enabled with `Builder::http2_enable_push`.
+#[must_use = "streams do nothing unless polled"]
+pub struct PushedResponses {
+    rx: mpsc::UnboundedReceiver<crate::Result<PushedResponse>>,
+    taken: Arc<AtomicBool>,
+}
+
+/// A response the server has promised to push.
+pub struct PushedResponse {
+    req: Request<()>,
+    fut: PushedResponseFuture,
+}
+
+/// A future resolving to the `Response` of a `PushedResponse`.
+#[must_use = "futures do nothing unless polled"]
+pub struct PushedResponseFuture {
+    inner: Pin<Box<dyn Future<Output=crate::Result<Response<Body>>> + Send>>,
+}
+
+impl PushedResponses {
+    pub(crate) fn channel() -> (PushTx, PushedResponses) {
+        let (tx, rx) = mpsc::unbounded();
+        let taken = Arc::new(AtomicBool::new(false));
+        (PushTx { tx, taken: taken.clone() }, PushedResponses { rx, taken })
+    }
+
+    /// Starts forwarding pushes, which are canceled until then.
+    pub(crate) fn take(self) -> PushedResponses {
+        self.taken.store(true, Ordering::Release);
+        self
+    }
+}
+
+impl Stream for PushedResponses {
+    type Item = crate::Result<PushedResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+impl fmt::Debug for PushedResponses {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PushedResponses")
+            .finish()
+    }
+}
+
+impl PushedResponse {
+    fn new(promise: h2::client::PushPromise, ping: ping::Recorder) -> PushedResponse {
+        let (req, fut) = promise.into_parts();
+        let inner = fut.map(move |result| match result {
+            Ok(res) => {
+                ping.record_non_data();
+                let content_length = content_length_parse_all(res.headers());
+                Ok(res.map(|stream| crate::Body::h2(stream, content_length, ping)))
+            },
+            Err(err) => {
+                debug!("pushed response error: {}", err);
+                Err(crate::Error::new_h2(err))
+            },
+        });
+        PushedResponse {
+            req,
+            fut: PushedResponseFuture {
+                inner: Box::pin(inner),
+            },
+        }
+    }
+
+    /// The promised request, as sent by the server.
+    pub fn request(&self) -> &Request<()> {
+        &self.req
+    }
+
+    /// Consumes the `PushedResponse`, returning the promised request and a
+    /// future of the pushed response.
+    pub fn into_parts(self) -> (Request<()>, PushedResponseFuture) {
+        (self.req, self.fut)
+    }
+}
+
+impl fmt::Debug for PushedResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PushedResponse")
+            .field("request", &self.req)
+            .finish()
+    }
+}
+
+impl Future for PushedResponseFuture {
+    type Output = crate::Result<Response<Body>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for PushedResponseFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PushedResponseFuture")
+            .finish()
+    }
+}
+
+/// Drives the h2 `Connection`, along with any pings configured for it.
+struct Conn<T, B>
+where
+    B: Buf,
+{
+    ponger: Option<ping::Ponger>,
+    conn: h2::client::Connection<T, SendBuf<B>>,
+}
+
+impl<T, B> Conn<T, B>
+where
+    B: Buf,
+{
+    fn new(
+        ponger: Option<ping::Ponger>,
+        conn: h2::client::Connection<T, SendBuf<B>>,
+    ) -> Self {
+        Conn {
+            ponger,
+            conn,
+        }
+    }
+}
+
+impl<T, B> Future for Conn<T, B>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    B: Buf,
+{
+    type Output = crate::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let me = &mut *self;
+        if let Some(ref mut ponger) = me.ponger {
+            match ponger.poll(cx) {
+                Poll::Ready(ping::Ponged::SizeUpdate(wnd)) => {
+                    me.conn.set_target_window_size(wnd);
+                },
+                Poll::Ready(ping::Ponged::KeepAliveTimedOut) => {
+                    debug!("connection keep-alive timed out");
+                    return Poll::Ready(Err(ping::KeepAliveTimedOut.crate_error()));
+                },
+                Poll::Pending => {},
+            }
+        }
+
+        Pin::new(&mut me.conn).poll(cx).map_err(crate::Error::new_h2)
     }
 }
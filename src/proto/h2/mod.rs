@@ -1,6 +1,12 @@
-use bytes::Buf;
-//use futures::{Async, Future, Poll};
-use h2::{SendStream};
+use std::io::{self, Cursor};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Waker;
+
+use bytes::{Buf, Bytes};
+use futures_util::task::{ArcWake, waker};
+use h2::{Reason, RecvStream, SendStream};
+use tokio_io::{AsyncRead, AsyncWrite};
 use http::header::{
     HeaderName, CONNECTION, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION, TE, TRAILER,
     TRANSFER_ENCODING, UPGRADE,
@@ -8,12 +14,126 @@ use http::header::{
 use http::HeaderMap;
 
 use crate::body::Payload;
+use crate::common::{Future, Pin, Poll, task};
 
 mod client;
+pub(crate) mod ping;
 pub(crate) mod server;
+mod settings;
 
 pub(crate) use self::client::Client;
+pub use self::client::{PushedResponse, PushedResponseFuture, PushedResponses};
+pub use self::ping::Rtt;
+pub use self::server::{PushFuture, Pusher};
 pub(crate) use self::server::Server;
+pub use self::settings::PeerSettings;
+use self::settings::{SettingsObserver, SharedSettings};
+
+/// Default initial stream and connection window size, as defined by the spec.
+pub(crate) const SPEC_WINDOW_SIZE: u32 = 65_535;
+
+/// State of an HTTP/2 client connection, updated by the connection task
+/// and read by the senders of requests.
+#[derive(Clone, Default)]
+pub(crate) struct ClientShared {
+    peer_settings: SharedSettings,
+    streams: Arc<AtomicUsize>,
+}
+
+/// Counts a stream as open on a client connection, until dropped.
+pub(crate) struct StreamRef(Arc<AtomicUsize>);
+
+impl ClientShared {
+    pub(crate) fn peer_settings(&self) -> Option<PeerSettings> {
+        self.peer_settings.lock().unwrap().clone()
+    }
+
+    fn open_stream(&self) -> StreamRef {
+        self.streams.fetch_add(1, Ordering::AcqRel);
+        StreamRef(self.streams.clone())
+    }
+
+    /// Returns whether as many streams are open as the peer allows, so that
+    /// new requests would be queued.
+    pub(crate) fn is_saturated(&self) -> bool {
+        let max = self.peer_settings
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(PeerSettings::max_concurrent_streams);
+        match max {
+            Some(max) => self.streams.load(Ordering::Acquire) >= max as usize,
+            None => false,
+        }
+    }
+}
+
+impl Drop for StreamRef {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Wakes each task waiting on a client stream.
+///
+/// h2 keeps a single waker per stream, so when a response and the pushes
+/// promised on its stream are polled by different tasks, only the task that
+/// polled last would be woken, whichever frame arrived.
+pub(crate) struct StreamWaker {
+    wakers: Mutex<[Option<Waker>; 2]>,
+}
+
+/// A task waiting on a client stream.
+#[derive(Clone, Copy)]
+pub(crate) enum Waiter {
+    /// The response, and then its body.
+    Response = 0,
+    /// The pushes promised on the stream.
+    Pushes = 1,
+}
+
+impl StreamWaker {
+    pub(crate) fn new() -> Arc<StreamWaker> {
+        Arc::new(StreamWaker {
+            wakers: Mutex::new([None, None]),
+        })
+    }
+
+    /// Registers the task of `cx` as `waiter`, and calls `f` with a context
+    /// waking every waiting task.
+    ///
+    /// Without a `StreamWaker`, `f` is just called with `cx`.
+    pub(crate) fn poll<T>(
+        this: Option<&Arc<StreamWaker>>,
+        waiter: Waiter,
+        cx: &mut task::Context<'_>,
+        f: impl FnOnce(&mut task::Context<'_>) -> T,
+    ) -> T {
+        let this = match this {
+            Some(this) => this,
+            None => return f(cx),
+        };
+        {
+            let mut wakers = this.wakers.lock().unwrap();
+            let slot = &mut wakers[waiter as usize];
+            match *slot {
+                Some(ref w) if w.will_wake(cx.waker()) => (),
+                _ => *slot = Some(cx.waker().clone()),
+            }
+        }
+        let waker = waker(this.clone());
+        f(&mut task::Context::from_waker(&waker))
+    }
+}
+
+impl ArcWake for StreamWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        let wakers = arc_self.wakers.lock().unwrap().clone();
+        for waker in wakers.iter().flatten() {
+            waker.wake_by_ref();
+        }
+    }
+}
 
 fn strip_connection_headers(headers: &mut HeaderMap, is_request: bool) {
     // List of connection headers from:
@@ -94,124 +214,280 @@ where
     fn on_user_err(&mut self, err: S::Error) -> crate::Error {
         let err = crate::Error::new_user_body(err);
         debug!("send body user stream error: {}", err);
-        self.body_tx.send_reset(err.h2_reason());
+        self.body_tx.send_reset(err.h2_reset_reason());
         err
     }
 
     fn send_eos_frame(&mut self) -> crate::Result<()> {
         trace!("send body eos");
         self.body_tx
-            .send_data(SendBuf(None), true)
+            .send_data(SendBuf::None, true)
             .map_err(crate::Error::new_body_write)
     }
 }
 
-/*
 impl<S> Future for PipeToSendStream<S>
 where
     S: Payload,
 {
-    type Item = ();
-    type Error = crate::Error;
+    type Output = crate::Result<()>;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        unimplemented!("impl Future for PipeToSendStream");
-        /*
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        // Safety: `stream` is never moved out of `self`, it is only
+        // projected to a pinned reference.
+        let me = unsafe { self.get_unchecked_mut() };
         loop {
-            if !self.data_done {
+            if !me.data_done {
                 // we don't have the next chunk of data yet, so just reserve 1 byte to make
                 // sure there's some capacity available. h2 will handle the capacity management
                 // for the actual body chunk.
-                self.body_tx.reserve_capacity(1);
+                me.body_tx.reserve_capacity(1);
 
-                if self.body_tx.capacity() == 0 {
+                if me.body_tx.capacity() == 0 {
                     loop {
-                        match try_ready!(self.body_tx.poll_capacity().map_err(crate::Error::new_body_write)) {
-                            Some(0) => {}
-                            Some(_) => break,
-                            None => return Err(crate::Error::new_canceled()),
+                        match ready!(me.body_tx.poll_capacity(cx)) {
+                            Some(Ok(0)) => {}
+                            Some(Ok(_)) => break,
+                            Some(Err(e)) => return Poll::Ready(Err(crate::Error::new_body_write(e))),
+                            None => return Poll::Ready(Err(crate::Error::new_canceled())),
                         }
                     }
-                } else {
-                    if let Async::Ready(reason) =
-                        self.body_tx.poll_reset().map_err(crate::Error::new_body_write)?
-                    {
-                        debug!("stream received RST_STREAM: {:?}", reason);
-                        return Err(crate::Error::new_body_write(::h2::Error::from(reason)));
-                    }
+                } else if let Poll::Ready(reason) = me.body_tx
+                    .poll_reset(cx)
+                    .map_err(crate::Error::new_body_write)?
+                {
+                    debug!("stream received RST_STREAM: {:?}", reason);
+                    return Poll::Ready(Err(crate::Error::new_body_write(::h2::Error::from(reason))));
                 }
 
-                match try_ready!(self.stream.poll_data().map_err(|e| self.on_user_err(e))) {
-                    Some(chunk) => {
-                        let is_eos = self.stream.is_end_stream();
+                let stream = unsafe { Pin::new_unchecked(&mut me.stream) };
+                match ready!(stream.poll_data(cx)) {
+                    Some(Ok(chunk)) => {
+                        let is_eos = me.stream.is_end_stream();
                         trace!(
                             "send body chunk: {} bytes, eos={}",
                             chunk.remaining(),
                             is_eos,
                         );
 
-                        let buf = SendBuf(Some(chunk));
-                        self.body_tx
+                        let buf = SendBuf::Buf(chunk);
+                        me.body_tx
                             .send_data(buf, is_eos)
                             .map_err(crate::Error::new_body_write)?;
 
                         if is_eos {
-                            return Ok(Async::Ready(()));
+                            return Poll::Ready(Ok(()));
                         }
                     }
+                    Some(Err(e)) => return Poll::Ready(Err(me.on_user_err(e))),
                     None => {
-                        self.body_tx.reserve_capacity(0);
-                        let is_eos = self.stream.is_end_stream();
+                        me.body_tx.reserve_capacity(0);
+                        let is_eos = me.stream.is_end_stream();
                         if is_eos {
-                            return self.send_eos_frame().map(Async::Ready);
+                            return Poll::Ready(me.send_eos_frame());
                         } else {
-                            self.data_done = true;
+                            me.data_done = true;
                             // loop again to poll_trailers
                         }
                     }
                 }
             } else {
-                if let Async::Ready(reason) =
-                    self.body_tx.poll_reset().map_err(|e| crate::Error::new_body_write(e))?
+                if let Poll::Ready(reason) = me.body_tx
+                    .poll_reset(cx)
+                    .map_err(crate::Error::new_body_write)?
                 {
                     debug!("stream received RST_STREAM: {:?}", reason);
-                    return Err(crate::Error::new_body_write(::h2::Error::from(reason)));
+                    return Poll::Ready(Err(crate::Error::new_body_write(::h2::Error::from(reason))));
                 }
 
-                match try_ready!(self.stream.poll_trailers().map_err(|e| self.on_user_err(e))) {
-                    Some(trailers) => {
-                        self.body_tx
+                let stream = unsafe { Pin::new_unchecked(&mut me.stream) };
+                match ready!(stream.poll_trailers(cx)) {
+                    Some(Ok(trailers)) => {
+                        me.body_tx
                             .send_trailers(trailers)
                             .map_err(crate::Error::new_body_write)?;
-                        return Ok(Async::Ready(()));
+                        return Poll::Ready(Ok(()));
                     }
+                    Some(Err(e)) => return Poll::Ready(Err(me.on_user_err(e))),
                     None => {
                         // There were no trailers, so send an empty DATA frame...
-                        return self.send_eos_frame().map(Async::Ready);
+                        return Poll::Ready(me.send_eos_frame());
                     }
                 }
             }
         }
-        */
     }
 }
-*/
 
-struct SendBuf<B>(Option<B>);
+enum SendBuf<B> {
+    Buf(B),
+    Cursor(Cursor<Box<[u8]>>),
+    None,
+}
+
+// The buffer is never pinned, so `SendBuf` is `Unpin` regardless of `B`.
+impl<B> Unpin for SendBuf<B> {}
 
 impl<B: Buf> Buf for SendBuf<B> {
     #[inline]
     fn remaining(&self) -> usize {
-        self.0.as_ref().map(|b| b.remaining()).unwrap_or(0)
+        match *self {
+            SendBuf::Buf(ref b) => b.remaining(),
+            SendBuf::Cursor(ref c) => c.remaining(),
+            SendBuf::None => 0,
+        }
     }
 
     #[inline]
     fn bytes(&self) -> &[u8] {
-        self.0.as_ref().map(|b| b.bytes()).unwrap_or(&[])
+        match *self {
+            SendBuf::Buf(ref b) => b.bytes(),
+            SendBuf::Cursor(ref c) => c.bytes(),
+            SendBuf::None => &[],
+        }
     }
 
     #[inline]
     fn advance(&mut self, cnt: usize) {
-        self.0.as_mut().map(|b| b.advance(cnt));
+        match *self {
+            SendBuf::Buf(ref mut b) => b.advance(cnt),
+            SendBuf::Cursor(ref mut c) => c.advance(cnt),
+            SendBuf::None => {},
+        }
+    }
+}
+
+// upgrade adapter used by both Client and Server
+
+/// The IO of an HTTP/2 stream that has been upgraded with `CONNECT`.
+///
+/// DATA frames received are read, and writes are sent as DATA frames, with
+/// the stream's flow control applied as read and write backpressure.
+pub(crate) struct H2Upgraded<B>
+where
+    B: Buf,
+{
+    ping: ping::Recorder,
+    send_stream: SendStream<SendBuf<B>>,
+    recv_stream: RecvStream,
+    buf: Bytes,
+}
+
+impl<B> H2Upgraded<B>
+where
+    B: Buf,
+{
+    fn new(
+        ping: ping::Recorder,
+        send_stream: SendStream<SendBuf<B>>,
+        recv_stream: RecvStream,
+    ) -> H2Upgraded<B> {
+        H2Upgraded {
+            ping,
+            send_stream,
+            recv_stream,
+            buf: Bytes::new(),
+        }
+    }
+
+    /// Maps a reset of the send stream to an `io::Error`.
+    fn poll_reset_err(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Error> {
+        let err = match ready!(self.send_stream.poll_reset(cx)) {
+            Ok(Reason::NO_ERROR) |
+            Ok(Reason::CANCEL) |
+            Ok(Reason::STREAM_CLOSED) => return Poll::Ready(io::ErrorKind::BrokenPipe.into()),
+            Ok(reason) => reason.into(),
+            Err(e) => e,
+        };
+        Poll::Ready(h2_to_io_error(err))
+    }
+}
+
+impl<B> AsyncRead for H2Upgraded<B>
+where
+    B: Buf,
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, read_buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.buf.is_empty() {
+            self.buf = loop {
+                match ready!(self.recv_stream.poll_data(cx)) {
+                    None => return Poll::Ready(Ok(0)),
+                    Some(Ok(buf)) if buf.is_empty() && !self.recv_stream.is_end_stream() => continue,
+                    Some(Ok(buf)) => {
+                        self.ping.record_data(buf.len());
+                        break buf;
+                    },
+                    Some(Err(e)) => {
+                        return Poll::Ready(match e.reason() {
+                            Some(Reason::NO_ERROR) |
+                            Some(Reason::CANCEL) => Ok(0),
+                            Some(Reason::STREAM_CLOSED) => {
+                                Err(io::Error::new(io::ErrorKind::BrokenPipe, e))
+                            },
+                            _ => Err(h2_to_io_error(e)),
+                        });
+                    },
+                }
+            };
+        }
+
+        let cnt = ::std::cmp::min(self.buf.len(), read_buf.len());
+        read_buf[..cnt].copy_from_slice(&self.buf[..cnt]);
+        self.buf.advance(cnt);
+        let _ = self.recv_stream.release_capacity().release_capacity(cnt);
+        Poll::Ready(Ok(cnt))
+    }
+}
+
+impl<B> AsyncWrite for H2Upgraded<B>
+where
+    B: Buf,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        self.send_stream.reserve_capacity(buf.len());
+
+        // Errors from `poll_capacity` and `send_data` are ignored, since
+        // the actual reason will be yielded by `poll_reset`.
+        let cnt = match ready!(self.send_stream.poll_capacity(cx)) {
+            None => Some(0),
+            Some(Ok(cnt)) => {
+                let chunk = SendBuf::Cursor(Cursor::new(buf[..cnt].into()));
+                self.send_stream
+                    .send_data(chunk, false)
+                    .ok()
+                    .map(|()| cnt)
+            },
+            Some(Err(_)) => None,
+        };
+
+        if let Some(cnt) = cnt {
+            return Poll::Ready(Ok(cnt));
+        }
+
+        self.poll_reset_err(cx).map(Err)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        if self.send_stream.send_data(SendBuf::None, true).is_ok() {
+            return Poll::Ready(Ok(()));
+        }
+
+        self.poll_reset_err(cx).map(Err)
+    }
+}
+
+fn h2_to_io_error(e: h2::Error) -> io::Error {
+    if e.is_io() {
+        e.into_io().expect("is_io")
+    } else {
+        io::Error::new(io::ErrorKind::Other, e)
     }
 }
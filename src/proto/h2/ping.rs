@@ -0,0 +1,617 @@
+//! HTTP2 Ping usage
+//!
+//! hyper uses HTTP2 pings for three purposes:
+//!
+//! 1. Keep-alive: hyper will send pings on an interval, and if a pong isn't
+//!    received within a timeout, the connection is closed.
+//! 2. Round-trip time: every time a pong is received, the time since the
+//!    matching ping was sent is recorded, and can be read from any `Rtt`
+//!    handle of the connection.
+//! 3. Adaptive flow control: while DATA is being received, a ping is sent to
+//!    measure how many bytes arrive within one round trip. That estimate of
+//!    the bandwidth-delay product (BDP) is used to grow the connection
+//!    window, up to a configured ceiling.
+//!
+//! # Keep-alive
+//!
+//! The keep-alive timer is reset whenever a frame is read from the peer, so
+//! busy connections don't pay for extra pings. If `keep_alive_while_idle` is
+//! disabled, no pings are sent while there are no open streams.
+//!
+//! # BDP
+//!
+//! The BDP algorithm follows the one used by gRPC: a BDP ping is sent when
+//! the first DATA frame after the previous pong is received. When its pong
+//! arrives, if the bytes received in the meantime are close to the current
+//! window, the window is doubled. Once the estimate stops growing, BDP pings
+//! are sent less often.
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use h2::{Ping, PingPong};
+#[cfg(feature = "runtime")]
+use tokio_timer::Delay;
+
+use crate::common::{Pin, Poll, task};
+#[cfg(feature = "runtime")]
+use crate::common::Future;
+
+pub(super) fn disabled() -> Recorder {
+    Recorder {
+        shared: None,
+    }
+}
+
+type WindowSize = u32;
+
+/// The largest window hyper will ever grow to, no matter the configuration.
+pub(crate) const MAX_WINDOW_SIZE: WindowSize = (1 << 31) - 1;
+
+/// The default ceiling for adaptive windows.
+pub(crate) const DEFAULT_ADAPTIVE_WINDOW_MAX: WindowSize = 1024 * 1024 * 16;
+
+pub(super) fn channel(ping_pong: PingPong, config: Config) -> (Recorder, Ponger) {
+    debug_assert!(
+        config.is_enabled(),
+        "ping channel requires bdp or keep-alive config",
+    );
+
+    let bdp = config.bdp_initial_window.map(|wnd| Bdp {
+        bdp: wnd,
+        max_window: config.bdp_max_window,
+        max_bandwidth: 0.0,
+        rtt: 0.0,
+        ping_delay: Duration::from_millis(100),
+        stable_count: 0,
+    });
+
+    let shared = Arc::new(Mutex::new(Shared {
+        bytes: bdp.as_ref().map(|_| 0),
+        next_bdp_at: None,
+        ping_pong,
+        ping_sent_at: None,
+        last_read_at: Instant::now(),
+        is_keep_alive_timed_out: false,
+        rtt: Rtt::new(),
+    }));
+
+    #[cfg(feature = "runtime")]
+    let keep_alive = config.keep_alive_interval.map(|interval| KeepAlive {
+        interval,
+        timeout: config.keep_alive_timeout,
+        while_idle: config.keep_alive_while_idle,
+        timer: tokio_timer::delay(Instant::now() + interval),
+        state: KeepAliveState::Init,
+    });
+
+    (
+        Recorder {
+            shared: Some(shared.clone()),
+        },
+        Ponger {
+            bdp,
+            #[cfg(feature = "runtime")]
+            keep_alive,
+            shared,
+        },
+    )
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    /// The window to start BDP estimation from, if adaptive windows are on.
+    pub(crate) bdp_initial_window: Option<WindowSize>,
+    /// The largest window BDP estimation is allowed to grow to.
+    pub(crate) bdp_max_window: WindowSize,
+    #[cfg(feature = "runtime")]
+    pub(crate) keep_alive_interval: Option<Duration>,
+    #[cfg(feature = "runtime")]
+    pub(crate) keep_alive_timeout: Duration,
+    #[cfg(feature = "runtime")]
+    pub(crate) keep_alive_while_idle: bool,
+}
+
+/// A handle to the round-trip time of an HTTP/2 connection.
+///
+/// hyper measures the round-trip time whenever one of the HTTP/2 keep-alive
+/// `PING` frames it sends is acknowledged by the peer. When keep-alive is
+/// enabled, a handle is inserted into the extensions of every response
+/// (client) or request (server) received over the connection.
+#[derive(Clone)]
+pub struct Rtt {
+    last: Arc<Mutex<Option<Duration>>>,
+}
+
+/// Records activity on a connection, shared by all of its streams.
+#[derive(Clone)]
+pub(crate) struct Recorder {
+    shared: Option<Arc<Mutex<Shared>>>,
+}
+
+pub(super) struct Ponger {
+    bdp: Option<Bdp>,
+    #[cfg(feature = "runtime")]
+    keep_alive: Option<KeepAlive>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    /// Bytes received since the last BDP pong, if BDP is enabled.
+    bytes: Option<usize>,
+    /// Don't send a BDP ping before this time, to back off once the
+    /// estimate is stable.
+    next_bdp_at: Option<Instant>,
+    ping_pong: PingPong,
+    ping_sent_at: Option<Instant>,
+    last_read_at: Instant,
+    is_keep_alive_timed_out: bool,
+    rtt: Rtt,
+}
+
+struct Bdp {
+    /// Current BDP estimate, in bytes.
+    bdp: WindowSize,
+    /// The estimate never grows past this.
+    max_window: WindowSize,
+    /// Largest bandwidth seen so far, in bytes per second.
+    max_bandwidth: f64,
+    /// Smoothed round-trip time, in seconds.
+    rtt: f64,
+    /// Delay the next BDP ping by this much after a pong.
+    ping_delay: Duration,
+    /// Number of pongs in a row that didn't change the estimate.
+    stable_count: u32,
+}
+
+#[cfg(feature = "runtime")]
+struct KeepAlive {
+    /// If no frames are received in this amount of time, a PING frame is sent.
+    interval: Duration,
+    /// After sending a keepalive PING, the connection will be closed if
+    /// a pong is not received in this amount of time.
+    timeout: Duration,
+    /// If true, sends pings even when there are no active streams.
+    while_idle: bool,
+
+    state: KeepAliveState,
+    timer: Delay,
+}
+
+#[cfg(feature = "runtime")]
+enum KeepAliveState {
+    Init,
+    Scheduled,
+    PingSent,
+}
+
+pub(super) enum Ponged {
+    SizeUpdate(WindowSize),
+    KeepAliveTimedOut,
+}
+
+#[derive(Debug)]
+pub(super) struct KeepAliveTimedOut;
+
+// ===== impl Config =====
+
+impl Config {
+    pub(crate) fn new() -> Config {
+        Config {
+            bdp_initial_window: None,
+            bdp_max_window: DEFAULT_ADAPTIVE_WINDOW_MAX,
+            #[cfg(feature = "runtime")]
+            keep_alive_interval: None,
+            #[cfg(feature = "runtime")]
+            keep_alive_timeout: Duration::from_secs(20),
+            #[cfg(feature = "runtime")]
+            keep_alive_while_idle: false,
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.bdp_initial_window.is_some() || self.is_keep_alive_enabled()
+    }
+
+    pub(super) fn is_bdp_enabled(&self) -> bool {
+        self.bdp_initial_window.is_some()
+    }
+
+    fn is_keep_alive_enabled(&self) -> bool {
+        #[cfg(feature = "runtime")]
+        {
+            self.keep_alive_interval.is_some()
+        }
+
+        #[cfg(not(feature = "runtime"))]
+        {
+            false
+        }
+    }
+}
+
+// ===== impl Rtt =====
+
+impl Rtt {
+    fn new() -> Rtt {
+        Rtt {
+            last: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns the most recently measured round-trip time, if any ping
+    /// has been acknowledged yet.
+    pub fn last(&self) -> Option<Duration> {
+        *self.last.lock().unwrap()
+    }
+
+    fn set(&self, rtt: Duration) {
+        *self.last.lock().unwrap() = Some(rtt);
+    }
+}
+
+impl fmt::Debug for Rtt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rtt")
+            .field("last", &self.last())
+            .finish()
+    }
+}
+
+// ===== impl Recorder =====
+
+impl Recorder {
+    pub(crate) fn record_data(&self, len: usize) {
+        let shared = if let Some(ref shared) = self.shared {
+            shared
+        } else {
+            return;
+        };
+
+        let mut locked = shared.lock().unwrap();
+        let now = Instant::now();
+
+        // Even zero-length DATA frames prove the peer is alive.
+        locked.last_read_at = now;
+
+        if let Some(next_bdp_at) = locked.next_bdp_at {
+            if now < next_bdp_at {
+                return;
+            } else {
+                locked.next_bdp_at = None;
+            }
+        }
+
+        if let Some(ref mut bytes) = locked.bytes {
+            *bytes += len;
+        } else {
+            // no BDP, don't bother sending pings
+            return;
+        }
+
+        if !locked.is_ping_sent() {
+            locked.send_ping();
+        }
+    }
+
+    pub(crate) fn record_non_data(&self) {
+        let shared = if let Some(ref shared) = self.shared {
+            shared
+        } else {
+            return;
+        };
+
+        let mut locked = shared.lock().unwrap();
+        locked.last_read_at = Instant::now();
+    }
+
+    /// Returns a handle to the round-trip time of the connection, if pings
+    /// are being sent.
+    pub(crate) fn rtt(&self) -> Option<Rtt> {
+        self.shared
+            .as_ref()
+            .map(|shared| shared.lock().unwrap().rtt.clone())
+    }
+
+    /// If the connection was closed because of a keep-alive timeout, map
+    /// the error to a keep-alive timeout error.
+    pub(crate) fn ensure_not_timed_out(&self) -> crate::Result<()> {
+        if let Some(ref shared) = self.shared {
+            let locked = shared.lock().unwrap();
+            if locked.is_keep_alive_timed_out {
+                return Err(KeepAliveTimedOut.crate_error());
+            }
+        }
+
+        // else
+        Ok(())
+    }
+}
+
+// ===== impl Ponger =====
+
+impl Ponger {
+    pub(super) fn poll(&mut self, cx: &mut task::Context<'_>) -> Poll<Ponged> {
+        let mut locked = self.shared.lock().unwrap();
+
+        #[cfg(feature = "runtime")]
+        {
+            let is_idle = self.is_idle();
+            if let Some(ref mut ka) = self.keep_alive {
+                ka.schedule(is_idle, &locked);
+                ka.maybe_ping(cx, &mut locked);
+            }
+        }
+
+        if locked.ping_sent_at.is_none() {
+            return Poll::Pending;
+        }
+
+        match locked.ping_pong.poll_pong(cx) {
+            Poll::Ready(Ok(_pong)) => {
+                let now = Instant::now();
+                let rtt = now - locked
+                    .ping_sent_at
+                    .take()
+                    .expect("pong received implies ping_sent_at");
+                locked.last_read_at = now;
+                locked.rtt.set(rtt);
+                trace!("recv pong, rtt = {:?}", rtt);
+
+                #[cfg(feature = "runtime")]
+                {
+                    let is_idle = self.is_idle();
+                    if let Some(ref mut ka) = self.keep_alive {
+                        ka.schedule(is_idle, &locked);
+                    }
+                }
+
+                if let Some(ref mut bdp) = self.bdp {
+                    let bytes = locked.bytes.expect("bdp enabled implies bytes");
+                    locked.bytes = Some(0); // reset
+                    trace!("received BDP ack; bytes = {}, rtt = {:?}", bytes, rtt);
+
+                    let update = bdp.calculate(bytes, rtt);
+                    locked.next_bdp_at = Some(now + bdp.ping_delay);
+                    if let Some(update) = update {
+                        return Poll::Ready(Ponged::SizeUpdate(update));
+                    }
+                }
+            }
+            Poll::Ready(Err(e)) => {
+                debug!("pong error: {}", e);
+            }
+            Poll::Pending => {
+                #[cfg(feature = "runtime")]
+                {
+                    if let Some(ref mut ka) = self.keep_alive {
+                        if let Err(KeepAliveTimedOut) = ka.maybe_timeout(cx) {
+                            self.keep_alive = None;
+                            locked.is_keep_alive_timed_out = true;
+                            return Poll::Ready(Ponged::KeepAliveTimedOut);
+                        }
+                    }
+                }
+            }
+        }
+
+        Poll::Pending
+    }
+
+    #[cfg(feature = "runtime")]
+    fn is_idle(&self) -> bool {
+        // The `Ponger` and the connection's own `Recorder` each hold a
+        // reference; any more means a stream is still open.
+        Arc::strong_count(&self.shared) <= 2
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    fn send_ping(&mut self) {
+        match self.ping_pong.send_ping(Ping::opaque()) {
+            Ok(()) => {
+                self.ping_sent_at = Some(Instant::now());
+                trace!("sent ping");
+            }
+            Err(err) => {
+                debug!("error sending ping: {}", err);
+            }
+        }
+    }
+
+    fn is_ping_sent(&self) -> bool {
+        self.ping_sent_at.is_some()
+    }
+}
+
+// ===== impl Bdp =====
+
+impl Bdp {
+    fn calculate(&mut self, bytes: usize, rtt: Duration) -> Option<WindowSize> {
+        // No need to do any math if we're at the limit.
+        if self.bdp >= self.max_window {
+            self.stabilize_delay();
+            return None;
+        }
+
+        // average the rtt
+        let rtt = seconds(rtt);
+        if self.rtt == 0.0 {
+            // First sample means rtt is first rtt.
+            self.rtt = rtt;
+        } else {
+            // Weigh this rtt as 1/8 for a moving average.
+            self.rtt += (rtt - self.rtt) * 0.125;
+        }
+
+        // calculate the current bandwidth
+        let bw = (bytes as f64) / (self.rtt * 1.5);
+        trace!("current bandwidth = {:.1}B/s", bw);
+
+        if bw < self.max_bandwidth {
+            // not a faster bandwidth, so don't update
+            self.stabilize_delay();
+            return None;
+        } else {
+            self.max_bandwidth = bw;
+        }
+
+        // if the current `bytes` sample is at least 2/3 the previous
+        // bdp, increase to double the current sample.
+        if bytes >= self.bdp as usize * 2 / 3 {
+            self.bdp = (bytes * 2).min(self.max_window as usize) as WindowSize;
+            trace!("BDP increased to {}", self.bdp);
+            Some(self.bdp)
+        } else {
+            self.stabilize_delay();
+            None
+        }
+    }
+
+    fn stabilize_delay(&mut self) {
+        if self.ping_delay < Duration::from_secs(10) {
+            self.stable_count += 1;
+
+            if self.stable_count >= 2 {
+                self.ping_delay *= 4;
+                self.stable_count = 0;
+            }
+        }
+    }
+}
+
+fn seconds(dur: Duration) -> f64 {
+    const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+    let secs = dur.as_secs() as f64;
+    secs + (dur.subsec_nanos() as f64) / NANOS_PER_SEC
+}
+
+// ===== impl KeepAlive =====
+
+#[cfg(feature = "runtime")]
+impl KeepAlive {
+    fn schedule(&mut self, is_idle: bool, shared: &Shared) {
+        match self.state {
+            KeepAliveState::Init => {
+                if !self.while_idle && is_idle {
+                    return;
+                }
+
+                self.state = KeepAliveState::Scheduled;
+                self.timer.reset(shared.last_read_at + self.interval);
+            }
+            KeepAliveState::PingSent => {
+                if shared.is_ping_sent() {
+                    return;
+                }
+
+                self.state = KeepAliveState::Scheduled;
+                self.timer.reset(shared.last_read_at + self.interval);
+            }
+            KeepAliveState::Scheduled => (),
+        }
+    }
+
+    fn maybe_ping(&mut self, cx: &mut task::Context<'_>, shared: &mut Shared) {
+        match self.state {
+            KeepAliveState::Scheduled => {
+                if Pin::new(&mut self.timer).poll(cx).is_pending() {
+                    return;
+                }
+                // check if we've received a frame while we were scheduled
+                if shared.last_read_at + self.interval > self.timer.deadline() {
+                    self.state = KeepAliveState::Init;
+                    cx.waker().wake_by_ref(); // schedule us again
+                    return;
+                }
+                trace!("keep-alive interval ({:?}) reached", self.interval);
+                shared.send_ping();
+                self.state = KeepAliveState::PingSent;
+                let timeout = Instant::now() + self.timeout;
+                self.timer.reset(timeout);
+            }
+            KeepAliveState::Init | KeepAliveState::PingSent => (),
+        }
+    }
+
+    fn maybe_timeout(&mut self, cx: &mut task::Context<'_>) -> Result<(), KeepAliveTimedOut> {
+        match self.state {
+            KeepAliveState::PingSent => {
+                if Pin::new(&mut self.timer).poll(cx).is_pending() {
+                    return Ok(());
+                }
+                trace!("keep-alive timeout ({:?}) reached", self.timeout);
+                Err(KeepAliveTimedOut)
+            }
+            KeepAliveState::Init | KeepAliveState::Scheduled => Ok(()),
+        }
+    }
+}
+
+// ===== impl KeepAliveTimedOut =====
+
+impl KeepAliveTimedOut {
+    pub(super) fn crate_error(self) -> crate::Error {
+        crate::Error::new(crate::error::Kind::Http2).with(self)
+    }
+}
+
+impl fmt::Display for KeepAliveTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("keep-alive timed out")
+    }
+}
+
+impl std::error::Error for KeepAliveTimedOut {
+    fn description(&self) -> &str {
+        "keep-alive timed out"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Bdp, WindowSize};
+
+    fn bdp(initial: WindowSize, max_window: WindowSize) -> Bdp {
+        Bdp {
+            bdp: initial,
+            max_window,
+            max_bandwidth: 0.0,
+            rtt: 0.0,
+            ping_delay: Duration::from_millis(100),
+            stable_count: 0,
+        }
+    }
+
+    #[test]
+    fn bdp_grows_when_window_is_saturated() {
+        let mut bdp = bdp(65_535, 1024 * 1024);
+        let rtt = Duration::from_millis(50);
+
+        assert_eq!(bdp.calculate(65_535, rtt), Some(131_070));
+        assert_eq!(bdp.calculate(131_070, rtt), Some(262_140));
+    }
+
+    #[test]
+    fn bdp_respects_max_window() {
+        let mut bdp = bdp(65_535, 100_000);
+        let rtt = Duration::from_millis(50);
+
+        assert_eq!(bdp.calculate(65_535, rtt), Some(100_000));
+        assert_eq!(bdp.calculate(100_000, rtt), None);
+    }
+
+    #[test]
+    fn bdp_backs_off_when_stable() {
+        let mut bdp = bdp(65_535, 1024 * 1024);
+        let rtt = Duration::from_millis(50);
+
+        assert_eq!(bdp.calculate(1_000, rtt), None);
+        assert_eq!(bdp.calculate(1_000, rtt), None);
+        assert_eq!(bdp.ping_delay, Duration::from_millis(400));
+    }
+}
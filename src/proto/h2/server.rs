@@ -1,8 +1,18 @@
 use std::error::Error as StdError;
+use std::fmt;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::time::{Duration, Instant};
 
-use h2::Reason;
+use bytes::Bytes;
+use futures_channel::{mpsc, oneshot};
+use futures_core::Stream;
+use h2::{Reason, RecvStream};
 use h2::server::{Builder, Connection, Handshake, SendResponse};
 use tokio_io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "runtime")]
+use tokio_timer::Delay;
 
 use crate::body::Payload;
 use crate::body::internal::FullDataArg;
@@ -12,9 +22,31 @@ use crate::headers;
 use crate::headers::content_length_parse_all;
 use crate::service::Service;
 use crate::proto::Dispatched;
-use super::{PipeToSendStream, SendBuf};
+use crate::upgrade::{self, Upgraded};
+use super::{H2Upgraded, PeerSettings, PipeToSendStream, SendBuf, SettingsObserver, SharedSettings};
+use super::ping;
 
-use crate::{Body, Response};
+use crate::{Body, Request, Response};
+
+/// Options of an HTTP/2 server connection that aren't h2 settings.
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    pub(crate) ping: ping::Config,
+    pub(crate) max_resets_per_second: Option<usize>,
+    #[cfg(feature = "runtime")]
+    pub(crate) graceful_shutdown_timeout: Option<Duration>,
+}
+
+impl Config {
+    pub(crate) fn new() -> Config {
+        Config {
+            ping: ping::Config::new(),
+            max_resets_per_second: None,
+            #[cfg(feature = "runtime")]
+            graceful_shutdown_timeout: None,
+        }
+    }
+}
 
 pub(crate) struct Server<T, S, B, E>
 where
@@ -22,8 +54,11 @@ where
     B: Payload,
 {
     exec: E,
+    config: Config,
     service: S,
-    state: State<T, B>,
+    peer_settings: SharedSettings,
+    reset_limit: Option<ResetLimit>,
+    state: State<SettingsObserver<T>, B>,
 }
 
 // TODO: fix me
@@ -42,34 +77,71 @@ struct Serving<T, B>
 where
     B: Payload,
 {
+    ping: Option<(ping::Recorder, ping::Ponger)>,
+    reset_limit: Option<ResetLimit>,
+    peer_settings: SharedSettings,
     conn: Connection<T, SendBuf<B::Data>>,
     closing: Option<crate::Error>,
+    /// Each `H2Stream` holds the receiving end, to count the active
+    /// streams and cancel them at the graceful shutdown deadline.
+    streams: Vec<oneshot::Sender<()>>,
+    /// Once a graceful shutdown has begun, the deadline to reset any
+    /// streams still active.
+    #[cfg(feature = "runtime")]
+    shutdown_deadline: Option<Delay>,
+}
+
+/// Counts the streams reset by the client within the last second, to stop a
+/// client from rapidly opening and resetting streams.
+///
+/// The `SettingsObserver` of the connection holds a clone, to record every
+/// RST_STREAM frame read from the client. That includes streams answered
+/// right away, and streams h2 drops before they are accepted.
+#[derive(Clone)]
+pub(super) struct ResetLimit {
+    inner: Arc<Mutex<ResetWindow>>,
+}
+
+struct ResetWindow {
+    max_per_second: usize,
+    /// When each of the resets within the last second happened.
+    resets: VecDeque<Instant>,
+    exceeded: bool,
+    /// The connection task, woken once the limit is exceeded.
+    conn_task: Option<Waker>,
 }
 
 
 impl<T, S, B, E> Server<T, S, B, E>
 where
-    T: AsyncRead + AsyncWrite,
+    T: AsyncRead + AsyncWrite + Unpin,
     S: Service<ReqBody=Body, ResBody=B>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
     B: Payload,
     E: H2Exec<S::Future, B>,
 {
-    pub(crate) fn new(io: T, service: S, builder: &Builder, exec: E) -> Server<T, S, B, E> {
-        unimplemented!("proto::h2::Server::new")
-        /*
+    pub(crate) fn new(
+        io: T,
+        service: S,
+        builder: &Builder,
+        config: Config,
+        exec: E,
+    ) -> Server<T, S, B, E> {
+        let peer_settings = SharedSettings::default();
+        let reset_limit = config.max_resets_per_second.map(ResetLimit::new);
+        let io = SettingsObserver::server(io, peer_settings.clone(), reset_limit.clone());
         let handshake = builder.handshake(io);
         Server {
             exec,
+            config,
+            peer_settings,
+            reset_limit,
             state: State::Handshaking(handshake),
             service,
         }
-        */
     }
 
     pub fn graceful_shutdown(&mut self) {
-        unimplemented!("proto::h2::Server::graceful_shutdown")
-        /*
         trace!("graceful_shutdown");
         match self.state {
             State::Handshaking(..) => {
@@ -77,7 +149,7 @@ where
             },
             State::Serving(ref mut srv) => {
                 if srv.closing.is_none() {
-                    srv.conn.graceful_shutdown();
+                    srv.graceful_shutdown(&self.config);
                 }
                 return;
             },
@@ -86,13 +158,24 @@ where
             }
         }
         self.state = State::Closed;
-        */
+    }
+
+    /// Returns the number of streams whose response hasn't completed.
+    pub(crate) fn active_streams(&self) -> Option<usize> {
+        match self.state {
+            State::Serving(ref srv) => Some(srv.active_streams()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn peer_settings(&self) -> Option<PeerSettings> {
+        self.peer_settings.lock().unwrap().clone()
     }
 }
 
 impl<T, S, B, E> Future for Server<T, S, B, E>
 where
-    T: AsyncRead + AsyncWrite,
+    T: AsyncRead + AsyncWrite + Unpin,
     S: Service<ReqBody=Body, ResBody=B>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
     B: Payload,
@@ -101,39 +184,54 @@ where
     type Output = crate::Result<Dispatched>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
-        unimplemented!("h2 server future")
-        /*
+        let me = &mut *self;
         loop {
-            let next = match self.state {
+            let next = match me.state {
                 State::Handshaking(ref mut h) => {
-                    let conn = try_ready!(h.poll().map_err(crate::Error::new_h2));
+                    let mut conn = ready!(Pin::new(h).poll(cx).map_err(crate::Error::new_h2))?;
+                    let ping = if me.config.ping.is_enabled() {
+                        let pp = conn.ping_pong().expect("conn.ping_pong");
+                        Some(ping::channel(pp, me.config.ping.clone()))
+                    } else {
+                        None
+                    };
                     State::Serving(Serving {
+                        ping,
+                        reset_limit: me.reset_limit.take(),
+                        peer_settings: me.peer_settings.clone(),
                         conn,
                         closing: None,
+                        streams: Vec::new(),
+                        #[cfg(feature = "runtime")]
+                        shutdown_deadline: None,
                     })
                 },
                 State::Serving(ref mut srv) => {
-                    try_ready!(srv.poll_server(&mut self.service, &self.exec));
-                    return Ok(Async::Ready(Dispatched::Shutdown));
+                    ready!(srv.poll_server(cx, &mut me.service, &mut me.exec))?;
+                    return Poll::Ready(Ok(Dispatched::Shutdown));
                 }
                 State::Closed => {
                     // graceful_shutdown was called before handshaking finished,
                     // nothing to do here...
-                    return Ok(Async::Ready(Dispatched::Shutdown));
+                    return Poll::Ready(Ok(Dispatched::Shutdown));
                 }
             };
-            self.state = next;
+            me.state = next;
         }
-        */
     }
 }
 
 impl<T, B> Serving<T, B>
 where
-    T: AsyncRead + AsyncWrite,
+    T: AsyncRead + AsyncWrite + Unpin,
     B: Payload,
 {
-    fn poll_server<S, E>(&mut self, service: &mut S, exec: &E) -> Poll<crate::Result<()>>
+    fn poll_server<S, E>(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        service: &mut S,
+        exec: &mut E,
+    ) -> Poll<crate::Result<()>>
     where
         S: Service<
             ReqBody=Body,
@@ -142,23 +240,37 @@ where
         S::Error: Into<Box<dyn StdError + Send + Sync>>,
         E: H2Exec<S::Future, B>,
     {
-        /*
         if self.closing.is_none() {
+            #[cfg(feature = "runtime")]
+            self.poll_shutdown_deadline(cx);
+
             loop {
+                self.poll_ping(cx);
+
+                if let Some(ref limit) = self.reset_limit {
+                    if limit.poll_exceeded(cx) {
+                        debug!("client reset too many streams, closing connection");
+                        self.conn.abrupt_shutdown(Reason::ENHANCE_YOUR_CALM);
+                        self.closing = Some(crate::Error::new_h2(Reason::ENHANCE_YOUR_CALM.into()));
+                        break;
+                    }
+                }
+
                 // At first, polls the readiness of supplied service.
-                match service.poll_ready() {
-                    Ok(Async::Ready(())) => (),
-                    Ok(Async::NotReady) => {
-                        // use `poll_close` instead of `poll`, in order to avoid accepting a request.
-                        try_ready!(self.conn.poll_close().map_err(crate::Error::new_h2));
+                match service.poll_ready(cx) {
+                    Poll::Ready(Ok(())) => (),
+                    Poll::Pending => {
+                        // use `poll_closed` instead of `poll_accept`,
+                        // in order to avoid accepting a request.
+                        ready!(self.conn.poll_closed(cx).map_err(crate::Error::new_h2))?;
                         trace!("incoming connection complete");
-                        return Ok(Async::Ready(()));
+                        return Poll::Ready(Ok(()));
                     }
-                    Err(err) => {
+                    Poll::Ready(Err(err)) => {
                         let err = crate::Error::new_user_service(err);
                         debug!("service closed: {}", err);
 
-                        let reason = err.h2_reason();
+                        let reason = err.h2_reset_reason();
                         if reason == Reason::NO_ERROR {
                             // NO_ERROR is only used for graceful shutdowns...
                             trace!("interpretting NO_ERROR user error as graceful_shutdown");
@@ -173,29 +285,299 @@ where
                 }
 
                 // When the service is ready, accepts an incoming request.
-                if let Some((req, respond)) = try_ready!(self.conn.poll().map_err(crate::Error::new_h2)) {
-                    trace!("incoming request");
-                    let content_length = content_length_parse_all(req.headers());
-                    let req = req.map(|stream| {
-                        crate::Body::h2(stream, content_length)
-                    });
-                    let fut = H2Stream::new(service.call(req), respond);
-                    exec.execute_h2stream(fut)?;
-                } else {
-                    // no more incoming streams...
-                    trace!("incoming connection complete");
-                    return Ok(Async::Ready(()))
+                match ready!(self.conn.poll_accept(cx)) {
+                    Some(Ok((req, respond))) => {
+                        trace!("incoming request");
+                        let content_length = content_length_parse_all(req.headers());
+                        let ping = self
+                            .ping
+                            .as_ref()
+                            .map(|ping| ping.0.clone())
+                            .unwrap_or_else(ping::disabled);
+
+                        // Record the headers received
+                        ping.record_non_data();
+
+                        let rtt = ping.rtt();
+                        let is_connect = req.method() == &::http::Method::CONNECT;
+                        let (mut req, connect_parts) = if is_connect {
+                            // The stream of a CONNECT becomes the upgraded
+                            // IO, instead of a request body.
+                            let (head, recv_stream) = req.into_parts();
+                            let mut req = Request::from_parts(head, Body::empty());
+                            let (pending, on_upgrade) = upgrade::pending();
+                            req.body_mut().set_on_upgrade(on_upgrade);
+                            let connect_parts = ConnectParts {
+                                pending,
+                                ping,
+                                recv_stream,
+                            };
+                            (req, Some(connect_parts))
+                        } else {
+                            let req = req.map(|stream| {
+                                crate::Body::h2(stream, content_length, ping)
+                            });
+                            (req, None)
+                        };
+                        if let Some(rtt) = rtt {
+                            req.extensions_mut().insert(rtt);
+                        }
+                        let (pusher, push_rx) = Pusher::channel();
+                        req.extensions_mut().insert(pusher);
+                        let (cancel_tx, cancel_rx) = oneshot::channel();
+                        self.streams.retain(|tx| !tx.is_canceled());
+                        self.streams.push(cancel_tx);
+                        let fut = H2Stream::new(
+                            service.call(req),
+                            connect_parts,
+                            respond,
+                            push_rx,
+                            self.peer_settings.clone(),
+                            cancel_rx,
+                        );
+                        exec.execute_h2stream(fut)?;
+                    },
+                    Some(Err(e)) => {
+                        return Poll::Ready(Err(crate::Error::new_h2(e)));
+                    },
+                    None => {
+                        // no more incoming streams...
+                        if let Some((ref ping, _)) = self.ping {
+                            ping.ensure_not_timed_out()?;
+                        }
+
+                        trace!("incoming connection complete");
+                        return Poll::Ready(Ok(()));
+                    }
                 }
             }
         }
 
         debug_assert!(self.closing.is_some(), "poll_server broke loop without closing");
 
-        try_ready!(self.conn.poll_close().map_err(crate::Error::new_h2));
+        ready!(self.conn.poll_closed(cx).map_err(crate::Error::new_h2))?;
+
+        Poll::Ready(Err(self.closing.take().expect("polled after error")))
+    }
+
+    fn active_streams(&self) -> usize {
+        self.streams.iter().filter(|tx| !tx.is_canceled()).count()
+    }
+
+    /// Starts a graceful shutdown.
+    ///
+    /// h2 first sends a `GOAWAY` with the maximum stream id, and once a
+    /// `PING` round trip shows the client has seen it, a final `GOAWAY` with
+    /// the last stream that will be processed.
+    fn graceful_shutdown(&mut self, config: &Config) {
+        debug!("graceful shutdown with {} active streams", self.active_streams());
+        self.conn.graceful_shutdown();
+
+        #[cfg(feature = "runtime")]
+        {
+            if self.shutdown_deadline.is_none() {
+                self.shutdown_deadline = config
+                    .graceful_shutdown_timeout
+                    .map(|timeout| tokio_timer::delay(Instant::now() + timeout));
+            }
+        }
+        #[cfg(not(feature = "runtime"))]
+        {
+            let _ = config;
+        }
+    }
+
+    #[cfg(feature = "runtime")]
+    fn poll_shutdown_deadline(&mut self, cx: &mut task::Context<'_>) {
+        if let Some(ref mut deadline) = self.shutdown_deadline {
+            if Pin::new(deadline).poll(cx).is_pending() {
+                return;
+            }
+        } else {
+            return;
+        }
+
+        debug!(
+            "graceful shutdown deadline passed, resetting {} active streams",
+            self.active_streams(),
+        );
+        self.shutdown_deadline = None;
+        // Each stream sends its own RST_STREAM(CANCEL), so the client can
+        // tell its requests weren't answered, and the connection closes
+        // once the last one is gone.
+        for tx in self.streams.drain(..) {
+            let _ = tx.send(());
+        }
+    }
+
+    fn poll_ping(&mut self, cx: &mut task::Context<'_>) {
+        if let Some((_, ref mut ponger)) = self.ping {
+            match ponger.poll(cx) {
+                Poll::Ready(ping::Ponged::SizeUpdate(wnd)) => {
+                    self.conn.set_target_window_size(wnd);
+                },
+                Poll::Ready(ping::Ponged::KeepAliveTimedOut) => {
+                    debug!("keep-alive timed out, closing connection");
+                    self.conn.abrupt_shutdown(h2::Reason::NO_ERROR);
+                },
+                Poll::Pending => {},
+            }
+        }
+    }
+}
+
+impl ResetLimit {
+    pub(super) fn new(max_per_second: usize) -> ResetLimit {
+        ResetLimit {
+            inner: Arc::new(Mutex::new(ResetWindow {
+                max_per_second,
+                resets: VecDeque::new(),
+                exceeded: false,
+                conn_task: None,
+            })),
+        }
+    }
+
+    /// Records a stream reset by the client, waking the connection if that
+    /// exceeds the limit.
+    pub(super) fn record(&self) {
+        self.record_at(Instant::now());
+    }
+
+    fn record_at(&self, now: Instant) {
+        let mut window = self.inner.lock().unwrap();
+        while let Some(&at) = window.resets.front() {
+            if now - at < Duration::from_secs(1) {
+                break;
+            }
+            window.resets.pop_front();
+        }
+        window.resets.push_back(now);
+
+        if window.resets.len() > window.max_per_second && !window.exceeded {
+            window.exceeded = true;
+            if let Some(task) = window.conn_task.take() {
+                task.wake();
+            }
+        }
+    }
 
-        Err(self.closing.take().expect("polled after error"))
-        */
-        unimplemented!("h2 server poll_server")
+    /// Returns whether more streams were reset within a second than allowed,
+    /// or registers the connection task to be woken once they are.
+    pub(super) fn poll_exceeded(&self, cx: &mut task::Context<'_>) -> bool {
+        let mut window = self.inner.lock().unwrap();
+        if !window.exceeded {
+            window.conn_task = Some(cx.waker().clone());
+        }
+        window.exceeded
+    }
+}
+
+/// A handle to push responses to the client of an HTTP/2 request.
+///
+/// When serving HTTP/2, a `Pusher` is inserted into the extensions of every
+/// request, typed with the `Service`'s response body. It can be used to
+/// start pushed request/response pairs until the response to the original
+/// request has been fully sent.
+///
+/// # Example
+///
+/// ```
+/// # use hyper::{Body, Request, Response};
+/// use hyper::server::conn::Pusher;
+///
+/// # async fn doc(req: Request<Body>) -> hyper::Result<Response<Body>> {
+/// if let Some(pusher) = req.extensions().get::<Pusher<Body>>() {
+///     let promised = Request::get("https://hyper.rs/style.css")
+///         .body(())
+///         .unwrap();
+///     let res = Response::new(Body::from("body { }"));
+///     // Clients may have disabled server push, that's fine.
+///     let _ = pusher.push(promised, res).await;
+/// }
+///
+/// Ok(Response::new(Body::from("<link rel=stylesheet href=/style.css>")))
+/// # }
+/// # fn main() {}
+/// ```
+pub struct Pusher<B> {
+    tx: mpsc::UnboundedSender<PushMsg<B>>,
+}
+
+/// A future resolving once a push has been promised to the client.
+///
+/// Yielded by `Pusher::push`.
+#[must_use = "futures do nothing unless polled"]
+pub struct PushFuture {
+    rx: Result<oneshot::Receiver<crate::Result<()>>, Option<crate::Error>>,
+}
+
+type PushMsg<B> = (Request<()>, Response<B>, oneshot::Sender<crate::Result<()>>);
+
+type PushRx<B> = mpsc::UnboundedReceiver<PushMsg<B>>;
+
+impl<B> Pusher<B> {
+    fn channel() -> (Pusher<B>, PushRx<B>) {
+        let (tx, rx) = mpsc::unbounded();
+        (Pusher { tx }, rx)
+    }
+
+    /// Push a `Response` for the given promised `Request`.
+    ///
+    /// The promised request must be safe and cacheable, like a `GET`, and
+    /// use an absolute URI the connection is authoritative for. Each pushed
+    /// response has its own body, which is streamed in the background.
+    ///
+    /// The returned future resolves once the `PUSH_PROMISE` frame has been
+    /// sent. It resolves to an error if the client disabled push with
+    /// `SETTINGS_ENABLE_PUSH`, or if the response to the original request
+    /// has already completed.
+    pub fn push(&self, req: Request<()>, res: Response<B>) -> PushFuture {
+        let (tx, rx) = oneshot::channel();
+        let rx = match self.tx.unbounded_send((req, res, tx)) {
+            Ok(()) => Ok(rx),
+            Err(_) => {
+                let err = crate::Error::new_closed().with("original stream already completed");
+                Err(Some(err))
+            }
+        };
+        PushFuture { rx }
+    }
+}
+
+impl<B> Clone for Pusher<B> {
+    fn clone(&self) -> Pusher<B> {
+        Pusher {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<B> fmt::Debug for Pusher<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pusher")
+            .finish()
+    }
+}
+
+impl Future for PushFuture {
+    type Output = crate::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        match self.rx {
+            Ok(ref mut rx) => Pin::new(rx).poll(cx).map(|res| match res {
+                Ok(res) => res,
+                Err(_canceled) => Err(crate::Error::new_canceled().with("original stream was dropped")),
+            }),
+            Err(ref mut err) => Poll::Ready(Err(err.take().expect("polled after complete"))),
+        }
+    }
+}
+
+impl fmt::Debug for PushFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PushFuture")
+            .finish()
     }
 }
 
@@ -206,6 +588,11 @@ where
 {
     reply: SendResponse<SendBuf<B::Data>>,
     state: H2StreamState<F, B>,
+    connect_parts: Option<ConnectParts>,
+    peer_settings: SharedSettings,
+    canceled: Option<oneshot::Receiver<()>>,
+    push_rx: Option<PushRx<B>>,
+    pushed: Vec<Pin<Box<PipeToSendStream<B>>>>,
 }
 
 enum H2StreamState<F, B>
@@ -214,57 +601,176 @@ where
 {
     Service(F),
     Body(PipeToSendStream<B>),
+    Done,
+}
+
+struct ConnectParts {
+    pending: upgrade::Pending,
+    ping: ping::Recorder,
+    recv_stream: RecvStream,
 }
 
 impl<F, B> H2Stream<F, B>
 where
-    //F: Future<Item=Response<B>>,
-    //F::Error: Into<Box<dyn StdError + Send + Sync>>,
     B: Payload,
 {
-    fn new(fut: F, respond: SendResponse<SendBuf<B::Data>>) -> H2Stream<F, B> {
+    fn new(
+        fut: F,
+        connect_parts: Option<ConnectParts>,
+        respond: SendResponse<SendBuf<B::Data>>,
+        push_rx: PushRx<B>,
+        peer_settings: SharedSettings,
+        canceled: oneshot::Receiver<()>,
+    ) -> H2Stream<F, B> {
         H2Stream {
             reply: respond,
             state: H2StreamState::Service(fut),
+            connect_parts,
+            peer_settings,
+            canceled: Some(canceled),
+            push_rx: Some(push_rx),
+            pushed: Vec::new(),
+        }
+    }
+
+    /// Sends any pushes the service has asked for so far.
+    fn recv_pushes(&mut self, cx: &mut task::Context<'_>) {
+        let mut rx = match self.push_rx.take() {
+            Some(rx) => rx,
+            None => return,
+        };
+
+        loop {
+            match Pin::new(&mut rx).poll_next(cx) {
+                Poll::Ready(Some((req, res, tx))) => {
+                    let _ = tx.send(self.push(req, res));
+                },
+                // Every `Pusher` was dropped, no more pushes can come.
+                Poll::Ready(None) => return,
+                Poll::Pending => break,
+            }
+        }
+
+        self.push_rx = Some(rx);
+    }
+
+    fn push(&mut self, req: Request<()>, res: Response<B>) -> crate::Result<()> {
+        trace!("push promise for {}", req.uri());
+        // h2 doesn't check SETTINGS_ENABLE_PUSH itself, and a PUSH_PROMISE
+        // to a client that disabled push is a connection error.
+        let push_enabled = self.peer_settings
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(true, PeerSettings::is_push_enabled);
+        if !push_enabled {
+            return Err(crate::Error::new_user_push_disabled());
+        }
+
+        let mut pushed = self.reply
+            .push_request(req)
+            .map_err(crate::Error::new_h2)?;
+
+        let (head, body) = res.into_parts();
+        let mut res = ::http::Response::from_parts(head, ());
+        super::strip_connection_headers(res.headers_mut(), false);
+        if let Some(len) = body.content_length() {
+            headers::set_content_length_if_missing(res.headers_mut(), len);
+        }
+
+        let eos = body.is_end_stream();
+        let body_tx = pushed
+            .send_response(res, eos)
+            .map_err(crate::Error::new_h2)?;
+        if !eos {
+            self.pushed.push(Box::pin(PipeToSendStream::new(body, body_tx)));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the connection canceled this stream, because the
+    /// graceful shutdown deadline passed.
+    fn poll_canceled(&mut self, cx: &mut task::Context<'_>) -> bool {
+        let res = match self.canceled {
+            Some(ref mut rx) => match Pin::new(rx).poll(cx) {
+                Poll::Ready(res) => res,
+                Poll::Pending => return false,
+            },
+            None => return false,
+        };
+        self.canceled = None;
+        // The sender is dropped without sending if the connection is gone.
+        if res.is_err() {
+            return false;
+        }
+
+        debug!("stream canceled at graceful shutdown deadline");
+        if let H2StreamState::Done = self.state {
+        } else {
+            self.reply.send_reset(Reason::CANCEL);
+            self.state = H2StreamState::Done;
+        }
+        // h2 resets the pushed streams with CANCEL as they are dropped.
+        self.pushed.clear();
+        self.push_rx = None;
+        true
+    }
+
+    fn poll_pushed(&mut self, cx: &mut task::Context<'_>) {
+        let mut i = 0;
+        while i < self.pushed.len() {
+            match self.pushed[i].as_mut().poll(cx) {
+                Poll::Ready(res) => {
+                    if let Err(e) = res {
+                        debug!("pushed stream error: {}", e);
+                    }
+                    self.pushed.swap_remove(i);
+                },
+                Poll::Pending => i += 1,
+            }
         }
     }
 }
 
-impl<F, B> Future for H2Stream<F, B>
+impl<F, B, E> H2Stream<F, B>
 where
-    //F: Future<Item=Response<B>>,
-    //F::Error: Into<Box<dyn StdError + Send + Sync>>,
+    F: Future<Output=Result<Response<B>, E>>,
     B: Payload,
+    E: Into<Box<dyn StdError + Send + Sync>>,
 {
-    type Output = ();
-
-    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
-        unimplemented!("impl Future for H2Stream");
-        /*
+    // Only called from `poll`, while `self` is pinned.
+    fn poll2(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
+        let me = self;
         loop {
-            let next = match self.state {
+            me.recv_pushes(cx);
+
+            let next = match me.state {
                 H2StreamState::Service(ref mut h) => {
-                    let res = match h.poll() {
-                        Ok(Async::Ready(r)) => r,
-                        Ok(Async::NotReady) => {
-                            // Body is not yet ready, so we want to check if the client has sent a
+                    let res = match unsafe { Pin::new_unchecked(h) }.poll(cx) {
+                        Poll::Ready(Ok(r)) => r,
+                        Poll::Pending => {
+                            // Response is not yet ready, so we want to check if the client has sent a
                             // RST_STREAM frame which would cancel the current request.
-                            if let Async::Ready(reason) =
-                                self.reply.poll_reset().map_err(|e| crate::Error::new_h2(e))?
+                            if let Poll::Ready(reason) =
+                                me.reply.poll_reset(cx).map_err(|e| crate::Error::new_h2(e))?
                             {
                                 debug!("stream received RST_STREAM: {:?}", reason);
-                                return Err(crate::Error::new_h2(reason.into()));
+                                return Poll::Ready(Err(crate::Error::new_h2(reason.into())));
                             }
-                            return Ok(Async::NotReady);
+                            return Poll::Pending;
                         }
-                        Err(e) => {
+                        Poll::Ready(Err(e)) => {
                             let err = crate::Error::new_user_service(e);
                             warn!("http2 service errored: {}", err);
-                            self.reply.send_reset(err.h2_reason());
-                            return Err(err);
+                            me.reply.send_reset(err.h2_reset_reason());
+                            return Poll::Ready(Err(err));
                         },
                     };
 
+                    // PUSH_PROMISEs must be sent before the response ends
+                    // the stream, so catch any the service just asked for.
+                    me.recv_pushes(cx);
+
                     let (head, mut body) = res.into_parts();
                     let mut res = ::http::Response::from_parts(head, ());
                     super::strip_connection_headers(res.headers_mut(), false);
@@ -278,17 +784,40 @@ where
 
                     macro_rules! reply {
                         ($eos:expr) => ({
-                            match self.reply.send_response(res, $eos) {
+                            match me.reply.send_response(res, $eos) {
                                 Ok(tx) => tx,
                                 Err(e) => {
                                     debug!("send response error: {}", e);
-                                    self.reply.send_reset(Reason::INTERNAL_ERROR);
-                                    return Err(crate::Error::new_h2(e));
+                                    me.reply.send_reset(Reason::INTERNAL_ERROR);
+                                    return Poll::Ready(Err(crate::Error::new_h2(e)));
                                 }
                             }
                         })
                     }
 
+                    // A successful response to a CONNECT turns the stream
+                    // into the upgraded IO, and so can't have a body.
+                    if let Some(connect_parts) = me.connect_parts.take() {
+                        if res.status().is_success() {
+                            let has_body = !body.is_end_stream()
+                                || content_length_parse_all(res.headers()).map_or(false, |len| len != 0);
+                            if has_body {
+                                warn!("h2 successful response to CONNECT request with body not supported");
+                                me.reply.send_reset(Reason::INTERNAL_ERROR);
+                                return Poll::Ready(Err(crate::Error::new_user_header()));
+                            }
+                            let send_stream = reply!(false);
+                            let io = H2Upgraded::new(
+                                connect_parts.ping,
+                                send_stream,
+                                connect_parts.recv_stream,
+                            );
+                            let upgraded = Upgraded::new(Box::new(io), Bytes::new());
+                            connect_parts.pending.fulfill(upgraded);
+                            return Poll::Ready(Ok(()));
+                        }
+                    }
+
                     // automatically set Content-Length from body...
                     if let Some(len) = body.content_length() {
                         headers::set_content_length_if_missing(res.headers_mut(), len);
@@ -296,11 +825,11 @@ where
 
                     if let Some(full) = body.__hyper_full_data(FullDataArg(())).0 {
                         let mut body_tx = reply!(false);
-                        let buf = SendBuf(Some(full));
+                        let buf = SendBuf::Buf(full);
                         body_tx
                             .send_data(buf, true)
                             .map_err(crate::Error::new_body_write)?;
-                        return Ok(Async::Ready(()));
+                        return Poll::Ready(Ok(()));
                     }
 
                     if !body.is_end_stream() {
@@ -308,32 +837,122 @@ where
                         H2StreamState::Body(PipeToSendStream::new(body, body_tx))
                     } else {
                         reply!(true);
-                        return Ok(Async::Ready(()));
+                        return Poll::Ready(Ok(()));
                     }
                 },
                 H2StreamState::Body(ref mut pipe) => {
-                    return pipe.poll();
-                }
+                    // The client may also reset the stream while the body
+                    // is being sent.
+                    if let Poll::Ready(reason) =
+                        me.reply.poll_reset(cx).map_err(|e| crate::Error::new_h2(e))?
+                    {
+                        debug!("stream received RST_STREAM: {:?}", reason);
+                        return Poll::Ready(Err(crate::Error::new_h2(reason.into())));
+                    }
+                    return unsafe { Pin::new_unchecked(pipe) }.poll(cx);
+                },
+                H2StreamState::Done => return Poll::Ready(Ok(())),
             };
-            self.state = next;
+            me.state = next;
         }
-        */
     }
 }
-/*
-impl<F, B> Future for H2Stream<F, B>
+
+impl<F, B, E> Future for H2Stream<F, B>
 where
-    F: Future<Item=Response<B>>,
-    F::Error: Into<Box<dyn StdError + Send + Sync>>,
+    F: Future<Output=Result<Response<B>, E>>,
     B: Payload,
+    E: Into<Box<dyn StdError + Send + Sync>>,
 {
-    type Item = ();
-    type Error = ();
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        // Safety: `state` is never moved out of, and the pushed streams are
+        // boxed on their own.
+        let me = unsafe { self.get_unchecked_mut() };
+
+        if me.poll_canceled(cx) {
+            return Poll::Ready(());
+        }
+
+        if let H2StreamState::Done = me.state {
+        } else if let Poll::Ready(res) = me.poll2(cx) {
+            if let Err(e) = res {
+                debug!("stream error: {}", e);
+            }
+            // Once the original stream is complete, no more pushes may be
+            // promised on it, but already pushed streams keep going.
+            me.state = H2StreamState::Done;
+            me.push_rx = None;
+        }
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.poll2()
-            .map_err(|e| debug!("stream error: {}", e))
+        me.poll_pushed(cx);
+
+        match me.state {
+            H2StreamState::Done if me.pushed.is_empty() => Poll::Ready(()),
+            _ => Poll::Pending,
+        }
     }
 }
-*/
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_exceeded(limit: &ResetLimit) -> bool {
+        limit.inner.lock().unwrap().exceeded
+    }
+
+    #[test]
+    fn reset_limit_exceeded() {
+        let limit = ResetLimit::new(2);
+        assert!(!is_exceeded(&limit));
+
+        let now = Instant::now();
+        limit.record_at(now);
+        limit.record_at(now);
+        assert!(!is_exceeded(&limit));
+
+        limit.record_at(now);
+        assert!(is_exceeded(&limit));
+    }
+
+    #[test]
+    fn reset_limit_slides() {
+        let limit = ResetLimit::new(2);
+        let start = Instant::now();
+        limit.record_at(start);
+        limit.record_at(start + Duration::from_millis(600));
+        // the first reset is more than a second old by now
+        limit.record_at(start + Duration::from_millis(1_200));
+        assert!(!is_exceeded(&limit));
+
+        // but two within the last second still count
+        limit.record_at(start + Duration::from_millis(1_300));
+        assert!(is_exceeded(&limit));
+    }
+
+    #[test]
+    fn reset_limit_wakes_connection() {
+        use futures_util::task::{ArcWake, waker};
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        struct Flag(AtomicBool);
+
+        impl ArcWake for Flag {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = waker(flag.clone());
+        let mut cx = task::Context::from_waker(&waker);
+
+        let limit = ResetLimit::new(0);
+        assert!(!limit.poll_exceeded(&mut cx));
+        limit.record();
+        assert!(flag.0.load(Ordering::SeqCst));
+        assert!(limit.poll_exceeded(&mut cx));
+    }
+}
@@ -0,0 +1,489 @@
+//! Observing the SETTINGS of an HTTP/2 peer
+//!
+//! h2 applies the SETTINGS it receives, but doesn't expose them. To know
+//! them anyway, the IO handed to h2 is wrapped in a `SettingsObserver`,
+//! which follows the frame headers of everything read, decoding the
+//! parameters of each SETTINGS frame, and skipping over any other frame.
+//!
+//! The frames themselves are still read, and validated, by h2. h2 only
+//! acknowledges a SETTINGS frame once it accepted it, so decoded frames are
+//! held back until the observer sees h2 write a SETTINGS ACK, and a frame
+//! h2 rejected is never recorded.
+//!
+//! On a server, the observer also counts every RST_STREAM frame read, for
+//! `http2_max_resets_per_second`. h2 handles many resets without ever
+//! surfacing the stream, so they can only be seen here.
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use crate::common::{Pin, Poll, task};
+use super::server::ResetLimit;
+
+/// The connection preface sent by a client before its first frame.
+const PREFACE_LEN: usize = 24;
+const FRAME_HEAD_LEN: usize = 9;
+const SETTING_LEN: usize = 6;
+
+const FRAME_TYPE_RST_STREAM: u8 = 0x3;
+const FRAME_TYPE_SETTINGS: u8 = 0x4;
+const FLAG_ACK: u8 = 0x1;
+
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+/// The SETTINGS of an HTTP/2 peer, as last acknowledged on a connection.
+///
+/// Settings the peer never sent have their default value from the spec.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerSettings {
+    header_table_size: u32,
+    enable_push: bool,
+    max_concurrent_streams: Option<u32>,
+    initial_window_size: u32,
+    max_frame_size: u32,
+    max_header_list_size: Option<u32>,
+}
+
+/// The peer settings of a connection, shared with the handles to it.
+///
+/// `None` until the peer's first SETTINGS frame has been acknowledged.
+pub(crate) type SharedSettings = Arc<Mutex<Option<PeerSettings>>>;
+
+impl PeerSettings {
+    /// Returns the peer's `SETTINGS_HEADER_TABLE_SIZE`, the largest HPACK
+    /// dynamic table this endpoint may use when encoding headers to it.
+    pub fn header_table_size(&self) -> u32 {
+        self.header_table_size
+    }
+
+    /// Returns the peer's `SETTINGS_ENABLE_PUSH`.
+    ///
+    /// Only clients send this setting, servers always appear to allow push.
+    pub fn is_push_enabled(&self) -> bool {
+        self.enable_push
+    }
+
+    /// Returns the peer's `SETTINGS_MAX_CONCURRENT_STREAMS`, the number of
+    /// streams this endpoint may have open to the peer, or `None` if the
+    /// peer set no limit.
+    pub fn max_concurrent_streams(&self) -> Option<u32> {
+        self.max_concurrent_streams
+    }
+
+    /// Returns the peer's `SETTINGS_INITIAL_WINDOW_SIZE`, the send window
+    /// each new stream starts with.
+    pub fn initial_window_size(&self) -> u32 {
+        self.initial_window_size
+    }
+
+    /// Returns the peer's `SETTINGS_MAX_FRAME_SIZE`, the largest frame
+    /// payload this endpoint may send.
+    pub fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+
+    /// Returns the peer's `SETTINGS_MAX_HEADER_LIST_SIZE`, or `None` if the
+    /// peer set no limit.
+    pub fn max_header_list_size(&self) -> Option<u32> {
+        self.max_header_list_size
+    }
+
+    fn apply(&mut self, id: u16, value: u32) {
+        match id {
+            SETTINGS_HEADER_TABLE_SIZE => self.header_table_size = value,
+            SETTINGS_ENABLE_PUSH => self.enable_push = value != 0,
+            SETTINGS_MAX_CONCURRENT_STREAMS => self.max_concurrent_streams = Some(value),
+            SETTINGS_INITIAL_WINDOW_SIZE => self.initial_window_size = value,
+            SETTINGS_MAX_FRAME_SIZE => self.max_frame_size = value,
+            SETTINGS_MAX_HEADER_LIST_SIZE => self.max_header_list_size = Some(value),
+            // Unknown settings must be ignored.
+            _ => {},
+        }
+    }
+}
+
+impl Default for PeerSettings {
+    fn default() -> PeerSettings {
+        PeerSettings {
+            header_table_size: 4_096,
+            enable_push: true,
+            max_concurrent_streams: None,
+            initial_window_size: super::SPEC_WINDOW_SIZE,
+            max_frame_size: 16_384,
+            max_header_list_size: None,
+        }
+    }
+}
+
+/// Wraps the IO of an HTTP/2 connection, recording the SETTINGS read from
+/// it into a `SharedSettings` once they are acknowledged.
+pub(crate) struct SettingsObserver<T> {
+    inner: T,
+    read: FrameState,
+    write: FrameState,
+    /// The parameters of the SETTINGS frame being read.
+    params: Vec<(u16, u32)>,
+    /// The SETTINGS frames read, but not acknowledged yet.
+    unacked: VecDeque<Vec<(u16, u32)>>,
+    settings: SharedSettings,
+    /// Records the RST_STREAM frames read by a server.
+    resets: Option<ResetLimit>,
+}
+
+#[derive(Debug)]
+enum FrameState {
+    /// Bytes of the client connection preface still to skip.
+    Preface(usize),
+    /// A frame header, with how many of its bytes were read so far.
+    Head([u8; FRAME_HEAD_LEN], usize),
+    /// The payload of a frame that isn't a SETTINGS frame.
+    Skip(usize),
+    /// The payload of a SETTINGS frame, with the bytes left in it, and the
+    /// parameter being read.
+    Settings(usize, [u8; SETTING_LEN], usize),
+}
+
+/// Decodes a frame header into the payload length, whether the frame is a
+/// SETTINGS frame on stream 0, and the flags.
+fn frame_head(head: &[u8; FRAME_HEAD_LEN]) -> (usize, bool, u8) {
+    let len = (head[0] as usize) << 16 | (head[1] as usize) << 8 | head[2] as usize;
+    let stream_id = u32::from(head[5]) << 24
+        | u32::from(head[6]) << 16
+        | u32::from(head[7]) << 8
+        | u32::from(head[8]);
+    let is_settings = head[3] == FRAME_TYPE_SETTINGS && stream_id & 0x7FFF_FFFF == 0;
+    (len, is_settings, head[4])
+}
+
+impl<T> SettingsObserver<T> {
+    /// Observes the IO of a client, which reads frames from the start, and
+    /// writes the client preface first.
+    pub(crate) fn client(io: T, settings: SharedSettings) -> SettingsObserver<T> {
+        SettingsObserver::new(io, FrameState::Head([0; FRAME_HEAD_LEN], 0), FrameState::Preface(PREFACE_LEN), settings, None)
+    }
+
+    /// Observes the IO of a server, which first reads the client preface,
+    /// recording the streams the client resets into `resets`, if any.
+    pub(crate) fn server(io: T, settings: SharedSettings, resets: Option<ResetLimit>) -> SettingsObserver<T> {
+        SettingsObserver::new(io, FrameState::Preface(PREFACE_LEN), FrameState::Head([0; FRAME_HEAD_LEN], 0), settings, resets)
+    }
+
+    fn new(
+        io: T,
+        read: FrameState,
+        write: FrameState,
+        settings: SharedSettings,
+        resets: Option<ResetLimit>,
+    ) -> SettingsObserver<T> {
+        SettingsObserver {
+            inner: io,
+            read,
+            write,
+            params: Vec::new(),
+            unacked: VecDeque::new(),
+            settings,
+            resets,
+        }
+    }
+
+    fn observe_read(&mut self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let next = match self.read {
+                FrameState::Preface(ref mut rem) |
+                FrameState::Skip(ref mut rem) => {
+                    let cnt = ::std::cmp::min(*rem, buf.len());
+                    buf = &buf[cnt..];
+                    *rem -= cnt;
+                    if *rem > 0 {
+                        continue;
+                    }
+                    FrameState::Head([0; FRAME_HEAD_LEN], 0)
+                },
+                FrameState::Head(ref mut head, ref mut filled) => {
+                    let cnt = ::std::cmp::min(FRAME_HEAD_LEN - *filled, buf.len());
+                    head[*filled..*filled + cnt].copy_from_slice(&buf[..cnt]);
+                    buf = &buf[cnt..];
+                    *filled += cnt;
+                    if *filled < FRAME_HEAD_LEN {
+                        continue;
+                    }
+                    if head[3] == FRAME_TYPE_RST_STREAM {
+                        if let Some(ref resets) = self.resets {
+                            resets.record();
+                        }
+                    }
+                    match frame_head(head) {
+                        (0, true, flags) if flags & FLAG_ACK == 0 => {
+                            self.unacked.push_back(Vec::new());
+                            FrameState::Head([0; FRAME_HEAD_LEN], 0)
+                        },
+                        (len, true, flags) if flags & FLAG_ACK == 0 => {
+                            FrameState::Settings(len, [0; SETTING_LEN], 0)
+                        },
+                        (len, _, _) => FrameState::Skip(len),
+                    }
+                },
+                FrameState::Settings(ref mut rem, ref mut param, ref mut filled) => {
+                    let cnt = ::std::cmp::min(::std::cmp::min(SETTING_LEN - *filled, *rem), buf.len());
+                    param[*filled..*filled + cnt].copy_from_slice(&buf[..cnt]);
+                    buf = &buf[cnt..];
+                    *filled += cnt;
+                    *rem -= cnt;
+                    if *filled == SETTING_LEN {
+                        *filled = 0;
+                        let id = u16::from(param[0]) << 8 | u16::from(param[1]);
+                        let value = u32::from(param[2]) << 24
+                            | u32::from(param[3]) << 16
+                            | u32::from(param[4]) << 8
+                            | u32::from(param[5]);
+                        self.params.push((id, value));
+                    }
+                    if *rem > 0 {
+                        continue;
+                    }
+                    self.unacked.push_back(mem::replace(&mut self.params, Vec::new()));
+                    FrameState::Head([0; FRAME_HEAD_LEN], 0)
+                },
+            };
+            self.read = next;
+        }
+    }
+
+    fn observe_write(&mut self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let next = match self.write {
+                FrameState::Preface(ref mut rem) |
+                FrameState::Skip(ref mut rem) => {
+                    let cnt = ::std::cmp::min(*rem, buf.len());
+                    buf = &buf[cnt..];
+                    *rem -= cnt;
+                    if *rem > 0 {
+                        continue;
+                    }
+                    FrameState::Head([0; FRAME_HEAD_LEN], 0)
+                },
+                FrameState::Head(ref mut head, ref mut filled) => {
+                    let cnt = ::std::cmp::min(FRAME_HEAD_LEN - *filled, buf.len());
+                    head[*filled..*filled + cnt].copy_from_slice(&buf[..cnt]);
+                    buf = &buf[cnt..];
+                    *filled += cnt;
+                    if *filled < FRAME_HEAD_LEN {
+                        continue;
+                    }
+                    let (len, is_settings, flags) = frame_head(head);
+                    // h2 acknowledges SETTINGS frames in the order it read them.
+                    if is_settings && flags & FLAG_ACK != 0 {
+                        if let Some(params) = self.unacked.pop_front() {
+                            let mut settings = self.settings.lock().unwrap();
+                            let settings = settings.get_or_insert_with(PeerSettings::default);
+                            for (id, value) in params {
+                                settings.apply(id, value);
+                            }
+                        }
+                    }
+                    FrameState::Skip(len)
+                },
+                FrameState::Settings(..) => unreachable!("SETTINGS payloads are only decoded when read"),
+            };
+            self.write = next;
+        }
+    }
+}
+
+impl<T> AsyncRead for SettingsObserver<T>
+where
+    T: AsyncRead + Unpin,
+{
+    #[inline]
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let cnt = ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.observe_read(&buf[..cnt]);
+        Poll::Ready(Ok(cnt))
+    }
+}
+
+impl<T> AsyncWrite for SettingsObserver<T>
+where
+    T: AsyncWrite + Unpin,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let cnt = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.observe_write(&buf[..cnt]);
+        Poll::Ready(Ok(cnt))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_frame(params: &[(u16, u32)]) -> Vec<u8> {
+        let len = params.len() * SETTING_LEN;
+        let mut frame = vec![(len >> 16) as u8, (len >> 8) as u8, len as u8, FRAME_TYPE_SETTINGS, 0, 0, 0, 0, 0];
+        for &(id, value) in params {
+            frame.extend_from_slice(&[(id >> 8) as u8, id as u8]);
+            frame.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+        }
+        frame
+    }
+
+    const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+    /// An observer of a server, or of a client that sent its preface.
+    fn observer(server: bool) -> SettingsObserver<()> {
+        let settings = SharedSettings::default();
+        if server {
+            SettingsObserver::server((), settings, None)
+        } else {
+            let mut obs = SettingsObserver::client((), settings);
+            obs.observe_write(PREFACE);
+            obs
+        }
+    }
+
+    fn observed(obs: &SettingsObserver<()>) -> Option<PeerSettings> {
+        obs.settings.lock().unwrap().clone()
+    }
+
+    /// Writes a SETTINGS ack, as h2 does once it accepted a SETTINGS frame.
+    fn ack(obs: &mut SettingsObserver<()>) {
+        obs.observe_write(&[0, 0, 0, FRAME_TYPE_SETTINGS, FLAG_ACK, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn observes_settings_split_across_reads() {
+        let mut bytes = settings_frame(&[
+            (SETTINGS_HEADER_TABLE_SIZE, 8_192),
+            (SETTINGS_MAX_CONCURRENT_STREAMS, 100),
+            (SETTINGS_INITIAL_WINDOW_SIZE, 1_000_000),
+            (SETTINGS_MAX_FRAME_SIZE, 32_768),
+            (SETTINGS_MAX_HEADER_LIST_SIZE, 4_000),
+            // unknown settings are ignored
+            (0x8, 1),
+        ]);
+        // a DATA frame on stream 1, which is skipped
+        bytes.extend_from_slice(&[0, 0, 3, 0x0, 0, 0, 0, 0, 1, 1, 2, 3]);
+
+        let mut obs = observer(false);
+        for byte in bytes.chunks(1) {
+            obs.observe_read(byte);
+        }
+        ack(&mut obs);
+
+        let settings = observed(&obs).expect("settings");
+        assert_eq!(settings.header_table_size(), 8_192);
+        assert_eq!(settings.max_concurrent_streams(), Some(100));
+        assert_eq!(settings.initial_window_size(), 1_000_000);
+        assert_eq!(settings.max_frame_size(), 32_768);
+        assert_eq!(settings.max_header_list_size(), Some(4_000));
+        assert!(settings.is_push_enabled());
+    }
+
+    #[test]
+    fn later_settings_update_earlier_ones() {
+        let mut obs = observer(true);
+        obs.observe_read(PREFACE);
+        assert_eq!(observed(&obs), None);
+
+        let mut bytes = settings_frame(&[(SETTINGS_ENABLE_PUSH, 0)]);
+        // a SETTINGS ack, with no payload
+        bytes.extend_from_slice(&[0, 0, 0, FRAME_TYPE_SETTINGS, FLAG_ACK, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&settings_frame(&[(SETTINGS_MAX_CONCURRENT_STREAMS, 1)]));
+        obs.observe_read(&bytes);
+        ack(&mut obs);
+        ack(&mut obs);
+
+        let settings = observed(&obs).expect("settings");
+        assert!(!settings.is_push_enabled());
+        assert_eq!(settings.max_concurrent_streams(), Some(1));
+        assert_eq!(settings.header_table_size(), 4_096);
+        assert_eq!(settings.max_header_list_size(), None);
+    }
+
+    #[test]
+    fn empty_settings_frame_uses_defaults() {
+        let mut obs = observer(false);
+        obs.observe_read(&settings_frame(&[]));
+        ack(&mut obs);
+        assert_eq!(observed(&obs), Some(PeerSettings::default()));
+    }
+
+    #[test]
+    fn settings_recorded_once_acknowledged() {
+        let mut obs = SettingsObserver::client((), SharedSettings::default());
+        let mut bytes = settings_frame(&[(SETTINGS_MAX_CONCURRENT_STREAMS, 10)]);
+        bytes.extend_from_slice(&settings_frame(&[(SETTINGS_MAX_CONCURRENT_STREAMS, 20)]));
+        obs.observe_read(&bytes);
+        assert_eq!(observed(&obs), None);
+
+        // the client preface, and the client's own SETTINGS, aren't acks
+        let mut written = PREFACE.to_vec();
+        written.extend_from_slice(&settings_frame(&[(SETTINGS_ENABLE_PUSH, 0)]));
+        // a WINDOW_UPDATE on stream 0
+        written.extend_from_slice(&[0, 0, 4, 0x8, 0, 0, 0, 0, 0, 0, 1, 0, 0]);
+        // the ack of the first frame, split across writes
+        written.extend_from_slice(&[0, 0, 0, FRAME_TYPE_SETTINGS, FLAG_ACK, 0, 0, 0, 0]);
+        for byte in written.chunks(1) {
+            obs.observe_write(byte);
+        }
+        let settings = observed(&obs).expect("settings");
+        assert_eq!(settings.max_concurrent_streams(), Some(10));
+        assert!(settings.is_push_enabled());
+
+        ack(&mut obs);
+        let settings = observed(&obs).expect("settings");
+        assert_eq!(settings.max_concurrent_streams(), Some(20));
+    }
+
+    #[test]
+    fn rejected_settings_are_not_recorded() {
+        let mut obs = observer(false);
+        // an initial window larger than the spec allows, which h2 rejects
+        // without an ack
+        obs.observe_read(&settings_frame(&[(SETTINGS_INITIAL_WINDOW_SIZE, 1 << 31)]));
+        // GOAWAY(FLOW_CONTROL_ERROR)
+        obs.observe_write(&[0, 0, 8, 0x7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!(observed(&obs), None);
+    }
+
+    #[test]
+    fn counts_rst_stream_frames() {
+        let waker = futures_util::task::noop_waker();
+        let mut cx = task::Context::from_waker(&waker);
+        let resets = ResetLimit::new(2);
+        let mut obs = SettingsObserver::server((), SharedSettings::default(), Some(resets.clone()));
+        let mut bytes = PREFACE.to_vec();
+        bytes.extend_from_slice(&settings_frame(&[]));
+        // RST_STREAM(CANCEL) on streams 1 and 3, with a DATA frame between
+        bytes.extend_from_slice(&[0, 0, 4, FRAME_TYPE_RST_STREAM, 0, 0, 0, 0, 1, 0, 0, 0, 8]);
+        bytes.extend_from_slice(&[0, 0, 1, 0x0, 0, 0, 0, 0, 5, 3]);
+        bytes.extend_from_slice(&[0, 0, 4, FRAME_TYPE_RST_STREAM, 0, 0, 0, 0, 3, 0, 0, 0, 8]);
+        for byte in bytes.chunks(1) {
+            obs.observe_read(byte);
+        }
+        assert!(!resets.poll_exceeded(&mut cx));
+
+        obs.observe_read(&[0, 0, 4, FRAME_TYPE_RST_STREAM, 0, 0, 0, 0, 5, 0, 0, 0, 8]);
+        assert!(resets.poll_exceeded(&mut cx));
+    }
+}
@@ -13,14 +13,14 @@ use std::fmt;
 use std::mem;
 #[cfg(feature = "runtime")] use std::net::SocketAddr;
 use std::sync::Arc;
-#[cfg(feature = "runtime")] use std::time::Duration;
+use std::time::Duration;
 
 use bytes::Bytes;
 use futures_core::Stream;
 use h2;
 use pin_utils::{unsafe_pinned, unsafe_unpinned};
 use tokio_io::{AsyncRead, AsyncWrite};
-#[cfg(feature = "runtime")] use tokio_reactor::Handle;
+#[cfg(feature = "runtime")] use tokio_net::driver::Handle;
 
 use crate::body::{Body, Payload};
 use crate::common::exec::{Exec, H2Exec, NewSvcExec};
@@ -37,6 +37,7 @@ pub(super) use self::spawn_all::Watcher;
 pub(super) use self::upgrades::UpgradeableConnection;
 
 #[cfg(feature = "runtime")] pub use super::tcp::{AddrIncoming, AddrStream};
+pub use crate::proto::h2::{PeerSettings, PushFuture, Pusher, Rtt};
 
 /// A lower-level configuration of the HTTP protocol.
 ///
@@ -50,6 +51,7 @@ pub struct Http<E = Exec> {
     h1_half_close: bool,
     h1_writev: bool,
     h2_builder: h2::server::Builder,
+    h2_config: proto::h2::server::Config,
     mode: ConnectionMode,
     keep_alive: bool,
     max_buf_size: Option<usize>,
@@ -128,7 +130,7 @@ pub(super) enum Either<A, B> {
 
 #[derive(Clone, Debug)]
 enum Fallback<E> {
-    ToHttp2(h2::server::Builder, E),
+    ToHttp2(h2::server::Builder, proto::h2::server::Config, E),
     Http1Only,
 }
 
@@ -176,6 +178,7 @@ impl Http {
             h1_half_close: true,
             h1_writev: true,
             h2_builder: h2::server::Builder::default(),
+            h2_config: proto::h2::server::Config::new(),
             mode: ConnectionMode::Fallback,
             keep_alive: true,
             max_buf_size: None,
@@ -255,11 +258,51 @@ impl<E> Http<E> {
     /// Default is 65,535
     pub fn http2_initial_connection_window_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
         if let Some(sz) = sz.into() {
+            self.h2_config.ping.bdp_initial_window = None;
             self.h2_builder.initial_connection_window_size(sz);
         }
         self
     }
 
+    /// Sets whether to use an adaptive flow control.
+    ///
+    /// Enabling this will override the limit set in
+    /// `http2_initial_connection_window_size`. The connection window starts
+    /// at the spec default, and grows as the bandwidth-delay product
+    /// measured with `PING` frames allows, up to
+    /// `http2_max_adaptive_window_size`.
+    ///
+    /// Only the connection window adapts. Stream windows can't be resized
+    /// once the connection is up, so each stream keeps the window set with
+    /// `http2_initial_stream_window_size`.
+    ///
+    /// Default is false.
+    pub fn http2_adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        use proto::h2::SPEC_WINDOW_SIZE;
+
+        if enabled {
+            self.h2_config.ping.bdp_initial_window = Some(SPEC_WINDOW_SIZE);
+            self.h2_builder.initial_connection_window_size(SPEC_WINDOW_SIZE);
+        } else {
+            self.h2_config.ping.bdp_initial_window = None;
+        }
+        self
+    }
+
+    /// Sets the largest window an adaptive flow control may grow to.
+    ///
+    /// Passing `None` will do nothing. Values larger than the maximum window
+    /// allowed by the spec are clamped to it. Does nothing unless
+    /// `http2_adaptive_window` is enabled.
+    ///
+    /// Default is 16MB.
+    pub fn http2_max_adaptive_window_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        if let Some(sz) = sz.into() {
+            self.h2_config.ping.bdp_max_window = sz.min(proto::h2::ping::MAX_WINDOW_SIZE);
+        }
+        self
+    }
+
     /// Sets the [`SETTINGS_MAX_CONCURRENT_STREAMS`][spec] option for HTTP2
     /// connections.
     ///
@@ -273,6 +316,151 @@ impl<E> Http<E> {
         self
     }
 
+    /// Sets the [`SETTINGS_MAX_FRAME_SIZE`][spec] option for HTTP2, the
+    /// largest frame payload this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_FRAME_SIZE
+    pub fn http2_max_frame_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        if let Some(sz) = sz.into() {
+            self.h2_builder.max_frame_size(sz);
+        }
+        self
+    }
+
+    /// Sets the [`SETTINGS_MAX_HEADER_LIST_SIZE`][spec] option for HTTP2,
+    /// the largest header list this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16MB.
+    ///
+    /// The HPACK table size (`SETTINGS_HEADER_TABLE_SIZE`) and the send
+    /// buffer size of each stream are not supported, since h2 0.2 has no
+    /// option for them. The table size stays at the spec default of 4KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_HEADER_LIST_SIZE
+    pub fn http2_max_header_list_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        if let Some(sz) = sz.into() {
+            self.h2_builder.max_header_list_size(sz);
+        }
+        self
+    }
+
+    /// Sets the maximum number of streams reset locally, which are kept in
+    /// memory to handle frames the client sends before seeing the reset.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 50.
+    pub fn http2_max_concurrent_reset_streams(&mut self, max: impl Into<Option<usize>>) -> &mut Self {
+        if let Some(max) = max.into() {
+            self.h2_builder.max_concurrent_reset_streams(max);
+        }
+        self
+    }
+
+    /// Sets how long streams reset locally are kept in memory, to handle
+    /// frames the client sends before seeing the reset.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 1 second.
+    pub fn http2_reset_stream_duration(&mut self, dur: impl Into<Option<Duration>>) -> &mut Self {
+        if let Some(dur) = dur.into() {
+            self.h2_builder.reset_stream_duration(dur);
+        }
+        self
+    }
+
+    /// Sets the maximum number of streams the client may reset within any
+    /// one second.
+    ///
+    /// If exceeded, the connection is closed with
+    /// `GOAWAY(ENHANCE_YOUR_CALM)`, and yields an error. This protects
+    /// against clients making the server start work that is thrown away
+    /// right after, such as in "rapid reset" attacks.
+    ///
+    /// Every `RST_STREAM` frame from the client counts, whether its stream
+    /// was already answered, or was reset before it could be accepted. h2
+    /// 0.2 has no separate limit for the latter, so this also bounds the
+    /// streams reset while waiting to be accepted.
+    ///
+    /// Default is no limit (`None`).
+    pub fn http2_max_resets_per_second(&mut self, max: impl Into<Option<usize>>) -> &mut Self {
+        self.h2_config.max_resets_per_second = max.into();
+        self
+    }
+
+    /// Sets how long a graceful shutdown of an HTTP2 connection may take.
+    ///
+    /// Once the deadline passes, any streams still active are reset with
+    /// `CANCEL`, and the connection is closed.
+    ///
+    /// Default is no deadline (`None`).
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_graceful_shutdown_timeout(&mut self, timeout: impl Into<Option<Duration>>) -> &mut Self {
+        self.h2_config.graceful_shutdown_timeout = timeout.into();
+        self
+    }
+
+    /// Sets an interval for HTTP2 Ping frames should be sent to keep a
+    /// connection alive.
+    ///
+    /// Pass `None` to disable HTTP2 keep-alive.
+    ///
+    /// Default is currently disabled.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_interval(&mut self, interval: impl Into<Option<Duration>>) -> &mut Self {
+        self.h2_config.ping.keep_alive_interval = interval.into();
+        self
+    }
+
+    /// Sets a timeout for receiving an acknowledgement of the keep-alive ping.
+    ///
+    /// If the ping is not acknowledged within the timeout, the connection will
+    /// be closed. Does nothing if `http2_keep_alive_interval` is disabled.
+    ///
+    /// Default is 20 seconds.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.h2_config.ping.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Sets whether HTTP2 keep-alive should apply while the connection is idle.
+    ///
+    /// If disabled, keep-alive pings are only sent while there are open
+    /// request/responses streams. If enabled, pings are also sent when no
+    /// streams are active. Does nothing if `http2_keep_alive_interval` is
+    /// disabled.
+    ///
+    /// Default is `false`.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_while_idle(&mut self, enabled: bool) -> &mut Self {
+        self.h2_config.ping.keep_alive_while_idle = enabled;
+        self
+    }
+
     /// Enables or disables HTTP keep-alive.
     ///
     /// Default is true.
@@ -316,6 +504,7 @@ impl<E> Http<E> {
             h1_half_close: self.h1_half_close,
             h1_writev: self.h1_writev,
             h2_builder: self.h2_builder,
+            h2_config: self.h2_config,
             mode: self.mode,
             keep_alive: self.keep_alive,
             max_buf_size: self.max_buf_size,
@@ -382,7 +571,13 @@ impl<E> Http<E> {
             }
             ConnectionMode::H2Only => {
                 let rewind_io = Rewind::new(io);
-                let h2 = proto::h2::Server::new(rewind_io, service, &self.h2_builder, self.exec.clone());
+                let h2 = proto::h2::Server::new(
+                    rewind_io,
+                    service,
+                    &self.h2_builder,
+                    self.h2_config.clone(),
+                    self.exec.clone(),
+                );
                 Either::B(h2)
             }
         };
@@ -390,7 +585,7 @@ impl<E> Http<E> {
         Connection {
             conn: Some(either),
             fallback: if self.mode == ConnectionMode::Fallback {
-                Fallback::ToHttp2(self.h2_builder.clone(), self.exec.clone())
+                Fallback::ToHttp2(self.h2_builder.clone(), self.h2_config.clone(), self.exec.clone())
             } else {
                 Fallback::Http1Only
             },
@@ -484,7 +679,8 @@ where
     /// Start a graceful shutdown process for this connection.
     ///
     /// This `Connection` should continue to be polled until shutdown
-    /// can finish.
+    /// can finish. For HTTP2, this can be bounded with
+    /// `Http::http2_graceful_shutdown_timeout`.
     pub fn graceful_shutdown(self: Pin<&mut Self>) {
         // Safety: neither h1 nor h2 poll any of the generic futures
         // in these methods.
@@ -498,6 +694,30 @@ where
         }
     }
 
+    /// Returns the number of HTTP2 streams whose response hasn't completed.
+    ///
+    /// Reading this right before calling `graceful_shutdown` tells how many
+    /// streams the shutdown is waiting on. Returns `None` if the connection
+    /// isn't serving HTTP/2, or hasn't finished the HTTP/2 handshake yet.
+    pub fn http2_active_streams(&self) -> Option<usize> {
+        match self.conn.as_ref().unwrap() {
+            Either::A(_) => None,
+            Either::B(ref h2) => h2.active_streams(),
+        }
+    }
+
+    /// Returns the SETTINGS the client sent on this connection.
+    ///
+    /// Returns `None` if the connection isn't serving HTTP/2, or the first
+    /// SETTINGS frame of the client hasn't been received and acknowledged
+    /// yet.
+    pub fn http2_peer_settings(&self) -> Option<PeerSettings> {
+        match self.conn.as_ref().unwrap() {
+            Either::A(_) => None,
+            Either::B(ref h2) => h2.peer_settings(),
+        }
+    }
+
     /// Return the inner IO object, and additional information.
     ///
     /// If the IO object has been "rewound" the io will not contain those bytes rewound.
@@ -549,7 +769,7 @@ where
         loop {
             let polled = match *self.conn.as_mut().unwrap() {
                 Either::A(ref mut h1) => h1.poll_without_shutdown(cx),
-                Either::B(ref mut h2) => unimplemented!("Connection::poll_without_shutdown h2"),//return h2.poll().map(|x| x.map(|_| ())),
+                Either::B(ref mut h2) => return Pin::new(h2).poll(cx).map_ok(|_| ()),
             };
             match ready!(polled) {
                 Ok(x) => return Poll::Ready(Ok(x)),
@@ -595,14 +815,15 @@ where
         };
         let mut rewind_io = Rewind::new(io);
         rewind_io.rewind(read_buf);
-        let (builder, exec) = match self.fallback {
-            Fallback::ToHttp2(ref builder, ref exec) => (builder, exec),
+        let (builder, h2_config, exec) = match self.fallback {
+            Fallback::ToHttp2(ref builder, ref h2_config, ref exec) => (builder, h2_config, exec),
             Fallback::Http1Only => unreachable!("upgrade_h2 with Fallback::Http1Only"),
         };
         let h2 = proto::h2::Server::new(
             rewind_io,
             dispatch.into_service(),
             builder,
+            h2_config.clone(),
             exec.clone(),
         );
 
@@ -57,12 +57,12 @@ use std::error::Error as StdError;
 use std::fmt;
 #[cfg(feature = "runtime")] use std::net::{SocketAddr, TcpListener as StdTcpListener};
 
-#[cfg(feature = "runtime")] use std::time::Duration;
+use std::time::Duration;
 
 use futures_core::Stream;
 use pin_utils::unsafe_pinned;
 use tokio_io::{AsyncRead, AsyncWrite};
-#[cfg(feature = "runtime")] use tokio_reactor;
+#[cfg(feature = "runtime")] use tokio_net::driver::Handle;
 
 use crate::body::{Body, Payload};
 use crate::common::exec::{Exec, H2Exec, NewSvcExec};
@@ -132,7 +132,7 @@ impl Server<AddrIncoming, ()> {
 
     /// Create a new instance from a `std::net::TcpListener` instance.
     pub fn from_tcp(listener: StdTcpListener) -> Result<Builder<AddrIncoming>, crate::Error> {
-        let handle = tokio_reactor::Handle::default();
+        let handle = Handle::default();
         AddrIncoming::from_std(listener, &handle)
             .map(Server::builder)
     }
@@ -352,6 +352,172 @@ impl<I, E> Builder<I, E> {
         self
     }
 
+    /// Sets the [`SETTINGS_MAX_FRAME_SIZE`][spec] option for HTTP2, the
+    /// largest frame payload this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_FRAME_SIZE
+    pub fn http2_max_frame_size(mut self, sz: impl Into<Option<u32>>) -> Self {
+        self.protocol.http2_max_frame_size(sz.into());
+        self
+    }
+
+    /// Sets the [`SETTINGS_MAX_HEADER_LIST_SIZE`][spec] option for HTTP2,
+    /// the largest header list this endpoint is willing to receive.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 16MB.
+    ///
+    /// The HPACK table size (`SETTINGS_HEADER_TABLE_SIZE`) and the send
+    /// buffer size of each stream are not supported, since h2 0.2 has no
+    /// option for them. The table size stays at the spec default of 4KB.
+    ///
+    /// [spec]: https://http2.github.io/http2-spec/#SETTINGS_MAX_HEADER_LIST_SIZE
+    pub fn http2_max_header_list_size(mut self, sz: impl Into<Option<u32>>) -> Self {
+        self.protocol.http2_max_header_list_size(sz.into());
+        self
+    }
+
+    /// Sets the maximum number of streams reset locally, which are kept in
+    /// memory to handle frames the client sends before seeing the reset.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 50.
+    pub fn http2_max_concurrent_reset_streams(mut self, max: impl Into<Option<usize>>) -> Self {
+        self.protocol.http2_max_concurrent_reset_streams(max.into());
+        self
+    }
+
+    /// Sets how long streams reset locally are kept in memory, to handle
+    /// frames the client sends before seeing the reset.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// Default is 1 second.
+    pub fn http2_reset_stream_duration(mut self, dur: impl Into<Option<Duration>>) -> Self {
+        self.protocol.http2_reset_stream_duration(dur.into());
+        self
+    }
+
+    /// Sets the maximum number of streams the client may reset within any
+    /// one second.
+    ///
+    /// If exceeded, the connection is closed with
+    /// `GOAWAY(ENHANCE_YOUR_CALM)`, and yields an error. This protects
+    /// against clients making the server start work that is thrown away
+    /// right after, such as in "rapid reset" attacks.
+    ///
+    /// Every `RST_STREAM` frame from the client counts, whether its stream
+    /// was already answered, or was reset before it could be accepted. h2
+    /// 0.2 has no separate limit for the latter, so this also bounds the
+    /// streams reset while waiting to be accepted.
+    ///
+    /// Default is no limit (`None`).
+    pub fn http2_max_resets_per_second(mut self, max: impl Into<Option<usize>>) -> Self {
+        self.protocol.http2_max_resets_per_second(max.into());
+        self
+    }
+
+    /// Sets how long a graceful shutdown of an HTTP2 connection may take.
+    ///
+    /// Once the deadline passes, any streams still active are reset with
+    /// `CANCEL`, and the connection is closed.
+    ///
+    /// Default is no deadline (`None`).
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_graceful_shutdown_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+        self.protocol.http2_graceful_shutdown_timeout(timeout.into());
+        self
+    }
+
+    /// Sets whether to use an adaptive flow control.
+    ///
+    /// Enabling this will override the limit set in
+    /// `http2_initial_connection_window_size`. The connection window starts
+    /// at the spec default, and grows as the bandwidth-delay product
+    /// measured with `PING` frames allows, up to
+    /// `http2_max_adaptive_window_size`.
+    ///
+    /// Only the connection window adapts. Stream windows can't be resized
+    /// once the connection is up, so each stream keeps the window set with
+    /// `http2_initial_stream_window_size`.
+    ///
+    /// Default is false.
+    pub fn http2_adaptive_window(mut self, enabled: bool) -> Self {
+        self.protocol.http2_adaptive_window(enabled);
+        self
+    }
+
+    /// Sets the largest window an adaptive flow control may grow to.
+    ///
+    /// Passing `None` will do nothing. Does nothing unless
+    /// `http2_adaptive_window` is enabled.
+    ///
+    /// Default is 16MB.
+    pub fn http2_max_adaptive_window_size(mut self, sz: impl Into<Option<u32>>) -> Self {
+        self.protocol.http2_max_adaptive_window_size(sz.into());
+        self
+    }
+
+    /// Sets an interval for HTTP2 Ping frames should be sent to keep a
+    /// connection alive.
+    ///
+    /// Pass `None` to disable HTTP2 keep-alive.
+    ///
+    /// Default is currently disabled.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_interval(mut self, interval: impl Into<Option<Duration>>) -> Self {
+        self.protocol.http2_keep_alive_interval(interval);
+        self
+    }
+
+    /// Sets a timeout for receiving an acknowledgement of the keep-alive ping.
+    ///
+    /// If the ping is not acknowledged within the timeout, the connection will
+    /// be closed. Does nothing if `http2_keep_alive_interval` is disabled.
+    ///
+    /// Default is 20 seconds.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.protocol.http2_keep_alive_timeout(timeout);
+        self
+    }
+
+    /// Sets whether HTTP2 keep-alive should apply while the connection is idle.
+    ///
+    /// If disabled, keep-alive pings are only sent while there are open
+    /// request/responses streams. If enabled, pings are also sent when no
+    /// streams are active. Does nothing if `http2_keep_alive_interval` is
+    /// disabled.
+    ///
+    /// Default is `false`.
+    ///
+    /// # Cargo Feature
+    ///
+    /// Requires the `runtime` cargo feature to be enabled.
+    #[cfg(feature = "runtime")]
+    pub fn http2_keep_alive_while_idle(mut self, enabled: bool) -> Self {
+        self.protocol.http2_keep_alive_while_idle(enabled);
+        self
+    }
+
     /// Set the maximum buffer size.
     ///
     /// Default is ~ 400kb.
@@ -1,11 +1,12 @@
 use std::fmt;
 use std::io;
 use std::net::{SocketAddr, TcpListener as StdTcpListener};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use futures_core::Stream;
-use tokio_reactor::Handle;
-use tokio_tcp::TcpListener;
+use futures_util::FutureExt as _;
+use tokio_net::driver::Handle;
+use tokio_net::tcp::TcpListener;
 use tokio_timer::Delay;
 
 use crate::common::{Future, Pin, Poll, task};
@@ -105,8 +106,11 @@ impl AddrIncoming {
         }
         self.timeout = None;
 
+        let accept = self.listener.accept();
+        futures_util::pin_mut!(accept);
+
         loop {
-            match Pin::new(&mut self.listener).poll_accept(cx) {
+            match accept.poll_unpin(cx) {
                 Poll::Ready(Ok((socket, addr))) => {
                     if let Some(dur) = self.tcp_keepalive_timeout {
                         if let Err(e) = socket.set_keepalive(Some(dur)) {
@@ -131,8 +135,7 @@ impl AddrIncoming {
                         error!("accept error: {}", e);
 
                         // Sleep 1s.
-                        let delay = Instant::now() + Duration::from_secs(1);
-                        let mut timeout = Delay::new(delay);
+                        let mut timeout = tokio_timer::delay_for(Duration::from_secs(1));
 
                         match Pin::new(&mut timeout).poll(cx) {
                             Poll::Ready(()) => {
@@ -193,7 +196,7 @@ mod addr_stream {
     use std::io;
     use std::net::SocketAddr;
     use bytes::{Buf, BufMut};
-    use tokio_tcp::TcpStream;
+    use tokio_net::tcp::TcpStream;
     use tokio_io::{AsyncRead, AsyncWrite};
 
     use crate::common::{Pin, Poll, task};
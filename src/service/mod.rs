@@ -8,6 +8,9 @@
 //!
 //! These types are conceptually similar to those in
 //! [tower](https://crates.io/crates/tower), while being specific to hyper.
+//! A tower `Service` can be used as a hyper `Service` with
+//! [`from_tower`](from_tower), and the other way around with
+//! [`into_tower`](into_tower).
 //!
 //! # Service
 //!
@@ -32,6 +35,8 @@
 
 mod make_service;
 mod service;
+mod tower;
 
 pub use self::make_service::{make_service_fn, MakeService, MakeServiceRef};
 pub use self::service::{service_fn, Service};
+pub use self::tower::{from_tower, into_tower};
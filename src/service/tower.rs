@@ -0,0 +1,164 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::body::Payload;
+use crate::common::{Poll, task};
+use crate::{Request, Response};
+use super::Service;
+
+/// Create a `Service` from a tower `Service`.
+///
+/// This allows serving with any tower `Service` of `Request`s, including
+/// those wrapped in tower middleware. Its readiness is passed along, so a
+/// connection only reads a request once the service is ready.
+///
+/// # Example
+///
+/// ```rust
+/// # #[cfg(feature = "runtime")]
+/// # {
+/// use hyper::{Body, Request, Response};
+/// use hyper::server::conn::AddrStream;
+/// use hyper::service::{from_tower, make_service_fn};
+///
+/// # fn doc<S>(tower_service: S)
+/// # where
+/// #     S: tower_service::Service<Request<Body>, Response=Response<Body>> + Clone,
+/// #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+/// # {
+/// let make_service = make_service_fn(move |_: &AddrStream| {
+///     let service = from_tower(tower_service.clone());
+///     async move { Ok::<_, hyper::Error>(service) }
+/// });
+/// # drop(make_service);
+/// # }
+/// # }
+/// ```
+pub fn from_tower<S, R>(service: S) -> FromTower<S, R>
+where
+    S: tower_service::Service<Request<R>>,
+{
+    FromTower {
+        service,
+        _req: PhantomData,
+    }
+}
+
+// Not exported from crate, like `ServiceFn`.
+pub struct FromTower<S, R> {
+    service: S,
+    _req: PhantomData<fn(R)>,
+}
+
+impl<S, ReqBody, ResBody> Service for FromTower<S, ReqBody>
+where
+    S: tower_service::Service<Request<ReqBody>, Response=Response<ResBody>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    ReqBody: Payload,
+    ResBody: Payload,
+{
+    type ReqBody = ReqBody;
+    type ResBody = ResBody;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        self.service.call(req)
+    }
+}
+
+impl<S: Clone, R> Clone for FromTower<S, R> {
+    fn clone(&self) -> FromTower<S, R> {
+        FromTower {
+            service: self.service.clone(),
+            _req: PhantomData,
+        }
+    }
+}
+
+impl<S, R> fmt::Debug for FromTower<S, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("impl Service")
+            .finish()
+    }
+}
+
+/// Create a tower `Service` from a `Service`.
+///
+/// This allows wrapping a hyper `Service` in tower middleware, such as
+/// timeouts or rate limits, and serving it again with
+/// [`from_tower`](from_tower).
+///
+/// # Example
+///
+/// ```rust
+/// use hyper::{Body, Request, Response};
+/// use hyper::service::{into_tower, service_fn};
+/// use tower_service::Service;
+///
+/// let mut service = into_tower(service_fn(|_: Request<Body>| async {
+///     Ok::<_, hyper::Error>(Response::new(Body::empty()))
+/// }));
+/// let future = service.call(Request::new(Body::empty()));
+/// # drop(future);
+/// ```
+pub fn into_tower<S: Service>(service: S) -> IntoTower<S> {
+    IntoTower {
+        service,
+    }
+}
+
+// Not exported from crate, like `ServiceFn`.
+#[derive(Clone)]
+pub struct IntoTower<S> {
+    service: S,
+}
+
+impl<S: Service> tower_service::Service<Request<S::ReqBody>> for IntoTower<S> {
+    type Response = Response<S::ResBody>;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<S::ReqBody>) -> Self::Future {
+        self.service.call(req)
+    }
+}
+
+impl<S> fmt::Debug for IntoTower<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("impl tower_service::Service")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::poll_fn;
+    use futures_util::try_stream::TryStreamExt;
+
+    use crate::Body;
+    use super::*;
+    use super::super::service_fn;
+
+    #[tokio::test]
+    async fn into_and_from_tower() {
+        let service = into_tower(service_fn(|req: Request<Body>| async move {
+            Ok::<_, crate::Error>(Response::new(req.into_body()))
+        }));
+        let mut service = from_tower(service);
+
+        poll_fn(|cx| service.poll_ready(cx)).await.expect("ready");
+        let res = service.call(Request::new(Body::from("hello"))).await.expect("response");
+        let body = res.into_body().try_concat().await.expect("body");
+        assert_eq!(body.as_ref(), b"hello");
+    }
+}
@@ -1,5 +1,10 @@
 //! HTTP Upgrades
 //!
+//! Over HTTP/1, a connection is upgraded with the `Upgrade` header or a
+//! `CONNECT` request. Over HTTP/2, the single stream of a `CONNECT` request
+//! is upgraded instead, leaving the connection multiplexed. Reads and writes
+//! on such an `Upgraded` are subject to the stream's flow control.
+//!
 //! See [this example][example] showing how upgrades work with both
 //! Clients and Servers.
 //!
@@ -92,7 +97,7 @@ impl dyn Io + Send {
         self.__hyper_type_id() == t
     }
 
-    fn __hyper_downcast<T: Io>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
+    pub(crate) fn __hyper_downcast<T: Io>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
         if self.__hyper_is::<T>() {
             // Taken from `std::error::Error::downcast()`.
             unsafe {
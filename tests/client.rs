@@ -1,4 +1,3 @@
-#![feature(async_await)]
 #![deny(warnings)]
 extern crate bytes;
 extern crate hyper;
@@ -7,7 +6,6 @@ extern crate net2;
 extern crate pretty_env_logger;
 extern crate tokio;
 extern crate tokio_io;
-extern crate tokio_tcp;
 extern crate tokio_timer;
 
 use std::io::{Read, Write};
@@ -25,14 +23,14 @@ use futures_util::future::{self, FutureExt};
 use futures_util::try_future::{self, TryFutureExt};
 use futures_util::try_stream::TryStreamExt;
 use tokio::runtime::current_thread::Runtime;
-use tokio_tcp::TcpStream;
+use tokio::net::TcpStream;
 
 fn s(buf: &[u8]) -> &str {
     ::std::str::from_utf8(buf).expect("from_utf8")
 }
 
 fn tcp_connect(addr: &SocketAddr) -> impl Future<Output = std::io::Result<TcpStream>> {
-    TcpStream::connect(addr)
+    TcpStream::connect(*addr)
 }
 
 macro_rules! test {
@@ -271,6 +269,7 @@ macro_rules! test {
                 .expect("HttpConnector should set HttpInfo");
 
             assert_eq!(extra.remote_addr(), addr, "HttpInfo should have server addr");
+            assert_eq!(extra.local_addr().ip(), addr.ip(), "HttpInfo should have local addr");
 
             resp
         })
@@ -310,14 +309,14 @@ macro_rules! __client_req_prop {
 }
 
 macro_rules! __client_req_header {
-    ($req_builder:ident, { $($name:expr => $val:expr,)* }) => {
+    ($req_builder:ident, { $($name:expr => $val:expr,)* }) => {{
         $(
         $req_builder.header($name, $val);
         )*
-    }
+    }}
 }
 
-static REPLY_OK: &'static str = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+static REPLY_OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
 
 test! {
     name: client_get,
@@ -773,12 +772,11 @@ mod dispatch_impl {
     use futures_util::try_stream::TryStreamExt;
     use tokio::runtime::current_thread::Runtime;
     use tokio_io::{AsyncRead, AsyncWrite};
-    use tokio_tcp::TcpStream;
-    use tokio_timer::Delay;
+    use tokio::net::TcpStream;
 
     use hyper::client::connect::{Connect, Connected, Destination, HttpConnector};
     use hyper::Client;
-    use hyper;
+    
 
 
 
@@ -801,7 +799,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             let body = vec![b'x'; 1024 * 128];
             write!(sock, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).expect("write head");
             let _ = sock.write_all(&body);
@@ -814,7 +812,7 @@ mod dispatch_impl {
             .unwrap();
         let res = client.request(req).map_ok(move |res| {
             assert_eq!(res.status(), hyper::StatusCode::OK);
-            Delay::new(Instant::now() + Duration::from_secs(1))
+            tokio_timer::delay(Instant::now() + Duration::from_secs(1))
         });
         let rx = rx1.expect("thread panicked");
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
@@ -839,7 +837,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             let body =[b'x'; 64];
             write!(sock, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).expect("write head");
             let _ = sock.write_all(&body);
@@ -858,7 +856,7 @@ mod dispatch_impl {
                 assert_eq!(res.status(), hyper::StatusCode::OK);
                 res.into_body().try_concat()
             }).map_ok(|_| {
-                Delay::new(Instant::now() + Duration::from_secs(1))
+                tokio_timer::delay(Instant::now() + Duration::from_secs(1))
             })
         };
         // client is dropped
@@ -888,7 +886,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             let body = [b'x'; 64];
             write!(sock, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).expect("write head");
             let _ = sock.write_all(&body);
@@ -922,7 +920,7 @@ mod dispatch_impl {
         }
         drop(client);
 
-        let t = Delay::new(Instant::now() + Duration::from_millis(100))
+        let t = tokio_timer::delay(Instant::now() + Duration::from_millis(100))
             .map(|_| panic!("time out"));
         let close = closes
             .into_future()
@@ -947,7 +945,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             // we never write a response head
             // simulates a slow server operation
             let _ = tx1.send(());
@@ -971,7 +969,7 @@ mod dispatch_impl {
         rt.block_on(future::select(res, rx1));
 
         // res now dropped
-        let t = Delay::new(Instant::now() + Duration::from_millis(100))
+        let t = tokio_timer::delay(Instant::now() + Duration::from_millis(100))
             .map(|_| panic!("time out"));
         let close = closes
             .into_future()
@@ -996,7 +994,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             write!(sock, "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n").expect("write head");
             let _ = tx1.send(());
 
@@ -1020,7 +1018,7 @@ mod dispatch_impl {
         let rx = rx1.expect("thread panicked");
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
 
-        let t = Delay::new(Instant::now() + Duration::from_millis(100))
+        let t = tokio_timer::delay(Instant::now() + Duration::from_millis(100))
             .map(|_| panic!("time out"));
         let close = closes
             .into_future()
@@ -1046,7 +1044,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
             let _ = tx1.send(());
 
@@ -1070,7 +1068,7 @@ mod dispatch_impl {
         let rx = rx1.expect("thread panicked");
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
 
-        let t = Delay::new(Instant::now() + Duration::from_millis(100))
+        let t = tokio_timer::delay(Instant::now() + Duration::from_millis(100))
             .map(|_| panic!("time out"));
         let close = closes
             .into_future()
@@ -1095,7 +1093,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
             let _ = tx1.send(());
         });
@@ -1114,7 +1112,7 @@ mod dispatch_impl {
         let rx = rx1.expect("thread panicked");
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
 
-        let t = Delay::new(Instant::now() + Duration::from_millis(100))
+        let t = tokio_timer::delay(Instant::now() + Duration::from_millis(100))
             .map(|_| panic!("time out"));
         let close = closes
             .into_future()
@@ -1166,7 +1164,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write 1");
             let _ = tx1.send(());
 
@@ -1207,6 +1205,279 @@ mod dispatch_impl {
         drop(client);
     }
 
+    #[test]
+    fn connection_info() {
+        use hyper::client::connect::ConnectionInfo;
+
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        let client = Client::new();
+
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            for _ in 0..2 {
+                let _ = sock.read(&mut buf).expect("read");
+                sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write");
+            }
+        });
+
+        let uri = format!("http://{}/a", addr).parse::<hyper::Uri>().unwrap();
+        let res1 = rt.block_on(client.get(uri.clone())).unwrap();
+
+        // sleep real quick to let the threadpool put connection in ready
+        // state and back into client pool
+        thread::sleep(Duration::from_millis(50));
+
+        let res2 = rt.block_on(client.get(uri)).unwrap();
+
+        let info1 = res1.extensions().get::<ConnectionInfo>().expect("first ConnectionInfo");
+        let info2 = res2.extensions().get::<ConnectionInfo>().expect("second ConnectionInfo");
+        assert_eq!(info1.id(), info2.id());
+        assert_eq!(info1.version(), hyper::Version::HTTP_11);
+        assert!(!info1.is_reused());
+        assert!(info2.is_reused());
+        assert_eq!(info1.connect_duration(), info2.connect_duration());
+    }
+
+    struct Record {
+        name: &'static str,
+        log: Arc<std::sync::Mutex<Vec<String>>>,
+        teapot: bool,
+    }
+
+    impl hyper::client::Interceptor for Record {
+        fn on_request<'a>(&'a self, req: &'a mut hyper::http::request::Parts) -> hyper::client::InterceptFuture<'a> {
+            Box::pin(async move {
+                self.log.lock().unwrap().push(format!("{} request", self.name));
+                if self.name == "failing" {
+                    return Err("no token".into());
+                }
+                req.headers.append("x-intercepted", self.name.parse().unwrap());
+                Ok(())
+            })
+        }
+
+        fn on_response<'a>(&'a self, res: &'a mut hyper::Result<hyper::Response<Body>>) -> hyper::client::InterceptFuture<'a> {
+            Box::pin(async move {
+                let outcome = match *res {
+                    Ok(ref mut res) => {
+                        let status = res.status();
+                        if self.teapot {
+                            *res.status_mut() = StatusCode::IM_A_TEAPOT;
+                        }
+                        status.as_u16().to_string()
+                    },
+                    Err(ref err) => err.to_string(),
+                };
+                self.log.lock().unwrap().push(format!("{} response {}", self.name, outcome));
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn interceptors_run_in_order() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        let (tx, rx) = oneshot::channel();
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            let n = sock.read(&mut buf).expect("read");
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write");
+            let _ = tx.send(s(&buf[..n]).to_string());
+        });
+
+        let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = Client::builder()
+            .interceptor(Record { name: "outer", log: log.clone(), teapot: false })
+            .interceptor(Record { name: "inner", log: log.clone(), teapot: true })
+            .build_http::<Body>();
+
+        let res = rt.block_on(client.get(format!("http://{}/a", addr).parse().unwrap())).unwrap();
+        assert_eq!(res.status(), StatusCode::IM_A_TEAPOT);
+
+        let req = rt.block_on(rx).unwrap();
+        assert!(req.contains("x-intercepted: outer\r\nx-intercepted: inner\r\n"), "{:?}", req);
+        assert_eq!(*log.lock().unwrap(), vec![
+            "outer request",
+            "inner request",
+            "inner response 200",
+            "outer response 418",
+        ]);
+    }
+
+    #[test]
+    fn interceptor_error_fails_request() {
+        let _ = pretty_env_logger::try_init();
+        let mut rt = Runtime::new().unwrap();
+
+        let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = Client::builder()
+            .interceptor(Record { name: "outer", log: log.clone(), teapot: false })
+            .interceptor(Record { name: "failing", log: log.clone(), teapot: false })
+            .interceptor(Record { name: "never", log: log.clone(), teapot: false })
+            .build_http::<Body>();
+
+        let err = rt.block_on(client.get("http://127.0.0.1:1/a".parse().unwrap())).unwrap_err();
+        assert!(err.is_user());
+        assert_eq!(*log.lock().unwrap(), vec![
+            "outer request",
+            "failing request",
+            "outer response error from user's Interceptor: no token",
+        ]);
+    }
+
+    #[test]
+    fn local_address_partitions_pool() {
+        use hyper::client::connect::{ConnectionInfo, HttpInfo, LocalAddress};
+
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        let client = Client::new();
+
+        thread::spawn(move || {
+            let mut socks = Vec::new();
+            for _ in 0..2 {
+                let mut sock = server.accept().unwrap().0;
+                sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+                sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+                let mut buf = [0; 4096];
+                let _ = sock.read(&mut buf).expect("read");
+                sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write");
+                socks.push(sock);
+            }
+        });
+
+        let uri = format!("http://{}/a", addr);
+        let res1 = rt.block_on(client.get(uri.parse().unwrap())).unwrap();
+
+        // sleep real quick to let the threadpool put connection in ready
+        // state and back into client pool
+        thread::sleep(Duration::from_millis(50));
+
+        let mut req = Request::get(&*uri).body(Body::empty()).unwrap();
+        req.extensions_mut().insert(LocalAddress::new([127, 0, 0, 1].into()));
+        let res2 = rt.block_on(client.request(req)).unwrap();
+
+        let info1 = res1.extensions().get::<ConnectionInfo>().unwrap();
+        let info2 = res2.extensions().get::<ConnectionInfo>().unwrap();
+        assert_ne!(info1.id(), info2.id(), "not the pooled connection");
+        assert!(!info2.is_reused());
+        let local = res2.extensions().get::<HttpInfo>().unwrap().local_addr();
+        assert_eq!(local.ip(), addr.ip());
+    }
+
+    #[test]
+    fn cookie_store_sends_stored_cookies() {
+        use hyper::client::cookie::{CookieStore, MemoryStore};
+
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        let store = Arc::new(MemoryStore::new());
+        let client = Client::builder()
+            .cookie_store(store.clone())
+            .build_http::<Body>();
+
+        let (tx, rx) = oneshot::channel();
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            let n = sock.read(&mut buf).expect("read 1");
+            assert!(!s(&buf[..n]).contains("cookie:"), "{}", s(&buf[..n]));
+            sock.write_all(b"\
+                HTTP/1.1 200 OK\r\n\
+                Set-Cookie: sid=a1b2; Path=/; HttpOnly\r\n\
+                Set-Cookie: other=c3; Path=/elsewhere\r\n\
+                Content-Length: 0\r\n\
+                \r\n\
+            ").expect("write 1");
+
+            let n = sock.read(&mut buf).expect("read 2");
+            let _ = tx.send(s(&buf[..n]).to_owned());
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write 2");
+        });
+
+        rt.block_on(client.get(format!("http://{}/login", addr).parse().unwrap())).unwrap();
+
+        // sleep real quick to let the threadpool put connection in ready
+        // state and back into client pool
+        thread::sleep(Duration::from_millis(50));
+
+        let uri = format!("http://{}/a", addr).parse().unwrap();
+        assert_eq!(
+            store.cookies(&uri).unwrap(),
+            "sid=a1b2",
+        );
+        rt.block_on(client.get(uri)).unwrap();
+        let req = rt.block_on(rx).unwrap();
+        assert!(req.contains("cookie: sid=a1b2\r\n"), "{}", req);
+    }
+
+    #[test]
+    fn tower_poll_ready_reflects_pool_capacity() {
+        use tower_service::Service;
+
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        let (read_tx, read_rx) = oneshot::channel();
+        let (respond_tx, respond_rx) = std::sync::mpsc::channel::<()>();
+        thread::spawn(move || {
+            let mut sock = server.accept().unwrap().0;
+            sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 4096];
+            let _ = sock.read(&mut buf).expect("read");
+            let _ = read_tx.send(());
+            respond_rx.recv().expect("respond");
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write");
+            // keep the connection open
+            let _ = sock.read(&mut buf);
+        });
+
+        let mut client = Client::builder()
+            .max_connections(1)
+            .build_http::<Body>();
+
+        let req = Request::get(&*format!("http://{}/a", addr)).body(Body::empty()).unwrap();
+        let (res_tx, res_rx) = oneshot::channel();
+        rt.spawn(client.call(req).map(move |res| {
+            let _ = res_tx.send(res);
+        }));
+        rt.block_on(read_rx).expect("request read");
+
+        let ready = rt.block_on(future::poll_fn(|cx| Poll::Ready(client.poll_ready(cx).is_ready())));
+        assert!(!ready, "only connection is busy");
+
+        respond_tx.send(()).unwrap();
+        rt.block_on(res_rx).unwrap().expect("response");
+
+        // ready again once the connection is idle in the pool
+        rt.block_on(future::poll_fn(|cx| client.poll_ready(cx))).expect("ready");
+    }
+
     #[test]
     fn client_keep_alive_extra_body() {
         let _ = pretty_env_logger::try_init();
@@ -1227,7 +1498,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello").expect("write 1");
             // the body "hello", while ignored because its a HEAD request, should mean the connection
             // cannot be put back in the pool
@@ -1269,7 +1540,6 @@ mod dispatch_impl {
 
     #[test]
     fn client_keep_alive_when_response_before_request_body_ends() {
-        use tokio_timer::Delay;
 
         let _ = pretty_env_logger::try_init();
         let server = TcpListener::bind("127.0.0.1:0").unwrap();
@@ -1290,12 +1560,12 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").expect("write 1");
             // after writing the response, THEN stream the body
             let _ = tx1.send(());
 
-            sock.read(&mut buf).expect("read 2");
+            let _ = sock.read(&mut buf).expect("read 2");
             let _ = tx2.send(());
 
             let n2 = sock.read(&mut buf).expect("read 3");
@@ -1310,7 +1580,7 @@ mod dispatch_impl {
         assert_eq!(connects.load(Ordering::Relaxed), 0);
 
         let delayed_body = rx1
-            .then(|_| Delay::new(Instant::now() + Duration::from_millis(200)))
+            .then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)))
             .map(|_| Ok::<_, ()>("hello a"))
             .map_err(|_| -> hyper::Error { panic!("rx1") })
             .into_stream();
@@ -1325,7 +1595,7 @@ mod dispatch_impl {
 
         // req 1
         let fut = future::join(client.request(req), rx)
-            .then(|_| Delay::new(Instant::now() + Duration::from_millis(200)))
+            .then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)))
             // req 2
             .then(move |()| {
                 let rx = rx3.expect("thread panicked");
@@ -1437,7 +1707,7 @@ mod dispatch_impl {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"\
                 HTTP/1.1 101 Switching Protocols\r\n\
                 Upgrade: foobar\r\n\
@@ -1549,8 +1819,8 @@ mod dispatch_impl {
 
         fn with_http_and_closes(http: HttpConnector, closes: mpsc::Sender<()>) -> DebugConnector {
             DebugConnector {
-                http: http,
-                closes: closes,
+                http,
+                closes,
                 connects: Arc::new(AtomicUsize::new(0)),
                 is_proxy: false,
                 alpn_h2: false,
@@ -1635,8 +1905,7 @@ mod conn {
     use futures_util::try_stream::TryStreamExt;
     use tokio::runtime::current_thread::Runtime;
     use tokio_io::{AsyncRead, AsyncWrite};
-    use tokio_tcp::TcpStream;
-    use tokio_timer::Delay;
+    use tokio::net::TcpStream;
 
     use hyper::{self, Request, Body, Method};
     use hyper::client::conn;
@@ -1683,7 +1952,7 @@ mod conn {
             res.into_body().try_concat()
         });
         let rx = rx1.expect("thread panicked");
-        let rx = rx.then(|_| Delay::new(Instant::now() + Duration::from_millis(200)));
+        let rx = rx.then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)));
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
     }
 
@@ -1729,7 +1998,7 @@ mod conn {
         });
 
         let rx = rx1.expect("thread panicked");
-        let rx = rx.then(|_| Delay::new(Instant::now() + Duration::from_millis(200)));
+        let rx = rx.then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)));
         let chunk = rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
         assert_eq!(chunk.len(), 5);
     }
@@ -1821,7 +2090,7 @@ mod conn {
             res.into_body().try_concat()
         });
         let rx = rx1.expect("thread panicked");
-        let rx = rx.then(|_| Delay::new(Instant::now() + Duration::from_millis(200)));
+        let rx = rx.then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)));
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
     }
 
@@ -1865,7 +2134,7 @@ mod conn {
             res.into_body().try_concat()
         });
         let rx = rx1.expect("thread panicked");
-        let rx = rx.then(|_| Delay::new(Instant::now() + Duration::from_millis(200)));
+        let rx = rx.then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)));
         rt.block_on(future::join(res, rx).map(|r| r.0)).unwrap();
     }
 
@@ -1882,7 +2151,7 @@ mod conn {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
 
             let _ = tx1.send(Ok::<_, ()>(()));
@@ -1917,7 +2186,7 @@ mod conn {
             });
 
         let rx = rx1.expect("thread panicked");
-        let rx = rx.then(|_| Delay::new(Instant::now() + Duration::from_millis(200)));
+        let rx = rx.then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)));
         rt.block_on(future::join3(res1, res2, rx).map(|r| r.0)).unwrap();
     }
 
@@ -1938,7 +2207,7 @@ mod conn {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"\
                 HTTP/1.1 101 Switching Protocols\r\n\
                 Upgrade: foobar\r\n\
@@ -1955,7 +2224,7 @@ mod conn {
         let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
 
         let io = DebugStream {
-            tcp: tcp,
+            tcp,
             shutdown_called: false,
         };
 
@@ -1977,7 +2246,7 @@ mod conn {
             });
 
             let rx = rx1.expect("thread panicked");
-            let rx = rx.then(|_| Delay::new(Instant::now() + Duration::from_millis(200)));
+            let rx = rx.then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)));
             rt.block_on(future::join3(until_upgrade, res, rx).map(|r| r.0)).unwrap();
 
             // should not be ready now
@@ -2022,7 +2291,7 @@ mod conn {
             sock.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
             sock.set_write_timeout(Some(Duration::from_secs(5))).unwrap();
             let mut buf = [0; 4096];
-            sock.read(&mut buf).expect("read 1");
+            let _ = sock.read(&mut buf).expect("read 1");
             sock.write_all(b"\
                 HTTP/1.1 200 OK\r\n\
                 \r\n\
@@ -2038,7 +2307,7 @@ mod conn {
         let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
 
         let io = DebugStream {
-            tcp: tcp,
+            tcp,
             shutdown_called: false,
         };
 
@@ -2065,7 +2334,7 @@ mod conn {
                 });
 
             let rx = rx1.expect("thread panicked");
-            let rx = rx.then(|_| Delay::new(Instant::now() + Duration::from_millis(200)));
+            let rx = rx.then(|_| tokio_timer::delay(Instant::now() + Duration::from_millis(200)));
             rt.block_on(future::join3(until_tunneled, res, rx).map(|r| r.0)).unwrap();
 
             // should not be ready now
@@ -2139,12 +2408,41 @@ mod conn {
         let _ = shdn_tx.send(());
 
         // Allow time for graceful shutdown roundtrips...
-        rt.block_on(Delay::new(::std::time::Instant::now() + Duration::from_millis(100)).map_err(|e| panic!("delay error: {:?}", e))).expect("delay");
+        rt.block_on(tokio_timer::delay(::std::time::Instant::now() + Duration::from_millis(100)).map_err(|e| panic!("delay error: {:?}", e))).expect("delay");
 
         // After graceful shutdown roundtrips, the client should be closed...
         rt.block_on(future::poll_fn(|ctx| client.poll_ready(ctx))).expect_err("client should be closed");
     }*/
 
+    #[test]
+    fn http2_keep_alive_detects_unresponsive_server() {
+        let _ = pretty_env_logger::try_init();
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut rt = Runtime::new().unwrap();
+
+        // Spawn a server that accepts the connection, but never responds
+        // to anything, including PINGs.
+        thread::spawn(move || {
+            let (_sock, _) = server.accept().unwrap();
+            thread::sleep(Duration::from_secs(10));
+        });
+
+        let io = rt.block_on(tcp_connect(&addr)).expect("tcp connect");
+        let (_client, conn) = rt.block_on(
+            conn::Builder::new()
+                .http2_only(true)
+                .http2_keep_alive_interval(Duration::from_millis(100))
+                .http2_keep_alive_timeout(Duration::from_millis(200))
+                .http2_keep_alive_while_idle(true)
+                .handshake::<_, Body>(io)
+        ).expect("http handshake");
+
+        let err = rt.block_on(conn).expect_err("conn should time out");
+        assert!(err.to_string().contains("keep-alive timed out"), "{}", err);
+    }
+
     struct DebugStream {
         tcp: TcpStream,
         shutdown_called: bool,
@@ -2190,6 +2488,866 @@ mod conn {
     }
 }
 
+/// A resolver of fixed addresses, to test the `HttpConnector`.
+mod fake_resolver {
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::pin::Pin;
+    use std::vec;
+
+    use futures_core::Future;
+    use futures_util::future;
+    use tokio::runtime::current_thread::Runtime;
+
+    use hyper::Uri;
+    use hyper::client::connect::{Connect, Destination, HttpConnector};
+    use hyper::client::connect::dns::{Name, Resolve, ServiceAddr, ServiceFuture};
+
+    type ResolveFuture = Pin<Box<dyn Future<Output = io::Result<vec::IntoIter<IpAddr>>> + Send>>;
+
+    /// Resolves every name to the same addresses.
+    #[derive(Clone)]
+    pub struct FakeResolver {
+        pub v6: Vec<IpAddr>,
+        pub v4: Vec<IpAddr>,
+        pub split: bool,
+        // Never answer the AAAA lookup when split.
+        pub v6_hangs: bool,
+        pub services: Option<Vec<ServiceAddr>>,
+    }
+
+    impl FakeResolver {
+        pub fn new(v6: Vec<IpAddr>, v4: Vec<IpAddr>) -> FakeResolver {
+            FakeResolver {
+                v6,
+                v4,
+                split: false,
+                v6_hangs: false,
+                services: None,
+            }
+        }
+    }
+
+    impl Resolve for FakeResolver {
+        type Addrs = vec::IntoIter<IpAddr>;
+        type Future = ResolveFuture;
+
+        fn resolve(&self, _name: Name) -> Self::Future {
+            let mut addrs = self.v6.clone();
+            addrs.extend(self.v4.clone());
+            Box::pin(future::ok(addrs.into_iter()))
+        }
+
+        fn resolve_split(&self, _name: Name) -> Option<(Self::Future, Self::Future)> {
+            if !self.split {
+                return None;
+            }
+            let v6: ResolveFuture = if self.v6_hangs {
+                Box::pin(future::pending())
+            } else {
+                Box::pin(future::ok(self.v6.clone().into_iter()))
+            };
+            let v4: ResolveFuture = Box::pin(future::ok(self.v4.clone().into_iter()));
+            Some((v6, v4))
+        }
+
+        fn resolve_service(&self, _name: Name) -> Option<ServiceFuture> {
+            self.services
+                .clone()
+                .map(|services| ServiceFuture::new(future::ok(services)))
+        }
+    }
+
+    pub fn localhost_v4() -> IpAddr {
+        Ipv4Addr::new(127, 0, 0, 1).into()
+    }
+
+    pub fn localhost_v6() -> IpAddr {
+        Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1).into()
+    }
+
+    pub fn connect(connector: HttpConnector<FakeResolver>, port: u16) -> io::Result<SocketAddr> {
+        let mut rt = Runtime::new().unwrap();
+        let uri = format!("http://fake.local:{}", port).parse::<Uri>().unwrap();
+        let dst = Destination::try_from_uri(uri).unwrap();
+        let (tcp, _) = rt.block_on(connector.connect(dst))?;
+        tcp.peer_addr()
+    }
+}
+
+mod happy_eyeballs {
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    use hyper::client::connect::{HttpConnector, IpPreference};
+
+    use super::fake_resolver::{connect, localhost_v4, localhost_v6, FakeResolver};
+
+    #[test]
+    fn falls_back_to_other_family() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+
+        // Nothing listens on ::1, so the IPv6 attempt fails.
+        let resolver = FakeResolver::new(vec![localhost_v6()], vec![localhost_v4()]);
+        let connector = HttpConnector::new_with_resolver(resolver);
+
+        let addr = connect(connector, port).expect("connect");
+        assert_eq!(addr.ip(), localhost_v4());
+    }
+
+    #[test]
+    fn ip_preference() {
+        let _ = pretty_env_logger::try_init();
+        let server4 = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server4.local_addr().unwrap().port();
+        let _server6 = match TcpListener::bind((localhost_v6(), port)) {
+            Ok(server) => server,
+            // no IPv6 loopback on this host
+            Err(_) => return,
+        };
+
+        let resolver = FakeResolver::new(vec![localhost_v6()], vec![localhost_v4()]);
+
+        let mut connector = HttpConnector::new_with_resolver(resolver.clone());
+        connector.set_ip_preference(Some(IpPreference::Ipv4));
+        let addr = connect(connector, port).expect("connect v4");
+        assert_eq!(addr.ip(), localhost_v4());
+
+        let mut connector = HttpConnector::new_with_resolver(resolver);
+        connector.set_ip_preference(Some(IpPreference::Ipv6));
+        let addr = connect(connector, port).expect("connect v6");
+        assert_eq!(addr.ip(), localhost_v6());
+    }
+
+    #[test]
+    fn resolution_delay_waits_for_aaaa() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+
+        // The AAAA lookup never answers, so the A answer is used after
+        // the resolution delay.
+        let mut resolver = FakeResolver::new(vec![localhost_v6()], vec![localhost_v4()]);
+        resolver.split = true;
+        resolver.v6_hangs = true;
+        let connector = HttpConnector::new_with_resolver(resolver);
+
+        let start = Instant::now();
+        let addr = connect(connector, port).expect("connect");
+        assert_eq!(addr.ip(), localhost_v4());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn no_addresses_is_an_error() {
+        let _ = pretty_env_logger::try_init();
+
+        let mut resolver = FakeResolver::new(vec![], vec![]);
+        resolver.split = true;
+        let connector = HttpConnector::new_with_resolver(resolver);
+
+        connect(connector, 80).unwrap_err();
+    }
+
+}
+
+mod service_addrs {
+    use std::net::TcpListener;
+
+    use hyper::client::connect::HttpConnector;
+    use hyper::client::connect::dns::ServiceAddr;
+
+    use super::fake_resolver::{connect, localhost_v4, FakeResolver};
+
+    #[test]
+    fn service_addrs_in_priority_order() {
+        let _ = pretty_env_logger::try_init();
+        let first = TcpListener::bind("127.0.0.1:0").unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").unwrap();
+        // Nothing listens here anymore, so the attempt fails.
+        let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let mut resolver = FakeResolver::new(vec![], vec![]);
+        resolver.services = Some(vec![
+            ServiceAddr::new(second.local_addr().unwrap(), 20, 100),
+            ServiceAddr::new(first.local_addr().unwrap(), 10, 0),
+            ServiceAddr::new(refused, 5, 0),
+        ]);
+        let connector = HttpConnector::new_with_resolver(resolver);
+
+        // The port of the URI isn't used.
+        let addr = connect(connector, 1).expect("connect");
+        assert_eq!(addr, first.local_addr().unwrap());
+    }
+
+    #[test]
+    fn no_service_addrs_resolves_name() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+
+        let mut resolver = FakeResolver::new(vec![], vec![localhost_v4()]);
+        resolver.services = Some(vec![]);
+        let connector = HttpConnector::new_with_resolver(resolver);
+
+        let addr = connect(connector, port).expect("connect");
+        assert_eq!(addr, server.local_addr().unwrap());
+    }
+}
+
+mod socket_options {
+    use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
+    use std::time::Duration;
+
+    use hyper::client::connect::HttpConnector;
+
+    use super::fake_resolver::{connect, localhost_v4, FakeResolver};
+
+    #[test]
+    fn local_address_of_each_family() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+
+        // Binding the IPv6 address would fail connecting to IPv4.
+        let resolver = FakeResolver::new(vec![], vec![localhost_v4()]);
+        let mut connector = HttpConnector::new_with_resolver(resolver);
+        connector.set_local_addresses(
+            Some(Ipv4Addr::new(127, 0, 0, 1)),
+            Some(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
+        );
+
+        let addr = connect(connector, port).expect("connect");
+        assert_eq!(addr, server.local_addr().unwrap());
+    }
+
+    #[test]
+    fn socket_options() {
+        let _ = pretty_env_logger::try_init();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+
+        let resolver = FakeResolver::new(vec![], vec![localhost_v4()]);
+        let mut connector = HttpConnector::new_with_resolver(resolver);
+        connector.set_keepalive(Some(Duration::from_secs(60)));
+        connector.set_keepalive_interval(Some(Duration::from_secs(10)));
+        connector.set_keepalive_retries(Some(3));
+        connector.set_tos(Some(34 << 2));
+        connector.set_user_timeout(Some(Duration::from_secs(30)));
+
+        let res = connect(connector, port);
+        if cfg!(any(target_os = "linux", target_os = "android")) {
+            res.expect("connect");
+        } else {
+            let err = res.unwrap_err();
+            assert!(err.to_string().contains("not supported"), "{}", err);
+        }
+    }
+}
+
+mod connect_timeout {
+    use std::io;
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    use hyper::client::connect::HttpConnector;
+    use hyper::client::connect::dns::ServiceAddr;
+
+    use super::fake_resolver::{connect, FakeResolver};
+
+    /// A listener that never accepts, with its backlog full, so that
+    /// connecting to it hangs.
+    struct Blackhole {
+        listener: TcpListener,
+        _queued: Vec<TcpStream>,
+    }
+
+    impl Blackhole {
+        fn new() -> Blackhole {
+            let listener = net2::TcpBuilder::new_v4()
+                .unwrap()
+                .bind("127.0.0.1:0")
+                .unwrap()
+                .listen(0)
+                .unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            // Once the backlog is full, the SYNs are dropped.
+            let mut queued = Vec::new();
+            loop {
+                match TcpStream::connect_timeout(&addr, Duration::from_millis(100)) {
+                    Ok(tcp) => queued.push(tcp),
+                    Err(ref err) if err.kind() == io::ErrorKind::TimedOut => break,
+                    Err(err) => panic!("filling backlog: {}", err),
+                }
+                assert!(queued.len() < 64, "backlog never filled");
+            }
+
+            Blackhole {
+                listener,
+                _queued: queued,
+            }
+        }
+
+        fn addr(&self) -> SocketAddr {
+            self.listener.local_addr().unwrap()
+        }
+    }
+
+    #[test]
+    fn connect_timeout_tries_next_address() {
+        let _ = pretty_env_logger::try_init();
+        let blackhole = Blackhole::new();
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        // Service addresses are tried in order, so the blackhole is first.
+        let mut resolver = FakeResolver::new(vec![], vec![]);
+        resolver.services = Some(vec![
+            ServiceAddr::new(blackhole.addr(), 0, 0),
+            ServiceAddr::new(server.local_addr().unwrap(), 1, 0),
+        ]);
+        let mut connector = HttpConnector::new_with_resolver(resolver);
+        connector.set_happy_eyeballs_timeout(None);
+        connector.set_connect_timeout(Some(Duration::from_secs(2)));
+
+        let start = Instant::now();
+        let addr = connect(connector, 1).expect("connect");
+        assert_eq!(addr, server.local_addr().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn connect_error_lists_every_address() {
+        let _ = pretty_env_logger::try_init();
+        let blackhole = Blackhole::new();
+        // Nothing listens here anymore, so the attempt fails.
+        let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        let mut resolver = FakeResolver::new(vec![], vec![]);
+        resolver.services = Some(vec![
+            ServiceAddr::new(blackhole.addr(), 0, 0),
+            ServiceAddr::new(refused, 1, 0),
+        ]);
+        let mut connector = HttpConnector::new_with_resolver(resolver);
+        connector.set_happy_eyeballs_timeout(None);
+        connector.set_connect_timeout(Some(Duration::from_millis(400)));
+
+        let err = connect(connector, 1).unwrap_err().to_string();
+        assert!(err.contains(&blackhole.addr().to_string()), "{}", err);
+        assert!(err.contains(&refused.to_string()), "{}", err);
+    }
+}
+
+mod http2 {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::Poll;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    use futures_channel::oneshot;
+    use futures_util::future::{self, FutureExt};
+    use futures_util::stream::StreamExt;
+    use futures_util::try_stream::TryStreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use hyper::{Body, Client, Request, Response, StatusCode};
+    use hyper::error::{Reason, StreamReset};
+    use hyper::client::conn;
+    use hyper::server::conn::{Http, Pusher};
+    use hyper::service::service_fn;
+
+    /// Connects a client and a server socket over loopback.
+    async fn tcp_pair() -> (TcpStream, TcpStream) {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().unwrap();
+        let (client, server) = future::join(TcpStream::connect(addr), listener.accept()).await;
+        (client.expect("connect"), server.expect("accept").0)
+    }
+
+    /// Handshakes an HTTP/2 client, and drives its connection in the background.
+    async fn handshake(io: TcpStream, builder: &mut conn::Builder) -> conn::SendRequest<Body> {
+        let (client, conn) = builder
+            .http2_only(true)
+            .handshake(io)
+            .await
+            .expect("client handshake");
+        tokio::spawn(conn.map(|_| ()));
+        client
+    }
+
+    fn get(path: &str) -> Request<Body> {
+        Request::get(format!("http://localhost{}", path))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn connect_request() -> Request<Body> {
+        // h2 0.2.0-alpha.3 requires a :scheme of every request, even a
+        // CONNECT, so the target is given as an absolute URI.
+        Request::connect("http://localhost:80")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn concat(body: Body) -> String {
+        let chunk = body.try_concat().await.expect("body");
+        String::from_utf8(chunk.to_vec()).expect("utf8")
+    }
+
+    /// Pushes `/pushed` and responds whether the push was promised.
+    async fn push_pushed(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+        let pusher = req.extensions().get::<Pusher<Body>>().cloned().expect("pusher");
+        let promised = Request::get("http://localhost/pushed").body(()).unwrap();
+        let pushed = pusher.push(promised, Response::new(Body::from("pushed"))).await;
+        Ok(Response::new(Body::from(pushed.is_ok().to_string())))
+    }
+
+    #[tokio::test]
+    async fn push_is_received() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .serve_connection(server_io, service_fn(push_pushed));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, conn::Builder::new().http2_enable_push(true)).await;
+        let mut pushes = client.pushed_responses().expect("pushed_responses");
+
+        let res = client.send_request(get("/")).await.expect("response");
+        assert_eq!(concat(res.into_body()).await, "true");
+
+        let pushed = pushes.next().await.expect("a push").expect("push promise");
+        assert_eq!(pushed.request().uri().path(), "/pushed");
+        let (_, pushed) = pushed.into_parts();
+        let pushed = pushed.await.expect("pushed response");
+        assert_eq!(concat(pushed.into_body()).await, "pushed");
+    }
+
+    #[tokio::test]
+    async fn push_canceled_until_taken() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let (pushed_tx, pushed_rx) = oneshot::channel();
+        let mut pushed_tx = Some(pushed_tx);
+        let server = Http::new()
+            .http2_only(true)
+            .serve_connection(server_io, service_fn(move |req: Request<Body>| {
+                let pusher = req.extensions().get::<Pusher<Body>>().cloned().expect("pusher");
+                let promised = Request::get("http://localhost/pushed").body(()).unwrap();
+                let (body_tx, body) = Body::channel();
+                let _ = pushed_tx.take().expect("one request").send(body_tx);
+                async move {
+                    pusher.push(promised, Response::new(body)).await?;
+                    Ok::<_, hyper::Error>(Response::new(Body::empty()))
+                }
+            }));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, conn::Builder::new().http2_enable_push(true)).await;
+        client.send_request(get("/")).await.expect("response");
+
+        // The pushed body is dropped once the client resets its stream.
+        let mut body_tx = pushed_rx.await.expect("pushed body");
+        let closed = future::poll_fn(move |cx| loop {
+            match body_tx.poll_ready(cx) {
+                Poll::Ready(Ok(())) => {
+                    let _ = body_tx.send_data(vec![0; 1024].into());
+                },
+                other => return other,
+            }
+        });
+        let delay = tokio_timer::delay(Instant::now() + Duration::from_secs(5));
+        match future::select(Box::pin(closed), Box::pin(delay)).await {
+            future::Either::Left((res, _)) => assert!(res.is_err()),
+            future::Either::Right(_) => panic!("push wasn't canceled"),
+        }
+    }
+
+    #[tokio::test]
+    async fn push_disabled_by_client() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .serve_connection(server_io, service_fn(push_pushed));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, &mut conn::Builder::new()).await;
+        assert!(client.pushed_responses().is_none());
+
+        let res = client.send_request(get("/")).await.expect("response");
+        assert_eq!(concat(res.into_body()).await, "false");
+    }
+
+    #[tokio::test]
+    async fn peer_settings_on_both_sides() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let mut server = Http::new()
+            .http2_only(true)
+            .http2_max_concurrent_streams(7)
+            .http2_initial_stream_window_size(200_000)
+            .http2_max_frame_size(32_768)
+            .http2_max_header_list_size(16_384)
+            .serve_connection(server_io, service_fn(|_req| {
+                future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+            }));
+        assert!(server.http2_peer_settings().is_none());
+        let (settings_tx, settings_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut settings_tx = Some(settings_tx);
+            let _ = future::poll_fn(|cx| {
+                let res = Pin::new(&mut server).poll(cx);
+                if let Some(settings) = server.http2_peer_settings() {
+                    if let Some(tx) = settings_tx.take() {
+                        let _ = tx.send(settings);
+                    }
+                }
+                res
+            }).await;
+        });
+
+        let mut client = handshake(client_io, conn::Builder::new()
+            .http2_initial_stream_window_size(100_000)
+            .http2_max_frame_size(20_000)
+            .http2_max_header_list_size(8_192)
+        ).await;
+        client.send_request(get("/")).await.expect("response");
+
+        let server_settings = client.http2_peer_settings().expect("server settings");
+        assert_eq!(server_settings.max_concurrent_streams(), Some(7));
+        assert_eq!(server_settings.initial_window_size(), 200_000);
+        assert_eq!(server_settings.max_frame_size(), 32_768);
+        assert_eq!(server_settings.max_header_list_size(), Some(16_384));
+        assert_eq!(server_settings.header_table_size(), 4_096);
+
+        let client_settings = settings_rx.await.expect("client settings");
+        assert!(!client_settings.is_push_enabled());
+        assert_eq!(client_settings.max_concurrent_streams(), None);
+        assert_eq!(client_settings.initial_window_size(), 100_000);
+        assert_eq!(client_settings.max_frame_size(), 20_000);
+        assert_eq!(client_settings.max_header_list_size(), Some(8_192));
+    }
+
+    #[tokio::test]
+    async fn adaptive_window_keeps_stream_window() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .http2_initial_stream_window_size(200_000)
+            .http2_adaptive_window(true)
+            .serve_connection(server_io, service_fn(|_req| {
+                future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+            }));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, conn::Builder::new()
+            .http2_adaptive_window(true)
+            .http2_initial_stream_window_size(100_000)
+        ).await;
+        client.send_request(get("/")).await.expect("response");
+
+        let server_settings = client.http2_peer_settings().expect("server settings");
+        assert_eq!(server_settings.initial_window_size(), 200_000);
+    }
+
+    /// Spawns `fut`, returning a receiver of its output.
+    fn spawn_output<F>(fut: F) -> oneshot::Receiver<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(fut.map(move |out| {
+            let _ = tx.send(out);
+        }));
+        rx
+    }
+
+    /// Responds to `/` right away, and never to anything else.
+    async fn hang_unless_root(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+        if req.uri().path() != "/" {
+            future::pending::<()>().await;
+        }
+        Ok(Response::new(Body::empty()))
+    }
+
+    /// Sends a request the server never answers, and resets it once sent.
+    async fn send_and_reset(client: &mut conn::SendRequest<Body>) {
+        let res = client.send_request(get("/hang"));
+        let delay = tokio_timer::delay(Instant::now() + Duration::from_millis(20));
+        match future::select(res, Box::pin(delay)).await {
+            future::Either::Left(_) => panic!("/hang responded"),
+            future::Either::Right(((), res)) => drop(res),
+        }
+    }
+
+    #[tokio::test]
+    async fn rapid_resets_go_away() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .http2_max_resets_per_second(2)
+            .serve_connection(server_io, service_fn(hang_unless_root));
+        let server = spawn_output(server);
+
+        let (mut client, conn) = conn::Builder::new()
+            .http2_only(true)
+            .handshake::<_, Body>(client_io)
+            .await
+            .expect("client handshake");
+        let conn = spawn_output(conn);
+
+        for _ in 0..3 {
+            send_and_reset(&mut client).await;
+        }
+
+        let err = server.await.expect("server done").expect_err("server error");
+        assert_eq!(err.h2_reason(), Some(Reason::ENHANCE_YOUR_CALM));
+        let err = conn.await.expect("client conn done").expect_err("client conn error");
+        assert_eq!(err.h2_reason(), Some(Reason::ENHANCE_YOUR_CALM));
+    }
+
+    #[tokio::test]
+    async fn resets_before_accept_go_away() {
+        let _ = pretty_env_logger::try_init();
+        let (mut client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .http2_max_resets_per_second(2)
+            .serve_connection(server_io, service_fn(|_req| {
+                future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+            }));
+        let server = spawn_output(server);
+
+        // Write the frames by hand, so each RST_STREAM directly follows its
+        // HEADERS, and h2 drops the stream before hyper can accept it.
+        let mut frames = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec();
+        // an empty SETTINGS frame
+        frames.extend_from_slice(&[0, 0, 0, 0x4, 0, 0, 0, 0, 0]);
+        for &id in &[1u8, 3, 5] {
+            // HEADERS(END_STREAM | END_HEADERS), with an indexed `GET`,
+            // `http` and `/`, and a literal `:authority: localhost`
+            frames.extend_from_slice(&[0, 0, 14, 0x1, 0x5, 0, 0, 0, id, 0x82, 0x86, 0x84, 0x41, 9]);
+            frames.extend_from_slice(b"localhost");
+            // RST_STREAM(CANCEL)
+            frames.extend_from_slice(&[0, 0, 4, 0x3, 0, 0, 0, 0, id, 0, 0, 0, 0x8]);
+        }
+        client_io.write_all(&frames).await.expect("write frames");
+
+        let err = server.await.expect("server done").expect_err("server error");
+        assert_eq!(err.h2_reason(), Some(Reason::ENHANCE_YOUR_CALM));
+    }
+
+    #[tokio::test]
+    async fn slow_resets_are_allowed() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .http2_max_resets_per_second(2)
+            .serve_connection(server_io, service_fn(hang_unless_root));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, &mut conn::Builder::new()).await;
+        for _ in 0..4 {
+            send_and_reset(&mut client).await;
+            tokio_timer::delay(Instant::now() + Duration::from_millis(600)).await;
+        }
+
+        let res = client.send_request(get("/")).await.expect("response after slow resets");
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown_deadline_cancels_active_streams() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let mut server = Http::new()
+            .http2_only(true)
+            .http2_graceful_shutdown_timeout(Duration::from_millis(200))
+            .serve_connection(server_io, service_fn(hang_unless_root));
+
+        let mut client = handshake(client_io, &mut conn::Builder::new()).await;
+        let res1 = spawn_output(client.send_request(get("/hang/1")));
+        future::poll_fn(|cx| client.poll_ready(cx)).await.expect("client ready");
+        let res2 = spawn_output(client.send_request(get("/hang/2")));
+
+        // Serve until both requests are being processed.
+        future::poll_fn(|cx| {
+            if let Poll::Ready(res) = Pin::new(&mut server).poll(cx) {
+                panic!("server closed early: {:?}", res);
+            }
+            match server.http2_active_streams() {
+                Some(2) => Poll::Ready(()),
+                _ => Poll::Pending,
+            }
+        }).await;
+
+        Pin::new(&mut server).graceful_shutdown();
+        let start = Instant::now();
+        let _ = (&mut server).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(200), "closed after {:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "closed after {:?}", elapsed);
+
+        for res in [res1, res2] {
+            let err = res.await.expect("response done").expect_err("canceled");
+            assert_eq!(err.h2_reason(), Some(Reason::CANCEL));
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_reset_reason_reaches_client() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .serve_connection(server_io, service_fn(|_req| {
+                future::err::<Response<Body>, _>(StreamReset::new(Reason::ENHANCE_YOUR_CALM))
+            }));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, &mut conn::Builder::new()).await;
+        let err = client.send_request(get("/")).await.expect_err("reset");
+        assert_eq!(err.h2_reason(), Some(Reason::ENHANCE_YOUR_CALM));
+    }
+
+    #[tokio::test]
+    async fn refused_stream_is_retried_on_new_connection() {
+        let _ = pretty_env_logger::try_init();
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            let (io, _) = listener.accept().await.expect("accept");
+            let server = Http::new()
+                .http2_only(true)
+                .serve_connection(io, service_fn(move |_req| {
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        future::err(StreamReset::new(Reason::REFUSED_STREAM))
+                    } else {
+                        future::ok(Response::new(Body::from("retried")))
+                    }
+                }));
+            let _ = server.await;
+        });
+
+        let client = Client::builder()
+            .http2_only(true)
+            .build_http::<Body>();
+        let res = client
+            .get(format!("http://{}/", addr).parse().unwrap())
+            .await
+            .expect("retried response");
+        assert_eq!(concat(res.into_body()).await, "retried");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    /// Fails any response that is an error.
+    struct FailErrors;
+
+    impl hyper::client::Interceptor for FailErrors {
+        fn on_response<'a>(&'a self, res: &'a mut hyper::Result<Response<Body>>) -> hyper::client::InterceptFuture<'a> {
+            let failed = res.is_err();
+            Box::pin(async move {
+                if failed {
+                    Err("attempt failed".into())
+                } else {
+                    Ok(())
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptor_error_stops_retry() {
+        let _ = pretty_env_logger::try_init();
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            let (io, _) = listener.accept().await.expect("accept");
+            let server = Http::new()
+                .http2_only(true)
+                .serve_connection(io, service_fn(move |_req| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    future::err::<Response<Body>, _>(StreamReset::new(Reason::REFUSED_STREAM))
+                }));
+            let _ = server.await;
+        });
+
+        let client = Client::builder()
+            .http2_only(true)
+            .interceptor(FailErrors)
+            .build_http::<Body>();
+        let err = client
+            .get(format!("http://{}/", addr).parse().unwrap())
+            .await
+            .expect_err("interceptor error");
+        assert!(err.is_user(), "{:?}", err);
+        assert_eq!(err.to_string(), "error from user's Interceptor: attempt failed");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn connect_tunnels_both_ways() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .serve_connection(server_io, service_fn(|req: Request<Body>| {
+                tokio::spawn(async move {
+                    let mut io = req.into_body().on_upgrade().await.expect("server upgrade");
+                    let mut buf = [0; 5];
+                    io.read_exact(&mut buf).await.expect("server read");
+                    assert_eq!(&buf, b"ping!");
+                    io.write_all(b"pong!").await.expect("server write");
+                });
+                future::ok::<_, hyper::Error>(Response::new(Body::empty()))
+            }));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, &mut conn::Builder::new()).await;
+        let req = connect_request();
+        let res = client.send_request(req).await.expect("response");
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut io = res.into_body().on_upgrade().await.expect("client upgrade");
+        io.write_all(b"ping!").await.expect("client write");
+        let mut buf = [0; 5];
+        io.read_exact(&mut buf).await.expect("client read");
+        assert_eq!(&buf, b"pong!");
+    }
+
+    #[tokio::test]
+    async fn connect_success_with_body_is_rejected() {
+        let _ = pretty_env_logger::try_init();
+        let (client_io, server_io) = tcp_pair().await;
+        let server = Http::new()
+            .http2_only(true)
+            .serve_connection(server_io, service_fn(|_req| {
+                future::ok::<_, hyper::Error>(Response::new(Body::from("not a tunnel")))
+            }));
+        tokio::spawn(server.map(|_| ()));
+
+        let mut client = handshake(client_io, &mut conn::Builder::new()).await;
+        let req = connect_request();
+        let err = client.send_request(req).await.expect_err("CONNECT with body");
+        assert_eq!(err.h2_reason(), Some(Reason::INTERNAL_ERROR));
+    }
+}
+
 trait FutureHyperExt: TryFuture {
     fn expect(self, msg: &'static str) -> Pin<Box<dyn Future<Output=Self::Ok>>>;
 }